@@ -16,7 +16,7 @@ fn bench_matching_engine(c: &mut Criterion) {
             let random_price = rng.random_range(10..150);
             let random_quantity = rng.random_range(1..10);
             let random_side: Side = rng.random();
-            Create(OrderEntry::new(random_price, random_quantity, random_side))
+            OrderEntry::new(random_price, random_quantity, random_side)
         })
         .collect();
 
@@ -30,7 +30,7 @@ fn bench_matching_engine(c: &mut Criterion) {
                 let engine_handle = tokio::spawn(matching_engine(rx, ws_tx));
 
                 for order in orders {
-                    tx.send(black_box(order)).await.unwrap();
+                    tx.send(black_box(Create(order))).await.unwrap();
                 }
 
                 drop(tx);
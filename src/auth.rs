@@ -0,0 +1,67 @@
+use crate::configuration::ApiKey;
+use crate::domain::account::AccountId;
+
+/// API keys that identify the account behind an HTTP request or WebSocket
+/// session.
+#[derive(Default)]
+pub struct ApiKeys {
+    keys: Vec<ApiKey>,
+}
+
+impl ApiKeys {
+    pub fn new(keys: Vec<ApiKey>) -> Self {
+        ApiKeys { keys }
+    }
+
+    /// Account the key was issued to. Every configured key is compared, so
+    /// the response time does not tell which one came closest.
+    pub fn account(&self, key: &str) -> Option<AccountId> {
+        self.keys.iter().fold(None, |found, candidate| {
+            if constant_time_eq(key.as_bytes(), candidate.key.as_bytes()) {
+                Some(candidate.account)
+            } else {
+                found
+            }
+        })
+    }
+}
+
+/// Compares every byte so the response time does not leak a common prefix.
+pub fn constant_time_eq(provided: &[u8], expected: &[u8]) -> bool {
+    provided.len() == expected.len()
+        && provided
+            .iter()
+            .zip(expected)
+            .fold(0, |acc, (a, b)| acc | (a ^ b))
+            == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys() -> ApiKeys {
+        ApiKeys::new(vec![
+            ApiKey {
+                account: AccountId(1),
+                key: "first".to_string(),
+            },
+            ApiKey {
+                account: AccountId(2),
+                key: "second".to_string(),
+            },
+        ])
+    }
+
+    #[test]
+    fn keys_identify_their_account() {
+        assert_eq!(keys().account("second"), Some(AccountId(2)));
+    }
+
+    #[test]
+    fn unknown_keys_and_prefixes_identify_no_account() {
+        assert_eq!(keys().account("secon"), None);
+        assert_eq!(keys().account("seconds"), None);
+        assert_eq!(keys().account(""), None);
+    }
+}
@@ -1,13 +1,21 @@
+use crate::domain::account::AccountId;
+use crate::domain::symbol::Symbol;
+use crate::domain::trading_phase::TradingPhase;
+use crate::matching::schedule::TimeOfDay;
 use smart_default::SmartDefault;
+use std::collections::HashMap;
 
 #[derive(serde::Deserialize, SmartDefault)]
 #[serde(default)]
 pub struct Settings {
     #[default(Default::default())]
     pub application: AppSettings,
+    pub instruments: HashMap<Symbol, InstrumentSettings>,
+    pub fees: FeeSettings,
 }
 
 #[derive(serde::Deserialize, SmartDefault)]
+#[serde(default)]
 pub struct AppSettings {
     #[default = "127.0.0.1"]
     pub host: String,
@@ -15,6 +23,226 @@ pub struct AppSettings {
     pub port: u16,
     #[default = 100_000]
     pub matching_buffer: usize,
+    /// Bearer token required by the `/admin` endpoints. They reject every
+    /// request while it is unset.
+    pub admin_token: Option<String>,
+    /// Bearer tokens identifying the account of HTTP and WebSocket clients.
+    pub api_keys: Vec<ApiKey>,
+    #[default(Default::default())]
+    pub rate_limit: RateLimitSettings,
+    /// Binary order entry gateway, disabled while unset.
+    pub ouch: Option<OuchSettings>,
+    /// FIX 4.4 order entry gateway, disabled while unset.
+    pub fix: Option<FixSettings>,
+    /// Binary market data feed, disabled while unset.
+    pub itch: Option<ItchSettings>,
+    #[default(Default::default())]
+    pub candles: CandleSettings,
+    #[default(Default::default())]
+    pub trades: TradeHistorySettings,
+    #[default(Default::default())]
+    pub ticker: TickerSettings,
+    #[default(Default::default())]
+    pub depth: DepthSettings,
+}
+
+#[derive(serde::Deserialize, SmartDefault, Clone, Debug)]
+#[serde(default)]
+pub struct DepthSettings {
+    /// Shortest time between two updates of a subscriber on the WebSocket.
+    #[default = 100]
+    pub interval_ms: u64,
+}
+
+#[derive(serde::Deserialize, SmartDefault, Clone, Debug)]
+#[serde(default)]
+pub struct TickerSettings {
+    /// Shortest time between two tickers of an instrument on the WebSocket.
+    #[default = 1_000]
+    pub interval_ms: u64,
+}
+
+#[derive(serde::Deserialize, SmartDefault, Clone, Debug)]
+#[serde(default)]
+pub struct TradeHistorySettings {
+    /// Latest trades kept across all instruments.
+    #[default = 100_000]
+    pub capacity: usize,
+}
+
+#[derive(serde::Deserialize, SmartDefault, Clone, Debug)]
+#[serde(default)]
+pub struct CandleSettings {
+    /// Finished bars kept in memory per instrument and interval.
+    #[default = 1_000]
+    pub capacity: usize,
+    /// Directory that bars leaving memory are appended to. They are dropped
+    /// while unset.
+    pub spill_dir: Option<String>,
+}
+
+#[derive(serde::Deserialize, SmartDefault, Clone, Debug)]
+#[serde(default)]
+pub struct OuchSettings {
+    #[default = "127.0.0.1"]
+    pub host: String,
+    #[default = 9000]
+    pub port: u16,
+    /// Idle time after which the server sends a heartbeat. Clients that stay
+    /// silent for three intervals are disconnected.
+    #[default = 1_000]
+    pub heartbeat_ms: u64,
+    pub credentials: Vec<AccountCredential>,
+}
+
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct ApiKey {
+    pub account: AccountId,
+    pub key: String,
+}
+
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct AccountCredential {
+    pub account: AccountId,
+    pub password: String,
+}
+
+#[derive(serde::Deserialize, SmartDefault, Clone, Debug)]
+#[serde(default)]
+pub struct FixSettings {
+    #[default = "127.0.0.1"]
+    pub host: String,
+    #[default = 9878]
+    pub port: u16,
+    /// SenderCompID of the exchange, counterparties address it as their
+    /// TargetCompID.
+    #[default = "EXCHANGE"]
+    pub comp_id: String,
+    /// Directory holding the sequence numbers and sent application messages
+    /// of every session, so they survive restarts.
+    #[default = "fix_store"]
+    pub store_dir: String,
+    pub sessions: Vec<FixSessionSettings>,
+}
+
+/// A counterparty allowed to log on with `sender_comp_id`. Its orders are
+/// placed for `account`.
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct FixSessionSettings {
+    pub sender_comp_id: String,
+    pub account: AccountId,
+    pub password: Option<String>,
+}
+
+#[derive(serde::Deserialize, SmartDefault, Clone, Debug)]
+#[serde(default)]
+pub struct ItchSettings {
+    #[default = "ITCH"]
+    pub session: String,
+    /// Multicast group or unicast address the datagrams are sent to.
+    #[default = "127.0.0.1:30001"]
+    pub target: String,
+    /// Local address of the sending socket.
+    #[default = "0.0.0.0:0"]
+    pub bind: String,
+    #[default = 1]
+    pub multicast_ttl: u32,
+    /// Interval of the heartbeats sent while there are no messages.
+    #[default = 1_000]
+    pub heartbeat_ms: u64,
+    #[default = "127.0.0.1"]
+    pub retransmit_host: String,
+    #[default = 9001]
+    pub retransmit_port: u16,
+    /// Messages kept for retransmission.
+    #[default = 1_000_000]
+    pub journal_capacity: usize,
+}
+
+#[derive(serde::Deserialize, SmartDefault, Clone, Debug)]
+#[serde(default)]
+pub struct RateLimitSettings {
+    #[default(TokenBucketSettings { burst: 200, refill_per_second: 100 })]
+    pub account: TokenBucketSettings,
+    #[default(TokenBucketSettings { burst: 500, refill_per_second: 250 })]
+    pub ip: TokenBucketSettings,
+}
+
+/// A bucket holds up to `burst` tokens and regains `refill_per_second` of
+/// them every second. Each order, modification or cancel costs one token.
+#[derive(serde::Deserialize, SmartDefault, Clone, Debug)]
+#[serde(default)]
+pub struct TokenBucketSettings {
+    #[default = 100]
+    pub burst: u32,
+    #[default = 50]
+    pub refill_per_second: u32,
+}
+
+#[derive(serde::Deserialize, SmartDefault, Clone, Debug)]
+#[serde(default)]
+pub struct InstrumentSettings {
+    pub circuit_breaker: Option<CircuitBreakerSettings>,
+    /// Orders and modifications above this quantity are rejected.
+    pub max_order_quantity: Option<i64>,
+    /// Trading day of the instrument, always open while unset.
+    pub schedule: Option<ScheduleSettings>,
+}
+
+/// Phases of the trading day, each lasting until the next one starts. The
+/// last phase of a day lasts until the first one of the next.
+#[derive(serde::Deserialize, Default, Clone, Debug)]
+#[serde(default)]
+pub struct ScheduleSettings {
+    pub phases: Vec<PhaseStart>,
+}
+
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct PhaseStart {
+    pub phase: TradingPhase,
+    /// UTC time of day the phase starts at.
+    pub start: TimeOfDay,
+}
+
+/// Halts an instrument when a trade moves the price more than
+/// `threshold_bps` basis points away from the first trade of the rolling
+/// `window_ms`, and resumes trading after `cooldown_ms`.
+#[derive(serde::Deserialize, SmartDefault, Clone, Debug)]
+#[serde(default)]
+pub struct CircuitBreakerSettings {
+    #[default = 1_000]
+    pub threshold_bps: i64,
+    #[default = 60_000]
+    pub window_ms: u64,
+    #[default = 300_000]
+    pub cooldown_ms: u64,
+}
+
+/// Maker and taker rates per volume tier. An account pays the rates of the
+/// highest tier whose `min_volume` its traded notional of the last 30 days
+/// reaches, unless it is pinned to a tier in `accounts`.
+#[derive(serde::Deserialize, SmartDefault, Clone, Debug)]
+#[serde(default)]
+pub struct FeeSettings {
+    pub tiers: Vec<FeeTier>,
+    pub accounts: Vec<AccountFeeTier>,
+}
+
+/// Rates are in basis points of the traded notional, negative rates are
+/// rebates.
+#[derive(serde::Deserialize, SmartDefault, Clone, Debug)]
+#[serde(default)]
+pub struct FeeTier {
+    pub name: String,
+    pub min_volume: i64,
+    pub maker_bps: i64,
+    pub taker_bps: i64,
+}
+
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct AccountFeeTier {
+    pub account: AccountId,
+    pub tier: String,
 }
 
 impl Settings {
@@ -25,6 +253,13 @@ impl Settings {
 
 pub fn get_configuration() -> Result<Settings, config::ConfigError> {
     let settings = config::Config::builder()
+        .add_source(config::File::with_name("config").required(false))
+        .add_source(
+            config::Environment::with_prefix("APP")
+                .prefix_separator("_")
+                .separator("__")
+                .try_parsing(true),
+        )
         .build()
         .expect("Error when reading config");
 
@@ -0,0 +1,10 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize, Serialize, Debug, Eq, PartialEq, Copy, Clone, Default, Hash)]
+pub struct AccountId(pub u64);
+
+impl From<u64> for AccountId {
+    fn from(value: u64) -> Self {
+        AccountId(value)
+    }
+}
@@ -0,0 +1,134 @@
+use crate::domain::order::{Price, Quantity};
+use serde::{Deserialize, Serialize};
+
+/// Price at which a crossed book is uncrossed at the end of a call auction.
+#[derive(Deserialize, Serialize, Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Equilibrium {
+    pub price: Price,
+    /// Quantity executed at `price`.
+    pub volume: Quantity,
+    /// Quantity left unmatched at `price`, positive on the buy side.
+    pub surplus: i64,
+}
+
+/// Finds the equilibrium of the aggregated levels, bids best first and asks
+/// best first, or `None` while they do not cross. Of the limit prices the one
+/// executing the most quantity wins; ties go to the smaller surplus, then to
+/// the highest price if every remaining price has a buy surplus or the
+/// lowest if every one has a sell surplus. Otherwise the `reference` price,
+/// usually the last trade, is used when it lies between the remaining prices
+/// and the nearest of them when it does not, falling back to their midpoint.
+pub fn equilibrium(
+    bids: &[(Price, Quantity)],
+    asks: &[(Price, Quantity)],
+    reference: Option<Price>,
+) -> Option<Equilibrium> {
+    let mut prices: Vec<Price> = bids.iter().chain(asks).map(|&(price, _)| price).collect();
+    prices.sort();
+    prices.dedup();
+
+    let at = |price: Price| {
+        let demand: i64 = bids
+            .iter()
+            .take_while(|&&(bid, _)| bid >= price)
+            .map(|(_, quantity)| quantity.0)
+            .sum();
+        let supply: i64 = asks
+            .iter()
+            .take_while(|&&(ask, _)| ask <= price)
+            .map(|(_, quantity)| quantity.0)
+            .sum();
+        Equilibrium {
+            price,
+            volume: Quantity(demand.min(supply)),
+            surplus: demand - supply,
+        }
+    };
+    let candidates: Vec<Equilibrium> = prices.into_iter().map(at).collect();
+
+    let volume = candidates.iter().map(|c| c.volume).max()?;
+    if volume == Quantity(0) {
+        return None;
+    }
+    let candidates: Vec<&Equilibrium> = candidates.iter().filter(|c| c.volume == volume).collect();
+    let surplus = candidates.iter().map(|c| c.surplus.abs()).min()?;
+    let candidates: Vec<&Equilibrium> = candidates
+        .into_iter()
+        .filter(|c| c.surplus.abs() == surplus)
+        .collect();
+
+    let lowest = *candidates[0];
+    let highest = **candidates.last().expect("At least one candidate");
+    if candidates.iter().all(|c| c.surplus > 0) {
+        return Some(highest);
+    }
+    if candidates.iter().all(|c| c.surplus < 0) {
+        return Some(lowest);
+    }
+
+    // every price between the remaining ones executes the same volume
+    Some(at(match reference {
+        Some(reference) => reference.clamp(lowest.price, highest.price),
+        None => Price(lowest.price.0 + (highest.price.0 - lowest.price.0) / 2),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn levels(levels: &[(i64, i64)]) -> Vec<(Price, Quantity)> {
+        levels
+            .iter()
+            .map(|&(price, quantity)| (Price(price), Quantity(quantity)))
+            .collect()
+    }
+
+    fn price(bids: &[(i64, i64)], asks: &[(i64, i64)], reference: Option<i64>) -> Option<i64> {
+        equilibrium(&levels(bids), &levels(asks), reference.map(Price)).map(|e| e.price.0)
+    }
+
+    #[test]
+    fn the_price_executing_the_most_quantity_wins() {
+        let bids = levels(&[(105, 10), (103, 20), (100, 30)]);
+        let asks = levels(&[(99, 15), (103, 15), (104, 30)]);
+
+        let equilibrium = equilibrium(&bids, &asks, None).expect("Book is crossed");
+
+        assert_eq!(equilibrium.price, Price(103));
+        assert_eq!(equilibrium.volume, Quantity(30));
+        assert_eq!(equilibrium.surplus, 0);
+    }
+
+    #[test]
+    fn uncrossed_books_have_no_equilibrium() {
+        assert_eq!(price(&[(99, 10)], &[(100, 10)], None), None);
+        assert_eq!(price(&[], &[(100, 10)], None), None);
+    }
+
+    #[test]
+    fn ties_go_to_the_smaller_surplus() {
+        // 10 execute at 100 and 101, leaving 5 or 0 unmatched
+        assert_eq!(price(&[(101, 10), (100, 5)], &[(100, 10)], None), Some(101));
+    }
+
+    #[test]
+    fn ties_follow_the_market_pressure() {
+        // buyers are left over at every price, so the highest one is used
+        assert_eq!(price(&[(102, 20)], &[(100, 5), (101, 5)], None), Some(102));
+        // sellers are left over at every price, so the lowest one is used
+        assert_eq!(
+            price(&[(101, 5), (100, 5)], &[(99, 20)], Some(101)),
+            Some(99)
+        );
+    }
+
+    #[test]
+    fn balanced_ties_use_the_reference_price() {
+        let (bids, asks) = (&[(105, 10)], &[(95, 10)]);
+        assert_eq!(price(bids, asks, Some(98)), Some(98));
+        assert_eq!(price(bids, asks, Some(120)), Some(105));
+        assert_eq!(price(bids, asks, Some(50)), Some(95));
+        assert_eq!(price(bids, asks, None), Some(100));
+    }
+}
@@ -0,0 +1,61 @@
+use crate::domain::account::AccountId;
+use crate::domain::order::{OrderId, Price, Quantity, Revision};
+use crate::domain::side::Side;
+use crate::domain::trade::{Trade, TradeId, TradeParty};
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize, Serialize, Debug, Eq, PartialEq, Copy, Clone)]
+pub enum Liquidity {
+    Maker,
+    Taker,
+}
+
+/// Execution report for one side of a trade, as seen by the account that
+/// owns the order.
+#[derive(Clone, Deserialize, Serialize, Debug)]
+pub struct Fill {
+    pub trade_id: TradeId,
+    pub account: AccountId,
+    pub order_id: OrderId,
+    /// Revision of the order after the fill.
+    pub revision: Revision,
+    pub side: Side,
+    pub aggressor: Side,
+    pub liquidity: Liquidity,
+    pub price: Price,
+    pub quantity: Quantity,
+    pub leaves_quantity: Quantity,
+    pub cum_quantity: Quantity,
+    pub avg_price: Price,
+    pub fee: i64,
+    pub exec_time: i64,
+}
+
+impl Fill {
+    /// Splits a trade into the maker and the taker report.
+    pub fn from_trade(trade: &Trade) -> (Fill, Fill) {
+        let taker_side = trade.aggressor;
+        let maker_side = taker_side.opposite();
+        let fill = |order_id, side, liquidity, party: &TradeParty| Fill {
+            trade_id: trade.id,
+            account: party.account,
+            order_id,
+            revision: party.revision,
+            side,
+            aggressor: taker_side,
+            liquidity,
+            price: trade.price,
+            quantity: trade.quantity,
+            leaves_quantity: party.leaves_quantity,
+            cum_quantity: party.cum_quantity,
+            avg_price: party.avg_price,
+            fee: party.fee,
+            exec_time: trade.exec_time(),
+        };
+
+        (
+            fill(trade.maker_id(), maker_side, Liquidity::Maker, &trade.maker),
+            fill(trade.taker_id(), taker_side, Liquidity::Taker, &trade.taker),
+        )
+    }
+}
@@ -1,6 +1,14 @@
+pub mod account;
+pub mod auction;
+pub mod fill;
 pub mod order;
 pub mod order_book;
-mod order_book_level;
+pub mod order_book_level;
+pub mod order_change;
 pub mod order_entry;
+pub mod order_filter;
 pub mod side;
+pub mod symbol;
 pub mod trade;
+pub mod trading_phase;
+pub mod trading_state;
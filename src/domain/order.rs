@@ -1,5 +1,7 @@
+use crate::domain::account::AccountId;
 use crate::domain::order_entry::OrderEntry;
 use crate::domain::side::Side;
+use crate::domain::symbol::Symbol;
 use serde::{Deserialize, Serialize};
 use std::iter::Sum;
 use std::ops::SubAssign;
@@ -20,7 +22,11 @@ impl From<Uuid> for OrderId {
         OrderId(value)
     }
 }
-#[derive(Deserialize, Serialize, Debug, Eq, PartialOrd, PartialEq, Ord, Copy, Clone)]
+/// Prices are fixed point numbers with two decimals, a price of 12_345 is
+/// 123.45. Notionals, `price * quantity / PRICE_SCALE`, are whole units.
+pub const PRICE_SCALE: i64 = 100;
+
+#[derive(Deserialize, Serialize, Debug, Eq, PartialOrd, PartialEq, Ord, Copy, Clone, Default)]
 pub struct Price(pub i64);
 
 impl From<i64> for Price {
@@ -28,7 +34,7 @@ impl From<i64> for Price {
         Price(value)
     }
 }
-#[derive(Deserialize, Serialize, Debug, Eq, PartialOrd, PartialEq, Copy, Clone, Ord)]
+#[derive(Deserialize, Serialize, Debug, Eq, PartialOrd, PartialEq, Copy, Clone, Ord, Default)]
 pub struct Quantity(pub i64);
 impl From<i64> for Quantity {
     fn from(value: i64) -> Self {
@@ -47,7 +53,9 @@ impl Sum for Quantity {
         Quantity(iter.map(|q| q.0).sum())
     }
 }
-#[derive(Deserialize, Serialize, Debug, Eq, PartialOrd, PartialEq, Hash, Copy, Clone)]
+#[derive(
+    Deserialize, Serialize, Debug, Eq, PartialOrd, PartialEq, Ord, Hash, Copy, Clone, Default,
+)]
 pub struct Revision(pub usize);
 impl Revision {
     pub fn increment(&mut self) {
@@ -61,6 +69,18 @@ impl From<usize> for Revision {
     }
 }
 
+/// How long an order may rest in the book.
+#[derive(Deserialize, Serialize, Debug, Eq, PartialEq, Copy, Clone, Default, Hash)]
+pub enum TimeInForce {
+    /// Expires when the instrument closes.
+    #[default]
+    Day,
+    /// Rests until it is filled or cancelled.
+    Gtc,
+    /// Whatever does not trade on entry is cancelled.
+    Ioc,
+}
+
 #[derive(Deserialize, Debug, Clone, Serialize)]
 pub struct Order {
     pub id: OrderId,
@@ -68,8 +88,32 @@ pub struct Order {
     pub quantity: Quantity,
     pub side: Side,
     pub revision: Revision,
+    #[serde(default)]
+    pub symbol: Symbol,
+    #[serde(default)]
+    pub account: AccountId,
+    #[serde(default)]
+    pub time_in_force: TimeInForce,
+    /// Quantity filled so far, across all revisions.
+    #[serde(default)]
+    pub executed: Quantity,
+    /// Sum of `price * quantity` over all fills, used for the average price.
+    #[serde(default)]
+    pub executed_value: i64,
 }
 impl Order {
+    pub fn record_execution(&mut self, price: Price, quantity: Quantity) {
+        self.executed.0 += quantity.0;
+        self.executed_value += price.0 * quantity.0;
+    }
+
+    pub fn avg_price(&self) -> Price {
+        if self.executed.0 == 0 {
+            return Price(0);
+        }
+        Price(self.executed_value / self.executed.0)
+    }
+
     pub fn update<P, Q>(&mut self, new_price: Option<P>, new_quantity: Option<Q>)
     where
         P: Into<Price>,
@@ -98,6 +142,66 @@ impl From<OrderEntry> for Order {
             quantity: value.quantity,
             side: value.side,
             revision: Revision(0),
+            symbol: value.symbol,
+            account: value.account,
+            time_in_force: value.time_in_force,
+            executed: Quantity(0),
+            executed_value: 0,
         }
     }
 }
+
+/// Serializes an order without the account that owns it, for the events
+/// every subscriber of the market data feed receives.
+pub mod public {
+    use super::*;
+    use serde::{Deserializer, Serializer};
+
+    #[derive(Deserialize, Serialize)]
+    struct PublicOrder {
+        id: OrderId,
+        price: Price,
+        quantity: Quantity,
+        side: Side,
+        revision: Revision,
+        #[serde(default)]
+        symbol: Symbol,
+        #[serde(default)]
+        time_in_force: TimeInForce,
+        #[serde(default)]
+        executed: Quantity,
+        #[serde(default)]
+        executed_value: i64,
+    }
+
+    pub fn serialize<S: Serializer>(order: &Order, serializer: S) -> Result<S::Ok, S::Error> {
+        PublicOrder {
+            id: order.id,
+            price: order.price,
+            quantity: order.quantity,
+            side: order.side,
+            revision: order.revision,
+            symbol: order.symbol,
+            time_in_force: order.time_in_force,
+            executed: order.executed,
+            executed_value: order.executed_value,
+        }
+        .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Order, D::Error> {
+        let order = PublicOrder::deserialize(deserializer)?;
+        Ok(Order {
+            id: order.id,
+            price: order.price,
+            quantity: order.quantity,
+            side: order.side,
+            revision: order.revision,
+            symbol: order.symbol,
+            account: AccountId::default(),
+            time_in_force: order.time_in_force,
+            executed: order.executed,
+            executed_value: order.executed_value,
+        })
+    }
+}
@@ -1,5 +1,7 @@
-use crate::domain::order::{Order, OrderId, Price, Quantity, Revision};
-use crate::domain::order_book_level::OrderBookLevel;
+use crate::domain::auction::{Equilibrium, equilibrium};
+use crate::domain::order::{Order, OrderId, Price, Quantity, Revision, TimeInForce};
+use crate::domain::order_book_level::{OrderBookLevel, OrderBookSnapshot, QueuedOrder};
+use crate::domain::order_filter::OrderFilter;
 use crate::domain::side::Side;
 use crate::domain::trade::Trade;
 use slotmap::{SlotMap, new_key_type};
@@ -8,10 +10,54 @@ use std::ops::Sub;
 
 new_key_type! { pub struct OrderKey; }
 
+/// Orders resting at one price in time priority. Removing an order from the
+/// middle of the queue only drops it from `orders`; its key stays behind as a
+/// tombstone that is skipped, and the queue is compacted once tombstones make
+/// up half of it.
+#[derive(Default, Debug)]
+struct PriceLevel {
+    keys: VecDeque<OrderKey>,
+    tombstones: usize,
+}
+
+impl PriceLevel {
+    fn push(&mut self, key: OrderKey) {
+        self.keys.push_back(key);
+    }
+
+    fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    fn orders<'a>(
+        &'a self,
+        orders: &'a SlotMap<OrderKey, Order>,
+    ) -> impl Iterator<Item = &'a Order> {
+        self.keys.iter().filter_map(|k| orders.get(*k))
+    }
+
+    /// Records that one of the keys no longer points to a live order.
+    fn bury(&mut self, orders: &SlotMap<OrderKey, Order>) {
+        self.tombstones += 1;
+        self.compact(orders);
+    }
+
+    fn compact(&mut self, orders: &SlotMap<OrderKey, Order>) {
+        while self.keys.front().is_some_and(|k| !orders.contains_key(*k)) {
+            self.keys.pop_front();
+            self.tombstones -= 1;
+        }
+        if self.tombstones * 2 > self.keys.len() {
+            self.keys.retain(|k| orders.contains_key(*k));
+            self.tombstones = 0;
+        }
+    }
+}
+
 #[derive(Default, Debug)]
 pub struct OrderBook {
-    bid: BTreeMap<Price, VecDeque<OrderKey>>,
-    ask: BTreeMap<Price, VecDeque<OrderKey>>,
+    bid: BTreeMap<Price, PriceLevel>,
+    ask: BTreeMap<Price, PriceLevel>,
     indexed: HashMap<(OrderId, Revision), OrderKey>,
     orders: SlotMap<OrderKey, Order>,
 }
@@ -29,35 +75,148 @@ pub enum OrderModificationError {
     OrderNotFound,
 }
 
+#[derive(Debug)]
+pub struct Modification {
+    pub previous: Order,
+    /// The order after the modification, before it was matched.
+    pub order: Order,
+    /// `false` when the order kept its place in the queue.
+    pub priority_lost: bool,
+    pub trades: Option<Vec<Trade>>,
+    /// See `Sweep::stopped_at`.
+    pub stopped_at: Option<Price>,
+}
+
+/// Outcome of matching an order that may only trade at some prices.
+#[derive(Debug, Default)]
+pub struct Sweep {
+    pub trades: Vec<Trade>,
+    /// First price of the opposite side the order was not allowed to trade
+    /// at. The rest of the order rests in the book, crossed with it.
+    pub stopped_at: Option<Price>,
+}
+
 impl OrderBook {
     pub fn best_of_book(&self) -> (Option<OrderBookLevel>, Option<OrderBookLevel>) {
         let best_bid = self
             .bid
             .last_key_value()
-            .map(|(&price, order_keys)| OrderBookLevel {
+            .map(|(&price, level)| OrderBookLevel {
                 price,
-                quantity: order_keys
-                    .iter()
-                    .filter_map(|k| self.orders.get(*k))
-                    .map(|o| o.quantity)
-                    .sum(),
+                quantity: level.orders(&self.orders).map(|o| o.quantity).sum(),
             });
         let best_ask = self
             .ask
             .first_key_value()
-            .map(|(&price, order_keys)| OrderBookLevel {
+            .map(|(&price, level)| OrderBookLevel {
                 price,
-                quantity: order_keys
-                    .iter()
-                    .filter_map(|k| self.orders.get(*k))
-                    .map(|o| o.quantity)
-                    .sum(),
+                quantity: level.orders(&self.orders).map(|o| o.quantity).sum(),
             });
 
         (best_bid, best_ask)
     }
 
-    fn add_to_book<O: Into<Order>>(&mut self, order_entry: O) -> OrderId {
+    pub fn snapshot(&self) -> OrderBookSnapshot {
+        OrderBookSnapshot {
+            bids: self.queued(self.bid.iter().rev()),
+            asks: self.queued(self.ask.iter()),
+            ..Default::default()
+        }
+    }
+
+    fn queued<'a>(
+        &'a self,
+        levels: impl Iterator<Item = (&'a Price, &'a PriceLevel)>,
+    ) -> Vec<QueuedOrder> {
+        levels
+            .flat_map(|(&price, level)| {
+                level
+                    .orders(&self.orders)
+                    .enumerate()
+                    .map(move |(position, order)| QueuedOrder {
+                        id: order.id,
+                        revision: order.revision,
+                        price,
+                        quantity: order.quantity,
+                        position,
+                    })
+            })
+            .collect()
+    }
+
+    /// Where the book would be uncrossed, `None` while it is not crossed.
+    pub fn equilibrium(&self, reference: Option<Price>) -> Option<Equilibrium> {
+        let bids = self.depth(self.bid.iter().rev());
+        let asks = self.depth(self.ask.iter());
+        equilibrium(&bids, &asks, reference)
+    }
+
+    fn depth<'a>(
+        &'a self,
+        levels: impl Iterator<Item = (&'a Price, &'a PriceLevel)>,
+    ) -> Vec<(Price, Quantity)> {
+        levels
+            .map(|(&price, level)| (price, level.orders(&self.orders).map(|o| o.quantity).sum()))
+            .collect()
+    }
+
+    /// Executes the crossed part of the book at its equilibrium price, the
+    /// best priced orders first and in time priority within a price.
+    pub fn uncross(&mut self, reference: Option<Price>) -> Option<Vec<Trade>> {
+        let Equilibrium { price, volume, .. } = self.equilibrium(reference)?;
+        let mut remaining = volume;
+        let mut trades = Vec::new();
+
+        while remaining > Quantity(0) {
+            let buy = self.best_order(Side::Buy).expect("Bids cover the volume");
+            let sell = self.best_order(Side::Sell).expect("Asks cover the volume");
+            let quantity = remaining
+                .min(self.orders[buy].quantity)
+                .min(self.orders[sell].quantity);
+            remaining -= quantity;
+
+            self.execute(buy, price, quantity);
+            self.execute(sell, price, quantity);
+            trades.push(Trade::crossed(
+                price,
+                &self.orders[buy],
+                &self.orders[sell],
+                quantity,
+            ));
+            for key in [buy, sell] {
+                if self.orders[key].quantity == Quantity(0) {
+                    self.remove_resting(key);
+                }
+            }
+        }
+
+        Some(trades)
+    }
+
+    fn best_order(&self, side: Side) -> Option<OrderKey> {
+        let level = match side {
+            Side::Buy => self.bid.last_key_value(),
+            Side::Sell => self.ask.first_key_value(),
+        };
+        let (_, level) = level?;
+        level
+            .keys
+            .iter()
+            .copied()
+            .find(|&k| self.orders.contains_key(k))
+    }
+
+    /// Fills part of a resting order in place, keeping its queue position.
+    fn execute(&mut self, key: OrderKey, price: Price, quantity: Quantity) {
+        let order = &mut self.orders[key];
+        self.indexed.remove(&(order.id, order.revision));
+        order.update(None::<Price>, Some(order.quantity - quantity));
+        order.record_execution(price, quantity);
+        self.indexed.insert((order.id, order.revision), key);
+    }
+
+    /// Rests the order without matching it, as during a call auction.
+    pub fn add_to_book<O: Into<Order>>(&mut self, order_entry: O) -> OrderId {
         let order = order_entry.into();
         let Order {
             id,
@@ -71,10 +230,10 @@ impl OrderBook {
 
         match side {
             Side::Buy => {
-                self.bid.entry(price).or_default().push_back(key);
+                self.bid.entry(price).or_default().push(key);
             }
             Side::Sell => {
-                self.ask.entry(price).or_default().push_back(key);
+                self.ask.entry(price).or_default().push(key);
             }
         }
         id
@@ -84,69 +243,199 @@ impl OrderBook {
         &mut self,
         key: &(OrderId, Revision),
     ) -> Result<Order, OrderModificationError> {
-        if let Some(order_key) = self.indexed.remove(key) {
-            self.indexed.remove(key);
-            let removed = self.orders.remove(order_key).unwrap();
-            Ok(removed)
-        } else {
-            Err(OrderModificationError::OrderNotFound)
+        let order_key = *self
+            .indexed
+            .get(key)
+            .ok_or(OrderModificationError::OrderNotFound)?;
+
+        Ok(self
+            .remove_resting(order_key)
+            .expect("Indexed order must exist"))
+    }
+
+    pub fn cancel_orders(&mut self, filter: &OrderFilter) -> Vec<Order> {
+        let keys: Vec<OrderKey> = self
+            .orders
+            .iter()
+            .filter(|(_, o)| filter.matches(o))
+            .map(|(k, _)| k)
+            .collect();
+
+        keys.into_iter()
+            .filter_map(|k| self.remove_resting(k))
+            .collect()
+    }
+
+    fn remove_resting(&mut self, key: OrderKey) -> Option<Order> {
+        let order = self.orders.remove(key)?;
+        self.indexed.remove(&(order.id, order.revision));
+
+        let side = match order.side {
+            Side::Buy => &mut self.bid,
+            Side::Sell => &mut self.ask,
+        };
+        if let Some(level) = side.get_mut(&order.price) {
+            level.bury(&self.orders);
+            if level.is_empty() {
+                side.remove(&order.price);
+            }
         }
+
+        Some(order)
     }
 
+    /// Applies a modification. A pure quantity reduction, or restating the
+    /// current price and quantity, updates the order in place and keeps its
+    /// queue position; a price change or a quantity
+    /// increase re-enters the order at the back of the queue, matching it
+    /// first if it became marketable and `matching` is set.
     pub fn modify_order(
         &mut self,
         order_id: OrderId,
         revision: Revision,
         price: Option<Price>,
         quantity: Option<Quantity>,
-    ) -> Result<Option<Vec<Trade>>, OrderModificationError> {
-        let key = (order_id, revision);
-        if let Ok(mut order) = self.delete_order(&key) {
-            order.update(price, quantity);
+        matching: bool,
+    ) -> Result<Modification, OrderModificationError> {
+        let mut admits = |_| true;
+        let admits: Option<&mut dyn FnMut(Price) -> bool> = matching.then_some(&mut admits);
+        self.modify(order_id, revision, price, quantity, admits)
+    }
 
-            let trades = self.match_order(order);
-            Ok(trades)
-        } else {
-            Err(OrderModificationError::OrderNotFound)
+    /// Like `modify_order` with matching, trading only at the prices
+    /// `admits` accepts, see `match_order_within`.
+    pub fn modify_order_within(
+        &mut self,
+        order_id: OrderId,
+        revision: Revision,
+        price: Option<Price>,
+        quantity: Option<Quantity>,
+        admits: &mut dyn FnMut(Price) -> bool,
+    ) -> Result<Modification, OrderModificationError> {
+        self.modify(order_id, revision, price, quantity, Some(admits))
+    }
+
+    fn modify(
+        &mut self,
+        order_id: OrderId,
+        revision: Revision,
+        price: Option<Price>,
+        quantity: Option<Quantity>,
+        matching: Option<&mut dyn FnMut(Price) -> bool>,
+    ) -> Result<Modification, OrderModificationError> {
+        let key = (order_id, revision);
+        let order_key = *self
+            .indexed
+            .get(&key)
+            .ok_or(OrderModificationError::OrderNotFound)?;
+        let order = &mut self.orders[order_key];
+
+        let keeps_price = price.is_none_or(|p| p == order.price);
+        let keeps_quantity = quantity.is_none_or(|q| q == order.quantity);
+        let reduces = quantity.is_some_and(|q| q > Quantity(0) && q < order.quantity);
+        if keeps_price && (reduces || keeps_quantity) {
+            let previous = order.clone();
+            order.update(None::<Price>, quantity);
+            self.indexed.remove(&key);
+            self.indexed.insert((order.id, order.revision), order_key);
+
+            return Ok(Modification {
+                previous,
+                order: order.clone(),
+                priority_lost: false,
+                trades: None,
+                stopped_at: None,
+            });
         }
+
+        let mut order = self
+            .remove_resting(order_key)
+            .expect("Indexed order must exist");
+        let previous = order.clone();
+        order.update(price, quantity);
+
+        let modified = order.clone();
+        let sweep = match matching {
+            Some(admits) => self.match_order_within(order, admits),
+            None => {
+                self.add_to_book(order);
+                Sweep::default()
+            }
+        };
+
+        Ok(Modification {
+            previous,
+            order: modified,
+            priority_lost: true,
+            trades: (!sweep.trades.is_empty()).then_some(sweep.trades),
+            stopped_at: sweep.stopped_at,
+        })
     }
+
     pub fn match_order<O: Into<Order>>(&mut self, order_entry: O) -> Option<Vec<Trade>> {
+        let sweep = self.match_order_within(order_entry, &mut |_| true);
+        (!sweep.trades.is_empty()).then_some(sweep.trades)
+    }
+
+    /// Matches the order level by level and stops before the first level
+    /// whose price `admits` refuses, e.g. because it lies outside the price
+    /// band of a circuit breaker.
+    pub fn match_order_within<O: Into<Order>>(
+        &mut self,
+        order_entry: O,
+        admits: &mut dyn FnMut(Price) -> bool,
+    ) -> Sweep {
         let mut new_order = order_entry.into();
+        let mut stopped_at = None;
         let mut remaining_quantity = new_order.quantity;
         let mut trades = Vec::with_capacity(8);
         let mut prices_to_remove = Vec::with_capacity(4);
 
         #[inline(always)]
         fn matching_loop(
-            order_keys: &mut VecDeque<OrderKey>,
+            level: &mut PriceLevel,
             orders: &mut SlotMap<OrderKey, Order>,
             indexed: &mut HashMap<(OrderId, Revision), OrderKey>,
             mut remaining_quantity: Quantity,
             trades: &mut Vec<Trade>,
-            taker_id: OrderId,
+            taker: &mut Order,
         ) -> Quantity {
             let zero_quantity = Quantity(0);
-            while remaining_quantity > zero_quantity && !order_keys.is_empty() {
-                let key = order_keys.front().unwrap();
-                let order = orders.get_mut(*key).expect("Order must exist");
+            while remaining_quantity > zero_quantity
+                && let Some(&key) = level.keys.front()
+            {
+                let Some(order) = orders.get_mut(key) else {
+                    level.keys.pop_front();
+                    level.tombstones -= 1;
+                    continue;
+                };
 
                 let trade_quantity = remaining_quantity.min(order.quantity);
 
-                trades.push(Trade::new(order.price, trade_quantity, order.id, taker_id));
-
                 remaining_quantity -= trade_quantity;
                 let old_index = (order.id, order.revision);
                 order.update(None::<Price>, Some(order.quantity - trade_quantity));
+                order.record_execution(order.price, trade_quantity);
+                taker.record_execution(order.price, trade_quantity);
+
+                trades.push(Trade::between(
+                    order,
+                    taker,
+                    trade_quantity,
+                    remaining_quantity,
+                ));
 
                 let new_index = (order.id, order.revision);
 
-                if let Some(order_key) = indexed.remove(&old_index) {
-                    indexed.insert(new_index, order_key);
-                }
+                indexed.remove(&old_index);
                 if order.quantity == zero_quantity {
-                    order_keys.pop_front();
+                    level.keys.pop_front();
+                    orders.remove(key);
+                } else {
+                    indexed.insert(new_index, key);
                 }
             }
+            level.compact(orders);
 
             remaining_quantity
         }
@@ -155,21 +444,25 @@ impl OrderBook {
             Side::Buy => {
                 let matching_side = &mut self.ask;
 
-                for (&price, order_keys) in matching_side {
+                for (&price, level) in matching_side {
                     if new_order.price < price {
                         break;
                     }
+                    if !admits(price) {
+                        stopped_at = Some(price);
+                        break;
+                    }
 
                     remaining_quantity = matching_loop(
-                        order_keys,
+                        level,
                         &mut self.orders,
                         &mut self.indexed,
                         remaining_quantity,
                         &mut trades,
-                        new_order.id,
+                        &mut new_order,
                     );
 
-                    if order_keys.is_empty() {
+                    if level.is_empty() {
                         prices_to_remove.push(price);
                     }
 
@@ -184,21 +477,25 @@ impl OrderBook {
             }
             Side::Sell => {
                 let matching_side = &mut self.bid;
-                for (&price, order_keys) in matching_side.iter_mut().rev() {
+                for (&price, level) in matching_side.iter_mut().rev() {
                     if new_order.price > price {
                         break;
                     }
+                    if !admits(price) {
+                        stopped_at = Some(price);
+                        break;
+                    }
 
                     remaining_quantity = matching_loop(
-                        order_keys,
+                        level,
                         &mut self.orders,
                         &mut self.indexed,
                         remaining_quantity,
                         &mut trades,
-                        new_order.id,
+                        &mut new_order,
                     );
 
-                    if order_keys.is_empty() {
+                    if level.is_empty() {
                         prices_to_remove.push(price);
                     }
 
@@ -214,18 +511,14 @@ impl OrderBook {
         }
 
         new_order.quantity = remaining_quantity;
-        if remaining_quantity > Quantity(0) {
+        if remaining_quantity > Quantity(0) && new_order.time_in_force != TimeInForce::Ioc {
             self.add_to_book(new_order);
         }
 
-        if trades.is_empty() {
-            None
-        } else {
-            Some(trades)
-        }
+        Sweep { trades, stopped_at }
     }
 
-    fn get_order<I, R>(&self, order_id: I, revision: R) -> Option<&Order>
+    pub fn get_order<I, R>(&self, order_id: I, revision: R) -> Option<&Order>
     where
         I: Into<OrderId>,
         R: Into<Revision>,
@@ -287,6 +580,10 @@ mod tests {
                 best_bid, real_best_bid,
                 "failed Best of Book for {real_best_bid:?} {real_best_ask:?}"
             );
+            assert_eq!(
+                best_ask, real_best_ask,
+                "failed Best of Book for {real_best_bid:?} {real_best_ask:?}"
+            );
         }
     }
 
@@ -299,7 +596,7 @@ mod tests {
                     OrderEntry::new(18, 4, Side::Sell),
                 ],
                 OrderEntry::new(21, 4, Side::Buy),
-                vec![Trade::new(18, 4, Uuid::new_v4(), Uuid::new_v4())],
+                vec![Trade::new(18, 4, Side::Buy, Uuid::new_v4(), Uuid::new_v4())],
                 1,
             ),
             (
@@ -309,8 +606,8 @@ mod tests {
                 ],
                 OrderEntry::new(21, 5, Side::Buy),
                 vec![
-                    Trade::new(18, 4, Uuid::new_v4(), Uuid::new_v4()),
-                    Trade::new(20, 1, Uuid::new_v4(), Uuid::new_v4()),
+                    Trade::new(18, 4, Side::Buy, Uuid::new_v4(), Uuid::new_v4()),
+                    Trade::new(20, 1, Side::Buy, Uuid::new_v4(), Uuid::new_v4()),
                 ],
                 1,
             ),
@@ -321,8 +618,8 @@ mod tests {
                 ],
                 OrderEntry::new(21, 5, Side::Buy),
                 vec![
-                    Trade::new(18, 4, Uuid::new_v4(), Uuid::new_v4()),
-                    Trade::new(18, 1, Uuid::new_v4(), Uuid::new_v4()),
+                    Trade::new(18, 4, Side::Buy, Uuid::new_v4(), Uuid::new_v4()),
+                    Trade::new(18, 1, Side::Buy, Uuid::new_v4(), Uuid::new_v4()),
                 ],
                 1,
             ),
@@ -375,7 +672,13 @@ mod tests {
                     OrderEntry::new(20, 6, Side::Buy),
                 ],
                 OrderEntry::new(17, 4, Side::Sell),
-                vec![Trade::new(20, 4, Uuid::new_v4(), Uuid::new_v4())],
+                vec![Trade::new(
+                    20,
+                    4,
+                    Side::Sell,
+                    Uuid::new_v4(),
+                    Uuid::new_v4(),
+                )],
                 2, // remaining buy price levels
             ),
             (
@@ -384,7 +687,13 @@ mod tests {
                     OrderEntry::new(20, 6, Side::Buy),
                 ],
                 OrderEntry::new(19, 5, Side::Sell),
-                vec![Trade::new(20, 5, Uuid::new_v4(), Uuid::new_v4())],
+                vec![Trade::new(
+                    20,
+                    5,
+                    Side::Sell,
+                    Uuid::new_v4(),
+                    Uuid::new_v4(),
+                )],
                 2,
             ),
             (
@@ -394,8 +703,8 @@ mod tests {
                 ],
                 OrderEntry::new(17, 10, Side::Sell),
                 vec![
-                    Trade::new(18, 4, Uuid::new_v4(), Uuid::new_v4()),
-                    Trade::new(18, 6, Uuid::new_v4(), Uuid::new_v4()),
+                    Trade::new(18, 4, Side::Sell, Uuid::new_v4(), Uuid::new_v4()),
+                    Trade::new(18, 6, Side::Sell, Uuid::new_v4(), Uuid::new_v4()),
                 ],
                 0,
             ),
@@ -453,4 +762,321 @@ mod tests {
         book.get_order(o_id, 1)
             .expect("Order has not updated revision!");
     }
+
+    #[test]
+    fn trades_report_leaves_and_cumulative_quantity_of_both_sides() {
+        let mut book = OrderBook::default();
+        book.add_to_book(OrderEntry::new(18, 4, Side::Sell));
+        book.add_to_book(OrderEntry::new(20, 6, Side::Sell));
+
+        let trades = book
+            .match_order(OrderEntry::new(21, 5, Side::Buy))
+            .expect("Expected some trades");
+
+        assert_eq!(trades[0].aggressor, Side::Buy);
+        assert_eq!(trades[0].maker.leaves_quantity, Quantity(0));
+        assert_eq!(trades[0].maker.cum_quantity, Quantity(4));
+        assert_eq!(trades[0].taker.leaves_quantity, Quantity(1));
+        assert_eq!(trades[1].maker.leaves_quantity, Quantity(5));
+        assert_eq!(trades[1].maker.revision, Revision(1));
+        assert_eq!(trades[1].taker.leaves_quantity, Quantity(0));
+        assert_eq!(trades[1].taker.cum_quantity, Quantity(5));
+        // (18 * 4 + 20 * 1) / 5
+        assert_eq!(trades[1].taker.avg_price, Price(18));
+    }
+
+    fn queue(book: &OrderBook, price: i64) -> Vec<OrderId> {
+        book.ask[&Price(price)]
+            .orders(&book.orders)
+            .map(|o| o.id)
+            .collect()
+    }
+
+    #[test]
+    fn quantity_reduction_keeps_queue_position() {
+        let mut book = OrderBook::default();
+        let first = book.add_to_book(OrderEntry::new(20, 6, Side::Sell));
+        let second = book.add_to_book(OrderEntry::new(20, 4, Side::Sell));
+
+        let modification = book
+            .modify_order(first, Revision(0), None, Some(Quantity(2)), true)
+            .expect("Order should exist");
+
+        assert!(!modification.priority_lost);
+        assert_eq!(modification.order.revision, Revision(1));
+        assert_eq!(queue(&book, 20), vec![first, second]);
+        assert_eq!(
+            book.get_order(first, Revision(1)).map(|o| o.quantity),
+            Some(Quantity(2))
+        );
+        assert!(book.get_order(first, Revision(0)).is_none());
+    }
+
+    #[test]
+    fn restating_price_and_quantity_keeps_queue_position() {
+        let mut book = OrderBook::default();
+        let first = book.add_to_book(OrderEntry::new(20, 6, Side::Sell));
+        let second = book.add_to_book(OrderEntry::new(20, 4, Side::Sell));
+
+        let modification = book
+            .modify_order(first, Revision(0), Some(Price(20)), Some(Quantity(6)), true)
+            .expect("Order should exist");
+
+        assert!(!modification.priority_lost);
+        assert_eq!(modification.order.revision, Revision(1));
+        assert_eq!(queue(&book, 20), vec![first, second]);
+    }
+
+    #[test]
+    fn price_change_or_size_increase_loses_priority() {
+        let mut book = OrderBook::default();
+        let first = book.add_to_book(OrderEntry::new(20, 6, Side::Sell));
+        let second = book.add_to_book(OrderEntry::new(20, 4, Side::Sell));
+
+        let modification = book
+            .modify_order(first, Revision(0), None, Some(Quantity(8)), true)
+            .expect("Order should exist");
+
+        assert!(modification.priority_lost);
+        assert_eq!(queue(&book, 20), vec![second, first]);
+
+        let modification = book
+            .modify_order(
+                second,
+                Revision(0),
+                Some(Price(21)),
+                Some(Quantity(1)),
+                true,
+            )
+            .expect("Order should exist");
+
+        assert!(modification.priority_lost);
+        assert_eq!(queue(&book, 20), vec![first]);
+        assert_eq!(queue(&book, 21), vec![second]);
+    }
+
+    #[test]
+    fn matching_skips_deleted_orders() {
+        let mut book = OrderBook::default();
+        let first = book.add_to_book(OrderEntry::new(20, 6, Side::Sell));
+        let second = book.add_to_book(OrderEntry::new(20, 4, Side::Sell));
+        let third = book.add_to_book(OrderEntry::new(20, 5, Side::Sell));
+
+        book.delete_order(&(second, Revision(0)))
+            .expect("Order should exist");
+
+        let trades = book
+            .match_order(OrderEntry::new(20, 8, Side::Buy))
+            .expect("Expected some trades");
+
+        assert_eq!(trades.len(), 2);
+        assert_eq!(trades[0].maker_id(), first);
+        assert_eq!(trades[1].maker_id(), third);
+        assert_eq!(trades[1].quantity, Quantity(2));
+    }
+
+    #[test]
+    fn snapshot_lists_orders_by_priority_with_queue_positions() {
+        let mut book = OrderBook::default();
+        let bid = book.add_to_book(OrderEntry::new(18, 3, Side::Buy));
+        let better_bid = book.add_to_book(OrderEntry::new(19, 2, Side::Buy));
+        let first = book.add_to_book(OrderEntry::new(20, 6, Side::Sell));
+        let deleted = book.add_to_book(OrderEntry::new(20, 4, Side::Sell));
+        let third = book.add_to_book(OrderEntry::new(20, 5, Side::Sell));
+        book.delete_order(&(deleted, Revision(0)))
+            .expect("Order should exist");
+
+        let snapshot = book.snapshot();
+
+        let positions = |orders: &[QueuedOrder]| {
+            orders
+                .iter()
+                .map(|o| (o.id, o.price.0, o.position))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            positions(&snapshot.bids),
+            vec![(better_bid, 19, 0), (bid, 18, 0)]
+        );
+        assert_eq!(
+            positions(&snapshot.asks),
+            vec![(first, 20, 0), (third, 20, 1)]
+        );
+        assert_eq!(snapshot.asks[1].quantity, Quantity(5));
+    }
+
+    #[test]
+    fn filled_orders_leave_the_book() {
+        let mut book = OrderBook::default();
+        let maker = book.add_to_book(OrderEntry::new(20, 6, Side::Sell));
+
+        book.match_order(OrderEntry::new(20, 6, Side::Buy));
+
+        assert!(book.orders.is_empty());
+        assert!(book.indexed.is_empty());
+        assert!(book.ask.is_empty());
+        assert!(book.get_order(maker, Revision(1)).is_none());
+    }
+
+    #[test]
+    fn deleting_every_order_prunes_the_level() {
+        let mut book = OrderBook::default();
+        let ids: Vec<OrderId> = (0..10)
+            .map(|_| book.add_to_book(OrderEntry::new(20, 1, Side::Buy)))
+            .collect();
+
+        for id in &ids[1..] {
+            book.delete_order(&(*id, Revision(0)))
+                .expect("Order should exist");
+        }
+
+        let level = &book.bid[&Price(20)];
+        assert_eq!(level.keys.len(), 1, "Tombstones should be compacted");
+
+        book.delete_order(&(ids[0], Revision(0)))
+            .expect("Order should exist");
+
+        assert!(book.bid.is_empty());
+        assert!(book.orders.is_empty());
+    }
+
+    #[test]
+    fn cancel_orders_removes_only_matching_orders() {
+        let mut book = OrderBook::default();
+
+        book.add_to_book(OrderEntry::new(100, 5, Side::Sell).with_account(1));
+        book.add_to_book(OrderEntry::new(100, 3, Side::Sell).with_account(2));
+        book.add_to_book(OrderEntry::new(90, 4, Side::Buy).with_account(1));
+
+        let cancelled = book.cancel_orders(&OrderFilter::account(1));
+
+        assert_eq!(cancelled.len(), 2);
+        assert!(book.bid.is_empty(), "Empty price levels should be removed");
+
+        let trades = book
+            .match_order(OrderEntry::new(100, 5, Side::Buy))
+            .expect("Expected some trades");
+
+        assert_eq!(trades.len(), 1);
+        assert_eq!(trades[0].quantity, Quantity(3));
+    }
+
+    #[test]
+    fn cancel_orders_respects_side_and_price_range() {
+        let mut book = OrderBook::default();
+
+        book.add_to_book(OrderEntry::new(90, 1, Side::Buy));
+        book.add_to_book(OrderEntry::new(95, 1, Side::Buy));
+        book.add_to_book(OrderEntry::new(99, 1, Side::Buy));
+        book.add_to_book(OrderEntry::new(101, 1, Side::Sell));
+
+        let filter = OrderFilter {
+            side: Some(Side::Buy),
+            min_price: Some(Price(95)),
+            max_price: Some(Price(100)),
+            ..Default::default()
+        };
+        let mut cancelled: Vec<Price> = book
+            .cancel_orders(&filter)
+            .into_iter()
+            .map(|o| o.price)
+            .collect();
+        cancelled.sort();
+
+        assert_eq!(cancelled, vec![Price(95), Price(99)]);
+        assert_eq!(book.bid.len(), 1);
+        assert_eq!(book.ask.len(), 1);
+    }
+
+    #[test]
+    fn uncrossing_trades_the_best_orders_at_the_equilibrium_price() {
+        let mut book = OrderBook::default();
+        let first_bid = book.add_to_book(OrderEntry::new(105, 4, Side::Buy));
+        let second_bid = book.add_to_book(OrderEntry::new(103, 6, Side::Buy));
+        book.add_to_book(OrderEntry::new(100, 5, Side::Buy));
+        let first_ask = book.add_to_book(OrderEntry::new(99, 3, Side::Sell));
+        let second_ask = book.add_to_book(OrderEntry::new(103, 6, Side::Sell));
+        book.add_to_book(OrderEntry::new(104, 2, Side::Sell));
+
+        let trades = book.uncross(None).expect("Book is crossed");
+
+        let executions: Vec<_> = trades
+            .iter()
+            .map(|t| (t.taker_id(), t.maker_id(), t.price.0, t.quantity.0))
+            .collect();
+        assert_eq!(
+            executions,
+            [
+                (first_bid, first_ask, 103, 3),
+                (first_bid, second_ask, 103, 1),
+                (second_bid, second_ask, 103, 5),
+            ]
+        );
+        assert_eq!(trades[2].taker.leaves_quantity, Quantity(1));
+        assert_eq!(trades[2].maker.leaves_quantity, Quantity(0));
+        assert_eq!(
+            book.best_of_book(),
+            (
+                Some(OrderBookLevel::new(103, 1)),
+                Some(OrderBookLevel::new(104, 2))
+            )
+        );
+        assert!(book.get_order(second_bid, Revision(1)).is_some());
+        assert!(book.uncross(None).is_none());
+    }
+
+    #[test]
+    fn modifications_can_rest_without_matching() {
+        let mut book = OrderBook::default();
+        book.add_to_book(OrderEntry::new(20, 6, Side::Sell));
+        let bid = book.add_to_book(OrderEntry::new(18, 4, Side::Buy));
+
+        let modification = book
+            .modify_order(bid, Revision(0), Some(Price(21)), None, false)
+            .expect("Order should exist");
+
+        assert!(modification.trades.is_none());
+        assert_eq!(
+            book.best_of_book(),
+            (
+                Some(OrderBookLevel::new(21, 4)),
+                Some(OrderBookLevel::new(20, 6))
+            )
+        );
+    }
+
+    #[test]
+    fn ioc_orders_never_rest() {
+        let mut book = OrderBook::default();
+        book.add_to_book(OrderEntry::new(20, 4, Side::Sell));
+
+        let trades = book
+            .match_order(OrderEntry::new(21, 10, Side::Buy).with_time_in_force(TimeInForce::Ioc))
+            .expect("Orders cross");
+
+        assert_eq!(trades[0].taker.leaves_quantity, Quantity(6));
+        assert_eq!(book.best_of_book(), (None, None));
+    }
+
+    #[test]
+    fn sweeps_stop_before_the_first_refused_level() {
+        let mut book = OrderBook::default();
+        book.add_to_book(OrderEntry::new(100, 5, Side::Sell));
+        book.add_to_book(OrderEntry::new(110, 5, Side::Sell));
+        book.add_to_book(OrderEntry::new(120, 5, Side::Sell));
+
+        let sweep =
+            book.match_order_within(OrderEntry::new(120, 12, Side::Buy), &mut |p| p < Price(110));
+
+        assert_eq!(sweep.trades.len(), 1);
+        assert_eq!(sweep.stopped_at, Some(Price(110)));
+        // the rest of the order rests crossed with the refused levels
+        assert_eq!(
+            book.best_of_book(),
+            (
+                Some(OrderBookLevel::new(120, 7)),
+                Some(OrderBookLevel::new(110, 5))
+            )
+        );
+    }
 }
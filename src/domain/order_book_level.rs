@@ -1,6 +1,7 @@
-use crate::domain::order::{Price, Quantity};
+use crate::domain::order::{OrderId, Price, Quantity, Revision};
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, PartialOrd, PartialEq)]
+#[derive(Deserialize, Serialize, Debug, PartialOrd, PartialEq, Clone)]
 pub struct OrderBookLevel {
     pub(crate) price: Price,
     pub(crate) quantity: Quantity,
@@ -18,3 +19,23 @@ impl OrderBookLevel {
         }
     }
 }
+
+/// A resting order and the number of orders ahead of it at its price.
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
+pub struct QueuedOrder {
+    pub id: OrderId,
+    pub revision: Revision,
+    pub price: Price,
+    pub quantity: Quantity,
+    pub position: usize,
+}
+
+/// Every resting order of a book, best price first and in time priority
+/// within a price.
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone, Default)]
+pub struct OrderBookSnapshot {
+    /// Sequence number of the latest event reflected in the snapshot.
+    pub sequence: u64,
+    pub bids: Vec<QueuedOrder>,
+    pub asks: Vec<QueuedOrder>,
+}
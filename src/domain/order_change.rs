@@ -0,0 +1,110 @@
+use crate::domain::account::AccountId;
+use crate::domain::order::{Order, OrderId, Price, Quantity, Revision};
+use crate::domain::side::Side;
+use crate::domain::symbol::Symbol;
+use crate::domain::trade::{Trade, TradeParty};
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize, Serialize, Debug, Eq, PartialEq, Copy, Clone)]
+pub enum ModifyReason {
+    UserModify,
+    PartialFill,
+    System,
+}
+
+#[derive(Deserialize, Serialize, Debug, Eq, PartialEq, Copy, Clone)]
+pub enum CancelReason {
+    UserCancel,
+    MassCancel,
+    CancelOnDisconnect,
+    /// The time in force of the order ran out.
+    Expired,
+    /// What an IOC order did not fill on entry.
+    Unfilled,
+    System,
+}
+
+/// State of a resting order before and after it was changed.
+#[derive(Clone, Deserialize, Serialize, Debug)]
+pub struct OrderChange {
+    pub id: OrderId,
+    pub symbol: Symbol,
+    /// Kept off the public feed, only the engine and gateways see it.
+    #[serde(skip)]
+    pub account: AccountId,
+    pub side: Side,
+    pub old_revision: Revision,
+    pub new_revision: Revision,
+    pub old_price: Price,
+    pub new_price: Price,
+    pub old_quantity: Quantity,
+    pub new_quantity: Quantity,
+    /// `false` when the order kept its place in the queue.
+    pub priority_lost: bool,
+    pub reason: ModifyReason,
+}
+
+impl OrderChange {
+    pub fn new(before: &Order, after: &Order, priority_lost: bool, reason: ModifyReason) -> Self {
+        OrderChange {
+            id: after.id,
+            symbol: after.symbol,
+            account: after.account,
+            side: after.side,
+            old_revision: before.revision,
+            new_revision: after.revision,
+            old_price: before.price,
+            new_price: after.price,
+            old_quantity: before.quantity,
+            new_quantity: after.quantity,
+            priority_lost,
+            reason,
+        }
+    }
+
+    /// Change of the resting order in `trade`, or `None` if it was filled
+    /// completely and left the book.
+    pub fn partial_fill(trade: &Trade, symbol: Symbol) -> Option<Self> {
+        let side = trade.aggressor.opposite();
+        Self::fill(trade, symbol, trade.maker_id(), side, &trade.maker)
+    }
+
+    /// Like `partial_fill` for the taker, which only rested before the trade
+    /// when it was crossed in an auction.
+    pub fn taker_partial_fill(trade: &Trade, symbol: Symbol) -> Option<Self> {
+        Self::fill(
+            trade,
+            symbol,
+            trade.taker_id(),
+            trade.aggressor,
+            &trade.taker,
+        )
+    }
+
+    fn fill(
+        trade: &Trade,
+        symbol: Symbol,
+        id: OrderId,
+        side: Side,
+        party: &TradeParty,
+    ) -> Option<Self> {
+        if party.leaves_quantity == Quantity(0) {
+            return None;
+        }
+
+        Some(OrderChange {
+            id,
+            symbol,
+            account: party.account,
+            side,
+            old_revision: Revision(party.revision.0 - 1),
+            new_revision: party.revision,
+            old_price: party.price,
+            new_price: party.price,
+            old_quantity: Quantity(party.leaves_quantity.0 + trade.quantity.0),
+            new_quantity: party.leaves_quantity,
+            priority_lost: false,
+            reason: ModifyReason::PartialFill,
+        })
+    }
+}
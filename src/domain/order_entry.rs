@@ -1,5 +1,7 @@
-use crate::domain::order::{Price, Quantity};
+use crate::domain::account::AccountId;
+use crate::domain::order::{Price, Quantity, TimeInForce};
 use crate::domain::side::Side;
+use crate::domain::symbol::Symbol;
 use serde::Deserialize;
 
 #[derive(Deserialize, Debug, Clone)]
@@ -7,6 +9,12 @@ pub struct OrderEntry {
     pub price: Price,
     pub quantity: Quantity,
     pub side: Side,
+    #[serde(default)]
+    pub symbol: Symbol,
+    #[serde(default)]
+    pub account: AccountId,
+    #[serde(default)]
+    pub time_in_force: TimeInForce,
 }
 
 impl OrderEntry {
@@ -19,6 +27,24 @@ impl OrderEntry {
             price: price.into(),
             quantity: quantity.into(),
             side,
+            symbol: Symbol::default(),
+            account: AccountId::default(),
+            time_in_force: TimeInForce::default(),
         }
     }
+
+    pub fn with_symbol(mut self, symbol: Symbol) -> Self {
+        self.symbol = symbol;
+        self
+    }
+
+    pub fn with_account<A: Into<AccountId>>(mut self, account: A) -> Self {
+        self.account = account.into();
+        self
+    }
+
+    pub fn with_time_in_force(mut self, time_in_force: TimeInForce) -> Self {
+        self.time_in_force = time_in_force;
+        self
+    }
 }
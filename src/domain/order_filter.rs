@@ -0,0 +1,35 @@
+use crate::domain::account::AccountId;
+use crate::domain::order::{Order, Price, TimeInForce};
+use crate::domain::side::Side;
+use crate::domain::symbol::Symbol;
+use serde::Deserialize;
+
+/// Selects resting orders for a mass cancel. Every criterion left empty
+/// matches all orders; price bounds are inclusive.
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct OrderFilter {
+    pub account: Option<AccountId>,
+    pub side: Option<Side>,
+    pub symbol: Option<Symbol>,
+    pub min_price: Option<Price>,
+    pub max_price: Option<Price>,
+    pub time_in_force: Option<TimeInForce>,
+}
+
+impl OrderFilter {
+    pub fn account<A: Into<AccountId>>(account: A) -> Self {
+        OrderFilter {
+            account: Some(account.into()),
+            ..Default::default()
+        }
+    }
+
+    pub fn matches(&self, order: &Order) -> bool {
+        self.account.is_none_or(|a| a == order.account)
+            && self.side.is_none_or(|s| s == order.side)
+            && self.symbol.is_none_or(|s| s == order.symbol)
+            && self.min_price.is_none_or(|p| p <= order.price)
+            && self.max_price.is_none_or(|p| p >= order.price)
+            && self.time_in_force.is_none_or(|t| t == order.time_in_force)
+    }
+}
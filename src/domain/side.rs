@@ -1,12 +1,21 @@
 use rand::Rng;
 use rand::distr::{Distribution, StandardUniform};
 use serde::{Deserialize, Serialize};
-#[derive(Deserialize, Serialize, Debug, Copy, Clone)]
+#[derive(Deserialize, Serialize, Debug, Eq, PartialEq, Copy, Clone, Hash)]
 pub enum Side {
     Buy,
     Sell,
 }
 
+impl Side {
+    pub fn opposite(&self) -> Side {
+        match self {
+            Side::Buy => Side::Sell,
+            Side::Sell => Side::Buy,
+        }
+    }
+}
+
 impl Distribution<Side> for StandardUniform {
     fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Side {
         match rng.random_range(0..=2) {
@@ -0,0 +1,88 @@
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// Instrument symbol, stored inline as up to 8 space-padded ASCII characters
+/// so it stays `Copy` and maps directly onto fixed-width wire formats.
+#[derive(Deserialize, Serialize, Debug, Eq, PartialOrd, PartialEq, Ord, Copy, Clone, Hash)]
+#[serde(try_from = "String", into = "String")]
+pub struct Symbol([u8; Symbol::LEN]);
+
+#[derive(Debug)]
+pub struct InvalidSymbol(pub String);
+
+impl Display for InvalidSymbol {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Invalid symbol {:?}: expected 1 to {} ASCII letters, digits or dots",
+            self.0,
+            Symbol::LEN
+        )
+    }
+}
+
+impl Symbol {
+    pub const LEN: usize = 8;
+
+    pub fn as_bytes(&self) -> &[u8; Symbol::LEN] {
+        &self.0
+    }
+
+    pub fn as_str(&self) -> &str {
+        std::str::from_utf8(&self.0)
+            .expect("Symbol is always ASCII")
+            .trim_end()
+    }
+}
+
+impl Default for Symbol {
+    fn default() -> Self {
+        Symbol(*b"DEFAULT ")
+    }
+}
+
+impl FromStr for Symbol {
+    type Err = InvalidSymbol;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let valid = (1..=Symbol::LEN).contains(&s.len())
+            && s.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'.');
+        if !valid {
+            return Err(InvalidSymbol(s.to_string()));
+        }
+
+        let mut bytes = [b' '; Symbol::LEN];
+        bytes[..s.len()].copy_from_slice(s.as_bytes());
+        Ok(Symbol(bytes))
+    }
+}
+
+impl TryFrom<&[u8]> for Symbol {
+    type Error = InvalidSymbol;
+
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        let s = String::from_utf8_lossy(value);
+        s.trim_end().parse()
+    }
+}
+
+impl TryFrom<String> for Symbol {
+    type Error = InvalidSymbol;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<Symbol> for String {
+    fn from(value: Symbol) -> Self {
+        value.as_str().to_string()
+    }
+}
+
+impl Display for Symbol {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
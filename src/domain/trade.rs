@@ -1,14 +1,67 @@
-use crate::domain::order::{OrderId, Price, Quantity};
+use crate::domain::account::AccountId;
+use crate::domain::order::{Order, OrderId, PRICE_SCALE, Price, Quantity, Revision};
+use crate::domain::side::Side;
+use crate::domain::symbol::Symbol;
 use serde::{Deserialize, Serialize};
 use std::time::{SystemTime, UNIX_EPOCH};
 
+/// Engine-wide, strictly increasing trade identifier.
+#[derive(
+    Deserialize, Serialize, Debug, Eq, PartialOrd, PartialEq, Ord, Copy, Clone, Default, Hash,
+)]
+pub struct TradeId(pub u64);
+
+impl TradeId {
+    pub fn increment(&mut self) -> TradeId {
+        self.0 += 1;
+        *self
+    }
+}
+
 #[derive(Clone, Deserialize, Serialize, Debug)]
 pub struct Trade {
+    pub id: TradeId,
+    #[serde(default)]
+    pub symbol: Symbol,
     pub price: Price,
     pub quantity: Quantity,
+    pub aggressor: Side,
     maker_id: OrderId,
     taker_id: OrderId,
     exec_time: i64,
+    /// Private details of each side, never part of the public trade feed.
+    #[serde(skip)]
+    pub maker: TradeParty,
+    #[serde(skip)]
+    pub taker: TradeParty,
+}
+
+/// State of one order right after the fill.
+#[derive(Clone, Deserialize, Serialize, Debug, Default, PartialEq)]
+pub struct TradeParty {
+    pub account: AccountId,
+    /// Fee charged to the account, negative for rebates.
+    pub fee: i64,
+    pub revision: Revision,
+    /// Limit price of the order.
+    pub price: Price,
+    pub leaves_quantity: Quantity,
+    pub cum_quantity: Quantity,
+    pub avg_price: Price,
+}
+
+impl TradeParty {
+    fn of(order: &Order, leaves_quantity: Quantity) -> Self {
+        TradeParty {
+            account: order.account,
+            fee: 0,
+            revision: order.revision,
+            price: order.price,
+            leaves_quantity,
+            cum_quantity: order.executed,
+            avg_price: order.avg_price(),
+        }
+    }
 }
 
 pub fn now_unix_ns() -> i64 {
@@ -19,7 +72,7 @@ pub fn now_unix_ns() -> i64 {
     since_unix.as_nanos() as i64
 }
 impl Trade {
-    pub fn new<P, Q, I>(price: P, quantity: Q, maker_id: I, taker_id: I) -> Self
+    pub fn new<P, Q, I>(price: P, quantity: Q, aggressor: Side, maker_id: I, taker_id: I) -> Self
     where
         P: Into<Price>,
         Q: Into<Quantity>,
@@ -28,11 +81,71 @@ impl Trade {
         let exec_time = now_unix_ns();
 
         Trade {
+            id: TradeId::default(),
+            symbol: Symbol::default(),
             price: price.into(),
             quantity: quantity.into(),
+            aggressor,
             maker_id: maker_id.into(),
             taker_id: taker_id.into(),
             exec_time,
+            maker: TradeParty::default(),
+            taker: TradeParty::default(),
         }
     }
+
+    /// Builds the trade from the maker and taker right after the fill was
+    /// recorded on both of them.
+    pub fn between(
+        maker: &Order,
+        taker: &Order,
+        quantity: Quantity,
+        taker_leaves: Quantity,
+    ) -> Self {
+        let mut trade = Trade::new(maker.price, quantity, taker.side, maker.id, taker.id);
+        trade.symbol = maker.symbol;
+        trade.maker = TradeParty::of(maker, maker.quantity);
+        trade.taker = TradeParty::of(taker, taker_leaves);
+        trade
+    }
+
+    /// Builds a trade of an auction, where both orders rested in the book
+    /// and trade at the equilibrium `price`. The buy order is reported as the
+    /// taker.
+    pub fn crossed(price: Price, buy: &Order, sell: &Order, quantity: Quantity) -> Self {
+        let mut trade = Trade::new(price, quantity, Side::Buy, sell.id, buy.id);
+        trade.symbol = sell.symbol;
+        trade.maker = TradeParty::of(sell, sell.quantity);
+        trade.taker = TradeParty::of(buy, buy.quantity);
+        trade
+    }
+
+    /// Stamps the trade with the time of the engine clock.
+    pub fn with_exec_time(mut self, exec_time: i64) -> Self {
+        self.exec_time = exec_time;
+        self
+    }
+
+    pub fn with_accounts(mut self, maker: AccountId, taker: AccountId) -> Self {
+        self.maker.account = maker;
+        self.taker.account = taker;
+        self
+    }
+
+    pub fn maker_id(&self) -> OrderId {
+        self.maker_id
+    }
+
+    pub fn taker_id(&self) -> OrderId {
+        self.taker_id
+    }
+
+    /// Traded value, see `PRICE_SCALE`.
+    pub fn notional(&self) -> i64 {
+        (self.price.0 as i128 * self.quantity.0 as i128 / PRICE_SCALE as i128) as i64
+    }
+
+    pub fn exec_time(&self) -> i64 {
+        self.exec_time
+    }
 }
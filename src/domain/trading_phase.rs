@@ -0,0 +1,58 @@
+use crate::domain::order::TimeInForce;
+use crate::domain::trading_state::TradingState;
+use serde::{Deserialize, Serialize};
+
+/// Phase of the trading day of an instrument with a schedule.
+#[derive(Deserialize, Serialize, Debug, Eq, PartialEq, Copy, Clone, Hash)]
+pub enum TradingPhase {
+    /// Orders are collected for the opening auction.
+    PreOpen,
+    /// Last part of the call, orders are still collected.
+    OpeningAuction,
+    Continuous,
+    /// Orders are collected for the closing auction.
+    ClosingAuction,
+    /// Only orders for the next day are accepted.
+    PostClose,
+    Closed,
+}
+
+impl TradingPhase {
+    pub fn trading_state(&self) -> TradingState {
+        match self {
+            TradingPhase::Continuous => TradingState::Open,
+            TradingPhase::Closed => TradingState::Closed,
+            TradingPhase::PreOpen
+            | TradingPhase::OpeningAuction
+            | TradingPhase::ClosingAuction
+            | TradingPhase::PostClose => TradingState::Auction,
+        }
+    }
+
+    /// Whether the book collected in the phase is uncrossed when it ends.
+    pub fn collects(&self) -> bool {
+        matches!(
+            self,
+            TradingPhase::PreOpen | TradingPhase::OpeningAuction | TradingPhase::ClosingAuction
+        )
+    }
+
+    /// Whether DAY orders expire when the phase starts.
+    pub fn ends_day(&self) -> bool {
+        matches!(self, TradingPhase::PostClose | TradingPhase::Closed)
+    }
+
+    /// Whether new orders with `time_in_force` are accepted. Nothing trades
+    /// on entry while orders are collected, and DAY orders entered after the
+    /// close would only expire.
+    pub fn accepts(&self, time_in_force: TimeInForce) -> bool {
+        match self {
+            TradingPhase::Continuous => true,
+            TradingPhase::PreOpen | TradingPhase::OpeningAuction | TradingPhase::ClosingAuction => {
+                time_in_force != TimeInForce::Ioc
+            }
+            TradingPhase::PostClose => time_in_force == TimeInForce::Gtc,
+            TradingPhase::Closed => false,
+        }
+    }
+}
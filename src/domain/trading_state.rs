@@ -0,0 +1,21 @@
+use serde::{Deserialize, Serialize};
+
+/// Trading state of an instrument or of the whole engine. States are ordered
+/// by severity, so the effective state of an instrument is the more
+/// restrictive of its own and the engine-wide one.
+#[derive(Deserialize, Serialize, Debug, Eq, PartialEq, Ord, PartialOrd, Copy, Clone, Default)]
+pub enum TradingState {
+    #[default]
+    Open,
+    /// Orders are collected without matching. The book is uncrossed once
+    /// the instrument opens.
+    Auction,
+    Halted,
+    Closed,
+}
+
+impl TradingState {
+    pub fn accepts_orders(&self) -> bool {
+        matches!(self, TradingState::Open | TradingState::Auction)
+    }
+}
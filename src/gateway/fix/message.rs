@@ -0,0 +1,418 @@
+//! FIX tag=value encoding. Messages are framed by BeginString (8) and
+//! BodyLength (9) and end with a CheckSum (10) over all preceding bytes.
+
+use crate::domain::order::{PRICE_SCALE, Price};
+use std::fmt::Display;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt};
+
+pub const BEGIN_STRING: &str = "FIX.4.4";
+pub const SOH: u8 = 0x01;
+
+/// Upper bound of the BodyLength accepted from counterparties.
+const MAX_BODY_LEN: usize = 64 * 1024;
+/// `10=NNN<SOH>`
+const TRAILER_LEN: usize = 7;
+/// Decimals of a price, `PRICE_SCALE` is a power of ten.
+const PRICE_DECIMALS: usize = PRICE_SCALE.ilog10() as usize;
+
+pub mod tag {
+    pub const ACCOUNT: u32 = 1;
+    pub const AVG_PX: u32 = 6;
+    pub const BEGIN_SEQ_NO: u32 = 7;
+    pub const BEGIN_STRING: u32 = 8;
+    pub const BODY_LENGTH: u32 = 9;
+    pub const CHECK_SUM: u32 = 10;
+    pub const CL_ORD_ID: u32 = 11;
+    pub const CUM_QTY: u32 = 14;
+    pub const ECHO_SEQ_NO_END: u32 = 16;
+    pub const EXEC_ID: u32 = 17;
+    pub const LAST_PX: u32 = 31;
+    pub const LAST_QTY: u32 = 32;
+    pub const MSG_SEQ_NUM: u32 = 34;
+    pub const MSG_TYPE: u32 = 35;
+    pub const NEW_SEQ_NO: u32 = 36;
+    pub const ORDER_ID: u32 = 37;
+    pub const ORDER_QTY: u32 = 38;
+    pub const ORD_STATUS: u32 = 39;
+    pub const ORD_TYPE: u32 = 40;
+    pub const ORIG_CL_ORD_ID: u32 = 41;
+    pub const POSS_DUP_FLAG: u32 = 43;
+    pub const PRICE: u32 = 44;
+    pub const REF_SEQ_NUM: u32 = 45;
+    pub const SENDER_COMP_ID: u32 = 49;
+    pub const SENDING_TIME: u32 = 52;
+    pub const SIDE: u32 = 54;
+    pub const SYMBOL: u32 = 55;
+    pub const TARGET_COMP_ID: u32 = 56;
+    pub const TEXT: u32 = 58;
+    pub const TIME_IN_FORCE: u32 = 59;
+    pub const TRANSACT_TIME: u32 = 60;
+    pub const ENCRYPT_METHOD: u32 = 98;
+    pub const CXL_REJ_REASON: u32 = 102;
+    pub const ORD_REJ_REASON: u32 = 103;
+    pub const HEART_BT_INT: u32 = 108;
+    pub const TEST_REQ_ID: u32 = 112;
+    pub const ORIG_SENDING_TIME: u32 = 122;
+    pub const GAP_FILL_FLAG: u32 = 123;
+    pub const RESET_SEQ_NUM_FLAG: u32 = 141;
+    pub const EXEC_TYPE: u32 = 150;
+    pub const LEAVES_QTY: u32 = 151;
+    pub const SESSION_REJECT_REASON: u32 = 373;
+    pub const CXL_REJ_RESPONSE_TO: u32 = 434;
+    pub const PASSWORD: u32 = 554;
+}
+
+pub mod msg_type {
+    pub const HEARTBEAT: &str = "0";
+    pub const TEST_REQUEST: &str = "1";
+    pub const RESEND_REQUEST: &str = "2";
+    pub const REJECT: &str = "3";
+    pub const SEQUENCE_RESET: &str = "4";
+    pub const LOGOUT: &str = "5";
+    pub const EXECUTION_REPORT: &str = "8";
+    pub const ORDER_CANCEL_REJECT: &str = "9";
+    pub const LOGON: &str = "A";
+    pub const NEW_ORDER_SINGLE: &str = "D";
+    pub const ORDER_CANCEL_REQUEST: &str = "F";
+    pub const ORDER_CANCEL_REPLACE_REQUEST: &str = "G";
+}
+
+/// A message without its framing fields, which `encode` adds.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FixMessage {
+    msg_type: String,
+    fields: Vec<(u32, String)>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum DecodeError {
+    Malformed,
+    BodyLength,
+    CheckSum,
+}
+
+impl FixMessage {
+    pub fn new(msg_type: &str) -> Self {
+        FixMessage {
+            msg_type: msg_type.to_string(),
+            fields: Vec::new(),
+        }
+    }
+
+    pub fn with(mut self, tag: u32, value: impl Display) -> Self {
+        self.fields.push((tag, value.to_string()));
+        self
+    }
+
+    /// Inserts header fields in front of the body.
+    pub fn with_header(mut self, header: Vec<(u32, String)>) -> Self {
+        self.fields.splice(0..0, header);
+        self
+    }
+
+    /// The sent message as resent after a ResendRequest: flagged as a
+    /// possible duplicate, with its SendingTime moved to OrigSendingTime.
+    pub fn possible_duplicate(mut self, sending_time: String) -> Self {
+        if let Some(position) = self
+            .fields
+            .iter()
+            .position(|(t, _)| *t == tag::SENDING_TIME)
+        {
+            let original = std::mem::replace(&mut self.fields[position].1, sending_time);
+            self.fields.splice(
+                position + 1..position + 1,
+                [
+                    (tag::POSS_DUP_FLAG, "Y".to_string()),
+                    (tag::ORIG_SENDING_TIME, original),
+                ],
+            );
+        }
+        self
+    }
+
+    pub fn msg_type(&self) -> &str {
+        &self.msg_type
+    }
+
+    /// First value of the tag.
+    pub fn get(&self, tag: u32) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(t, _)| *t == tag)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn parse<T: std::str::FromStr>(&self, tag: u32) -> Option<T> {
+        self.get(tag)?.parse().ok()
+    }
+
+    pub fn flag(&self, tag: u32) -> bool {
+        self.get(tag) == Some("Y")
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut body = Vec::new();
+        push_field(&mut body, tag::MSG_TYPE, &self.msg_type);
+        for (tag, value) in &self.fields {
+            push_field(&mut body, *tag, value);
+        }
+
+        let mut out = Vec::with_capacity(body.len() + 32);
+        push_field(&mut out, tag::BEGIN_STRING, BEGIN_STRING);
+        push_field(&mut out, tag::BODY_LENGTH, &body.len().to_string());
+        out.extend_from_slice(&body);
+        let checksum = format!("{:03}", checksum(&out));
+        push_field(&mut out, tag::CHECK_SUM, &checksum);
+        out
+    }
+
+    /// Decodes a complete message as returned by `read_message`, checking
+    /// BodyLength and CheckSum.
+    pub fn decode(raw: &[u8]) -> Result<Self, DecodeError> {
+        if raw.len() < TRAILER_LEN || raw.last() != Some(&SOH) {
+            return Err(DecodeError::Malformed);
+        }
+        let (framed, trailer) = raw.split_at(raw.len() - TRAILER_LEN);
+        let expected = trailer
+            .strip_prefix(b"10=")
+            .and_then(|sum| std::str::from_utf8(&sum[..3]).ok())
+            .and_then(|sum| sum.parse::<u8>().ok())
+            .ok_or(DecodeError::Malformed)?;
+        if checksum(framed) != expected {
+            return Err(DecodeError::CheckSum);
+        }
+
+        let mut fields = framed[..framed.len() - 1]
+            .split(|&b| b == SOH)
+            .map(parse_field);
+        let (begin, body_length) = match (fields.next(), fields.next()) {
+            (Some(Ok((tag::BEGIN_STRING, begin))), Some(Ok((tag::BODY_LENGTH, len)))) => {
+                (begin, len)
+            }
+            _ => return Err(DecodeError::Malformed),
+        };
+        if begin != BEGIN_STRING {
+            return Err(DecodeError::Malformed);
+        }
+        let header_len = begin.len() + body_length.len() + 6;
+        if body_length.parse() != Ok(framed.len() - header_len) {
+            return Err(DecodeError::BodyLength);
+        }
+
+        let msg_type = match fields.next() {
+            Some(Ok((tag::MSG_TYPE, msg_type))) => msg_type,
+            _ => return Err(DecodeError::Malformed),
+        };
+        Ok(FixMessage {
+            msg_type,
+            fields: fields.collect::<Result<_, _>>()?,
+        })
+    }
+}
+
+/// Reads one framed message, leaving validation to `FixMessage::decode`.
+pub async fn read_message<R>(reader: &mut R) -> std::io::Result<Vec<u8>>
+where
+    R: AsyncBufRead + Unpin,
+{
+    let mut raw = Vec::new();
+    for _ in 0..2 {
+        if reader.read_until(SOH, &mut raw).await? == 0 {
+            return Err(std::io::ErrorKind::UnexpectedEof.into());
+        }
+    }
+
+    let invalid = || std::io::Error::from(std::io::ErrorKind::InvalidData);
+    let begin_end = raw.iter().position(|&b| b == SOH).ok_or_else(invalid)?;
+    let body_length: usize = std::str::from_utf8(&raw[begin_end + 1..raw.len() - 1])
+        .ok()
+        .and_then(|field| field.strip_prefix("9="))
+        .and_then(|len| len.parse().ok())
+        .filter(|&len| len <= MAX_BODY_LEN)
+        .ok_or_else(invalid)?;
+
+    let start = raw.len();
+    raw.resize(start + body_length + TRAILER_LEN, 0);
+    reader.read_exact(&mut raw[start..]).await?;
+    Ok(raw)
+}
+
+/// Price (44), LastPx (31) and AvgPx (6) are decimals like `123.45`. More
+/// decimals than the engine keeps are only accepted when they are zeros.
+pub fn parse_price(value: &str) -> Option<Price> {
+    let (negative, digits) = match value.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, value),
+    };
+    let (whole, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+    let fraction = fraction.trim_end_matches('0');
+    let is_number = |s: &str| s.bytes().all(|b| b.is_ascii_digit());
+    if whole.is_empty() || !is_number(whole) || !is_number(fraction) {
+        return None;
+    }
+    if fraction.len() > PRICE_DECIMALS {
+        return None;
+    }
+
+    let fraction: i64 = format!("{fraction:0<PRICE_DECIMALS$}").parse().ok()?;
+    let price = whole
+        .parse::<i64>()
+        .ok()?
+        .checked_mul(PRICE_SCALE)?
+        .checked_add(fraction)?;
+    Some(Price(if negative { -price } else { price }))
+}
+
+/// Price as a decimal with all the decimals the engine keeps, `123.40`.
+pub fn format_price(price: Price) -> String {
+    let sign = if price.0 < 0 { "-" } else { "" };
+    let abs = price.0.unsigned_abs();
+    let scale = PRICE_SCALE as u64;
+    format!("{sign}{}.{:0PRICE_DECIMALS$}", abs / scale, abs % scale)
+}
+
+/// UTCTimestamp with milliseconds, `YYYYMMDD-HH:MM:SS.sss`.
+pub fn utc_timestamp(unix_ns: i64) -> String {
+    let millis = unix_ns.div_euclid(1_000_000);
+    let secs = millis.div_euclid(1_000);
+    let (year, month, day) = civil_from_days(secs.div_euclid(86_400));
+    let second_of_day = secs.rem_euclid(86_400);
+    format!(
+        "{year:04}{month:02}{day:02}-{:02}:{:02}:{:02}.{:03}",
+        second_of_day / 3_600,
+        second_of_day % 3_600 / 60,
+        second_of_day % 60,
+        millis.rem_euclid(1_000)
+    )
+}
+
+/// Gregorian date of a day count since 1970-01-01.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+fn push_field(out: &mut Vec<u8>, tag: u32, value: &str) {
+    out.extend_from_slice(tag.to_string().as_bytes());
+    out.push(b'=');
+    out.extend_from_slice(value.as_bytes());
+    out.push(SOH);
+}
+
+fn parse_field(field: &[u8]) -> Result<(u32, String), DecodeError> {
+    let field = std::str::from_utf8(field).map_err(|_| DecodeError::Malformed)?;
+    let (tag, value) = field.split_once('=').ok_or(DecodeError::Malformed)?;
+    let tag = tag.parse().map_err(|_| DecodeError::Malformed)?;
+    Ok((tag, value.to_string()))
+}
+
+fn checksum(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0u8, |sum, &b| sum.wrapping_add(b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn logon() -> FixMessage {
+        FixMessage::new(msg_type::LOGON)
+            .with(tag::SENDER_COMP_ID, "CLIENT")
+            .with(tag::TARGET_COMP_ID, "EXCHANGE")
+            .with(tag::MSG_SEQ_NUM, 1)
+            .with(tag::ENCRYPT_METHOD, 0)
+            .with(tag::HEART_BT_INT, 30)
+    }
+
+    #[test]
+    fn encodes_body_length_and_checksum() {
+        let encoded = String::from_utf8(logon().encode())
+            .unwrap()
+            .replace('\x01', "|");
+
+        assert_eq!(
+            encoded,
+            "8=FIX.4.4|9=44|35=A|49=CLIENT|56=EXCHANGE|34=1|98=0|108=30|10=044|"
+        );
+    }
+
+    #[test]
+    fn decode_round_trips() {
+        let message = logon();
+        let decoded = FixMessage::decode(&message.encode()).unwrap();
+
+        assert_eq!(decoded, message);
+        assert_eq!(decoded.parse::<u64>(tag::HEART_BT_INT), Some(30));
+    }
+
+    #[test]
+    fn decode_rejects_corrupted_messages() {
+        let mut encoded = logon().encode();
+        let position = encoded.len() - 12;
+        encoded[position] = b'9';
+        assert_eq!(FixMessage::decode(&encoded), Err(DecodeError::CheckSum));
+
+        let truncated = String::from_utf8(logon().encode())
+            .unwrap()
+            .replace("9=44", "9=43");
+        let mut truncated = truncated.into_bytes();
+        let sum = checksum(&truncated[..truncated.len() - TRAILER_LEN]);
+        let len = truncated.len();
+        truncated[len - 4..len - 1].copy_from_slice(format!("{sum:03}").as_bytes());
+        assert_eq!(FixMessage::decode(&truncated), Err(DecodeError::BodyLength));
+    }
+
+    #[tokio::test]
+    async fn reads_framed_messages_from_a_stream() {
+        let mut bytes = logon().encode();
+        bytes.extend(FixMessage::new(msg_type::HEARTBEAT).encode());
+        let mut reader = tokio::io::BufReader::new(bytes.as_slice());
+
+        let first = read_message(&mut reader).await.unwrap();
+        let second = read_message(&mut reader).await.unwrap();
+
+        assert_eq!(FixMessage::decode(&first).unwrap(), logon());
+        assert_eq!(
+            FixMessage::decode(&second).unwrap().msg_type(),
+            msg_type::HEARTBEAT
+        );
+        assert!(read_message(&mut reader).await.is_err());
+    }
+
+    #[test]
+    fn prices_round_trip_as_decimals() {
+        for (decimal, price) in [
+            ("123.45", 12_345),
+            ("0.05", 5),
+            ("-1.50", -150),
+            ("7.00", 700),
+        ] {
+            assert_eq!(parse_price(decimal), Some(Price(price)));
+            assert_eq!(format_price(Price(price)), decimal);
+        }
+
+        assert_eq!(parse_price("99"), Some(Price(9_900)));
+        assert_eq!(parse_price("1.5"), Some(Price(150)));
+        assert_eq!(parse_price("1.2500"), Some(Price(125)));
+        for invalid in ["1.234", "", ".5", "1.-5", "1e3", "abc"] {
+            assert_eq!(parse_price(invalid), None, "{invalid}");
+        }
+    }
+
+    #[test]
+    fn formats_utc_timestamps() {
+        assert_eq!(utc_timestamp(0), "19700101-00:00:00.000");
+        assert_eq!(
+            utc_timestamp(1_709_210_096_789_000_000),
+            "20240229-12:34:56.789"
+        );
+    }
+}
@@ -0,0 +1,377 @@
+//! FIX 4.4 order entry acceptor.
+//!
+//! Counterparties log on with their SenderCompID and send NewOrderSingle,
+//! OrderCancelRequest and OrderCancelReplaceRequest messages, answered by
+//! ExecutionReports and OrderCancelRejects. Sequence numbers and sent
+//! application messages are persisted per session. Resend requests replay
+//! the application messages and gap fill the session messages in between.
+
+pub mod message;
+mod session;
+mod store;
+
+use crate::auth::constant_time_eq;
+use crate::configuration::FixSettings;
+use crate::domain::trade::now_unix_ns;
+use crate::gateway::fix::message::{FixMessage, msg_type, read_message, tag, utc_timestamp};
+use crate::gateway::fix::session::Sessions;
+use crate::gateway::fix::store::SequenceStore;
+use crate::matching::state::AppState;
+use log::{debug, info, warn};
+use std::io::ErrorKind;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncWriteExt, BufReader};
+use tokio::net::tcp::OwnedWriteHalf;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+
+const LOGON_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub async fn serve(listener: TcpListener, state: AppState, settings: Arc<FixSettings>) {
+    let sessions = Arc::new(Sessions::default());
+
+    loop {
+        match listener.accept().await {
+            Ok((stream, peer)) => {
+                debug!("FIX connection from {peer}");
+                let (state, settings, sessions) =
+                    (state.clone(), settings.clone(), sessions.clone());
+                tokio::spawn(async move {
+                    if let Err(e) = connection(stream, state, settings, sessions).await {
+                        debug!("FIX connection from {peer} closed: {e}");
+                    }
+                });
+            }
+            Err(e) => warn!("Failed to accept FIX connection: {e}"),
+        }
+    }
+}
+
+async fn connection(
+    stream: TcpStream,
+    state: AppState,
+    settings: Arc<FixSettings>,
+    sessions: Arc<Sessions>,
+) -> std::io::Result<()> {
+    stream.set_nodelay(true)?;
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+
+    let raw = tokio::time::timeout(LOGON_TIMEOUT, read_message(&mut reader))
+        .await
+        .map_err(|_| ErrorKind::TimedOut)??;
+    let logon = FixMessage::decode(&raw).map_err(|_| ErrorKind::InvalidData)?;
+    if logon.msg_type() != msg_type::LOGON {
+        return Err(ErrorKind::InvalidData.into());
+    }
+
+    let counterparty = logon.get(tag::SENDER_COMP_ID).unwrap_or_default();
+    let heartbeat = logon.parse::<u64>(tag::HEART_BT_INT).filter(|&s| s > 0);
+    let sequence = logon.parse::<u64>(tag::MSG_SEQ_NUM);
+    let authorized = settings.sessions.iter().find(|s| {
+        s.sender_comp_id == counterparty
+            && logon.get(tag::TARGET_COMP_ID) == Some(settings.comp_id.as_str())
+            && s.password.as_deref().is_none_or(|p| {
+                logon
+                    .get(tag::PASSWORD)
+                    .is_some_and(|given| constant_time_eq(given.as_bytes(), p.as_bytes()))
+            })
+    });
+    let (Some(config), Some(heartbeat), Some(sequence)) = (authorized, heartbeat, sequence) else {
+        warn!("Rejected FIX logon of {counterparty:?}");
+        // unknown counterparties get no sequence store
+        let logout = FixMessage::new(msg_type::LOGOUT)
+            .with_header(header(&settings.comp_id, counterparty, 1))
+            .with(tag::TEXT, "Logon rejected");
+        writer.write_all(&logout.encode()).await?;
+        return Ok(());
+    };
+
+    let session = sessions.get_or_start(config, &state);
+    let Ok(mut outbox) = session.outbox.try_lock() else {
+        warn!("FIX session {counterparty:?} is already logged on");
+        return Ok(());
+    };
+
+    let store = SequenceStore::open(
+        Path::new(&settings.store_dir),
+        &settings.comp_id,
+        counterparty,
+    )
+    .await?;
+    let mut conn = Connection {
+        writer,
+        store,
+        sender: settings.comp_id.clone(),
+        target: counterparty.to_string(),
+        last_sent: Instant::now(),
+        resend_requested: false,
+    };
+
+    let reset = logon.flag(tag::RESET_SEQ_NUM_FLAG);
+    if reset {
+        conn.store.reset().await?;
+    } else if sequence < conn.store.next_target {
+        let text = format!("MsgSeqNum too low, expecting {}", conn.store.next_target);
+        conn.logout(&text).await?;
+        // unlocked before the connection closes, so the counterparty can
+        // log on again as soon as it sees the close
+        drop(outbox);
+        return Ok(());
+    }
+
+    let mut response = FixMessage::new(msg_type::LOGON)
+        .with(tag::ENCRYPT_METHOD, 0)
+        .with(tag::HEART_BT_INT, heartbeat);
+    if reset {
+        response = response.with(tag::RESET_SEQ_NUM_FLAG, "Y");
+    }
+    conn.send(response).await?;
+    info!("FIX session {counterparty:?} logged on");
+    conn.receive(&logon).await?;
+
+    // Reads run in their own task, a read cancelled halfway by select! would
+    // lose the start of a message.
+    let (messages_tx, mut messages) = mpsc::channel(64);
+    let read_task = tokio::spawn(async move {
+        while let Ok(raw) = read_message(&mut reader).await {
+            if messages_tx.send(raw).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    let interval = Duration::from_secs(heartbeat);
+    let mut last_received = Instant::now();
+
+    let result = loop {
+        tokio::select! {
+            report = outbox.recv() => {
+                let Some(report) = report else {
+                    break Ok(());
+                };
+                conn.send(report).await?;
+            }
+            raw = messages.recv() => {
+                let Some(raw) = raw else {
+                    break Ok(());
+                };
+                last_received = Instant::now();
+                // garbled messages are ignored, the gap they leave is
+                // recovered by a resend request
+                let Ok(message) = FixMessage::decode(&raw) else {
+                    warn!("FIX session {counterparty:?} sent a garbled message");
+                    continue;
+                };
+                match conn.receive(&message).await? {
+                    Received::Admin => {}
+                    Received::Application => {
+                        if !session.submit(message).await {
+                            break Ok(());
+                        }
+                    }
+                    Received::Logout => break Ok(()),
+                }
+            }
+            _ = tokio::time::sleep_until(conn.last_sent + interval) => {
+                let silent = last_received.elapsed();
+                if silent > interval * 3 {
+                    info!("FIX session {counterparty:?} timed out");
+                    break Ok(());
+                }
+                let message = if silent > interval * 2 {
+                    FixMessage::new(msg_type::TEST_REQUEST).with(tag::TEST_REQ_ID, now_unix_ns())
+                } else {
+                    FixMessage::new(msg_type::HEARTBEAT)
+                };
+                conn.send(message).await?;
+            }
+        }
+    };
+
+    read_task.abort();
+    drop(outbox);
+    info!("FIX session {counterparty:?} logged out");
+    result
+}
+
+/// What the session layer made of an incoming message.
+enum Received {
+    Admin,
+    Application,
+    Logout,
+}
+
+/// Session layer of a logged on counterparty: sequencing, heartbeats and
+/// recovery.
+struct Connection {
+    writer: OwnedWriteHalf,
+    store: SequenceStore,
+    sender: String,
+    target: String,
+    last_sent: Instant,
+    /// Set while a resend request is outstanding, so a gap is only asked for
+    /// once.
+    resend_requested: bool,
+}
+
+impl Connection {
+    async fn receive(&mut self, message: &FixMessage) -> std::io::Result<Received> {
+        let Some(sequence) = message.parse::<u64>(tag::MSG_SEQ_NUM) else {
+            return Err(ErrorKind::InvalidData.into());
+        };
+        let expected = self.store.next_target;
+
+        if message.msg_type() == msg_type::SEQUENCE_RESET && !message.flag(tag::GAP_FILL_FLAG) {
+            return self.sequence_reset(message).await;
+        }
+        if sequence > expected {
+            if !self.resend_requested {
+                self.resend_requested = true;
+                let request = FixMessage::new(msg_type::RESEND_REQUEST)
+                    .with(tag::BEGIN_SEQ_NO, expected)
+                    .with(tag::ECHO_SEQ_NO_END, 0);
+                self.send(request).await?;
+            }
+            // the counterparty resends this message after the gap
+            return Ok(match message.msg_type() {
+                msg_type::LOGOUT => Received::Logout,
+                _ => Received::Admin,
+            });
+        }
+        if sequence < expected {
+            if message.flag(tag::POSS_DUP_FLAG) {
+                return Ok(Received::Admin);
+            }
+            let text = format!("MsgSeqNum too low, expecting {expected}");
+            self.logout(&text).await?;
+            return Ok(Received::Logout);
+        }
+
+        self.resend_requested = false;
+        self.store.next_target = sequence + 1;
+        self.store.save().await?;
+
+        match message.msg_type() {
+            msg_type::LOGON | msg_type::HEARTBEAT => Ok(Received::Admin),
+            msg_type::TEST_REQUEST => {
+                let mut heartbeat = FixMessage::new(msg_type::HEARTBEAT);
+                if let Some(id) = message.get(tag::TEST_REQ_ID) {
+                    heartbeat = heartbeat.with(tag::TEST_REQ_ID, id);
+                }
+                self.send(heartbeat).await?;
+                Ok(Received::Admin)
+            }
+            msg_type::RESEND_REQUEST => {
+                let begin = message.parse(tag::BEGIN_SEQ_NO).unwrap_or(1);
+                let end = message.parse(tag::ECHO_SEQ_NO_END).unwrap_or(0);
+                self.resend(begin, end).await?;
+                Ok(Received::Admin)
+            }
+            msg_type::SEQUENCE_RESET => self.sequence_reset(message).await,
+            msg_type::LOGOUT => {
+                self.send(FixMessage::new(msg_type::LOGOUT)).await?;
+                Ok(Received::Logout)
+            }
+            msg_type::NEW_ORDER_SINGLE
+            | msg_type::ORDER_CANCEL_REQUEST
+            | msg_type::ORDER_CANCEL_REPLACE_REQUEST => Ok(Received::Application),
+            other => {
+                let reject = FixMessage::new(msg_type::REJECT)
+                    .with(tag::REF_SEQ_NUM, sequence)
+                    .with(tag::SESSION_REJECT_REASON, 11)
+                    .with(tag::TEXT, format!("Unsupported MsgType {other}"));
+                self.send(reject).await?;
+                Ok(Received::Admin)
+            }
+        }
+    }
+
+    /// Moves the expected sequence number forward, as a gap fill or a reset
+    /// asks for. It never moves back.
+    async fn sequence_reset(&mut self, message: &FixMessage) -> std::io::Result<Received> {
+        let Some(new_sequence) = message.parse::<u64>(tag::NEW_SEQ_NO) else {
+            return Err(ErrorKind::InvalidData.into());
+        };
+        if new_sequence > self.store.next_target {
+            self.store.next_target = new_sequence;
+            self.store.save().await?;
+        }
+        Ok(Received::Admin)
+    }
+
+    /// Answers a resend request by sending the journaled application
+    /// messages from `begin` to `end` (0 for all) again, and skipping the
+    /// session messages in between with gap fills.
+    async fn resend(&mut self, begin: u64, end: u64) -> std::io::Result<()> {
+        let last = self.store.next_sender - 1;
+        let end = if end == 0 { last } else { end.min(last) };
+        if begin > end {
+            return Ok(());
+        }
+
+        let mut next = begin;
+        for message in self.store.messages(begin, end).await? {
+            let sequence = message.parse::<u64>(tag::MSG_SEQ_NUM).unwrap_or(next);
+            if sequence > next {
+                self.gap_fill(next, sequence).await?;
+            }
+            let message = message.possible_duplicate(utc_timestamp(now_unix_ns()));
+            self.write(message).await?;
+            next = sequence + 1;
+        }
+        if next <= end {
+            self.gap_fill(next, end + 1).await?;
+        }
+        Ok(())
+    }
+
+    /// Skips the messages from `begin` up to `new_sequence`.
+    async fn gap_fill(&mut self, begin: u64, new_sequence: u64) -> std::io::Result<()> {
+        let gap_fill = FixMessage::new(msg_type::SEQUENCE_RESET)
+            .with_header(header(&self.sender, &self.target, begin))
+            .with(tag::POSS_DUP_FLAG, "Y")
+            .with(tag::GAP_FILL_FLAG, "Y")
+            .with(tag::NEW_SEQ_NO, new_sequence);
+        self.write(gap_fill).await
+    }
+
+    async fn logout(&mut self, text: &str) -> std::io::Result<()> {
+        self.send(FixMessage::new(msg_type::LOGOUT).with(tag::TEXT, text))
+            .await
+    }
+
+    /// Stamps the header with the next sequence number and sends.
+    /// Application messages are journaled first, so they can be resent.
+    async fn send(&mut self, message: FixMessage) -> std::io::Result<()> {
+        let sequence = self.store.next_sender;
+        self.store.next_sender += 1;
+        self.store.save().await?;
+        let message = message.with_header(header(&self.sender, &self.target, sequence));
+        if matches!(
+            message.msg_type(),
+            msg_type::EXECUTION_REPORT | msg_type::ORDER_CANCEL_REJECT
+        ) {
+            self.store.append(sequence, &message.encode()).await?;
+        }
+        self.write(message).await
+    }
+
+    async fn write(&mut self, message: FixMessage) -> std::io::Result<()> {
+        self.writer.write_all(&message.encode()).await?;
+        self.last_sent = Instant::now();
+        Ok(())
+    }
+}
+
+fn header(sender: &str, target: &str, sequence: u64) -> Vec<(u32, String)> {
+    vec![
+        (tag::SENDER_COMP_ID, sender.to_string()),
+        (tag::TARGET_COMP_ID, target.to_string()),
+        (tag::MSG_SEQ_NUM, sequence.to_string()),
+        (tag::SENDING_TIME, utc_timestamp(now_unix_ns())),
+    ]
+}
@@ -0,0 +1,474 @@
+use crate::configuration::FixSessionSettings;
+use crate::domain::account::AccountId;
+use crate::domain::fill::Fill;
+use crate::domain::order::{Order, OrderId, Price, Quantity, Revision, TimeInForce};
+use crate::domain::order_entry::OrderEntry;
+use crate::domain::side::Side;
+use crate::domain::symbol::Symbol;
+use crate::domain::trade::now_unix_ns;
+use crate::gateway::fix::message::{
+    FixMessage, format_price, msg_type, parse_price, tag, utc_timestamp,
+};
+use crate::matching::command::{CommandResult, MatchingEngineCommand};
+use crate::matching::engine::MarketEvent;
+use crate::matching::reject::{Reject, RejectCode};
+use crate::matching::state::AppState;
+use log::debug;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc;
+
+const INBOX_SIZE: usize = 1_024;
+
+/// Application side of one counterparty. It outlives the connections of the
+/// counterparty, reports produced while it is logged out are sent once it
+/// logs on again.
+pub struct Session {
+    inbox: mpsc::Sender<FixMessage>,
+    /// Held by the connection that is logged on, which keeps a second logon
+    /// of the same counterparty out.
+    pub outbox: tokio::sync::Mutex<mpsc::UnboundedReceiver<FixMessage>>,
+}
+
+impl Session {
+    pub async fn submit(&self, message: FixMessage) -> bool {
+        self.inbox.send(message).await.is_ok()
+    }
+}
+
+#[derive(Default)]
+pub struct Sessions {
+    sessions: Mutex<HashMap<String, Arc<Session>>>,
+}
+
+impl Sessions {
+    /// Returns the session of the counterparty, starting it on the first
+    /// logon.
+    pub fn get_or_start(&self, settings: &FixSessionSettings, state: &AppState) -> Arc<Session> {
+        let mut sessions = self.sessions.lock().expect("Sessions poisoned");
+        sessions
+            .entry(settings.sender_comp_id.clone())
+            .or_insert_with(|| {
+                let (inbox, requests) = mpsc::channel(INBOX_SIZE);
+                let (outbox, reports) = mpsc::unbounded_channel();
+                let worker = SessionWorker {
+                    account: settings.account,
+                    state: state.clone(),
+                    outbox,
+                    orders: HashMap::new(),
+                    cl_ord_ids: HashMap::new(),
+                    pending_cancels: HashMap::new(),
+                    exec_prefix: format!("{:x}", now_unix_ns()),
+                    exec_count: 0,
+                };
+                tokio::spawn(worker.run(requests));
+                Arc::new(Session {
+                    inbox,
+                    outbox: tokio::sync::Mutex::new(reports),
+                })
+            })
+            .clone()
+    }
+}
+
+/// Live order of the session, as last reported to the counterparty.
+struct TrackedOrder {
+    cl_ord_id: String,
+    revision: Revision,
+    symbol: Symbol,
+    side: Side,
+    price: Price,
+    leaves: Quantity,
+    cum: Quantity,
+    avg_price: Price,
+}
+
+impl TrackedOrder {
+    /// ExecutionReport with the current state of the order.
+    fn report(&self, id: OrderId, exec_id: String, exec_type: char, status: char) -> FixMessage {
+        FixMessage::new(msg_type::EXECUTION_REPORT)
+            .with(tag::ORDER_ID, id.0)
+            .with(tag::CL_ORD_ID, &self.cl_ord_id)
+            .with(tag::EXEC_ID, exec_id)
+            .with(tag::EXEC_TYPE, exec_type)
+            .with(tag::ORD_STATUS, status)
+            .with(tag::SYMBOL, self.symbol)
+            .with(tag::SIDE, side_code(self.side))
+            .with(tag::ORDER_QTY, self.leaves.0 + self.cum.0)
+            .with(tag::PRICE, format_price(self.price))
+            .with(tag::LEAVES_QTY, self.leaves.0)
+            .with(tag::CUM_QTY, self.cum.0)
+            .with(tag::AVG_PX, format_price(self.avg_price))
+    }
+}
+
+/// Turns NewOrderSingle, OrderCancelRequest and OrderCancelReplaceRequest
+/// into engine commands and engine events into ExecutionReports. Like the
+/// binary gateway, it handles one request at a time and drains the events
+/// first, so revisions are current when the next request needs them, and
+/// subscribes to the events of its account without losing any.
+struct SessionWorker {
+    account: AccountId,
+    state: AppState,
+    outbox: mpsc::UnboundedSender<FixMessage>,
+    orders: HashMap<OrderId, TrackedOrder>,
+    cl_ord_ids: HashMap<String, OrderId>,
+    /// ClOrdID of the cancel request of an order, reported with the cancel.
+    pending_cancels: HashMap<OrderId, String>,
+    exec_prefix: String,
+    exec_count: u64,
+}
+
+impl SessionWorker {
+    async fn run(mut self, mut requests: mpsc::Receiver<FixMessage>) {
+        let Some(mut events) = self.state.subscribe(self.account).await else {
+            return;
+        };
+
+        loop {
+            tokio::select! {
+                biased;
+                event = events.recv() => match event {
+                    Some(event) => self.on_event(event),
+                    None => break,
+                },
+                request = requests.recv() => match request {
+                    Some(request) => self.handle(request).await,
+                    None => break,
+                },
+            }
+        }
+    }
+
+    async fn handle(&mut self, request: FixMessage) {
+        match request.msg_type() {
+            msg_type::NEW_ORDER_SINGLE => self.new_order(request).await,
+            msg_type::ORDER_CANCEL_REQUEST => self.cancel(request).await,
+            msg_type::ORDER_CANCEL_REPLACE_REQUEST => self.replace(request).await,
+            other => debug!("FIX session ignores MsgType {other}"),
+        }
+    }
+
+    async fn new_order(&mut self, request: FixMessage) {
+        let cl_ord_id = request.get(tag::CL_ORD_ID).unwrap_or_default().to_string();
+        let symbol = request
+            .get(tag::SYMBOL)
+            .and_then(|s| s.parse::<Symbol>().ok());
+        let side = request.get(tag::SIDE).and_then(side);
+        let quantity = request.parse::<i64>(tag::ORDER_QTY);
+        let price = request.get(tag::PRICE).and_then(parse_price);
+        let limit = request.get(tag::ORD_TYPE).is_none_or(|t| t == "2");
+        let time_in_force = request
+            .get(tag::TIME_IN_FORCE)
+            .map_or(Some(TimeInForce::Day), time_in_force);
+
+        let (Some(symbol), Some(side), Some(quantity), Some(price), Some(time_in_force)) =
+            (symbol, side, quantity, price, time_in_force)
+        else {
+            return self.reject_order(&request, &cl_ord_id, 11, "Unsupported or missing fields");
+        };
+        if !limit || cl_ord_id.is_empty() {
+            return self.reject_order(&request, &cl_ord_id, 11, "Unsupported or missing fields");
+        }
+        if self.cl_ord_ids.contains_key(&cl_ord_id) {
+            return self.reject_order(&request, &cl_ord_id, 6, "Duplicate ClOrdID");
+        }
+
+        let entry = OrderEntry::new(price, quantity, side)
+            .with_symbol(symbol)
+            .with_account(self.account)
+            .with_time_in_force(time_in_force);
+        match self.submit(MatchingEngineCommand::Create(entry)).await {
+            Ok(order) => {
+                let tracked = TrackedOrder {
+                    cl_ord_id: cl_ord_id.clone(),
+                    revision: order.revision,
+                    symbol,
+                    side,
+                    price: order.price,
+                    leaves: order.quantity,
+                    cum: Quantity(0),
+                    avg_price: Price(0),
+                };
+                let report = tracked.report(order.id, self.exec_id(), '0', '0');
+                self.cl_ord_ids.insert(cl_ord_id, order.id);
+                self.orders.insert(order.id, tracked);
+                self.send(report);
+            }
+            Err(reject) => {
+                let reason = ord_rej_reason(reject.code);
+                self.reject_order(&request, &cl_ord_id, reason, &reject.message)
+            }
+        }
+    }
+
+    async fn cancel(&mut self, request: FixMessage) {
+        let Some((id, cl_ord_id, revision)) = self.lookup(&request, '1') else {
+            return;
+        };
+
+        self.pending_cancels.insert(id, cl_ord_id.clone());
+        // the cancel is reported from the OrderDeleted event
+        if let Err(reject) = self
+            .submit(MatchingEngineCommand::Delete(
+                id,
+                revision,
+                Some(self.account),
+            ))
+            .await
+        {
+            self.pending_cancels.remove(&id);
+            self.reject_cancel(&request, Some(id), &cl_ord_id, '1', &reject);
+        }
+    }
+
+    async fn replace(&mut self, request: FixMessage) {
+        let Some((id, cl_ord_id, revision)) = self.lookup(&request, '2') else {
+            return;
+        };
+        let cum = self.orders[&id].cum;
+
+        // OrderQty covers the executed quantity, the engine wants the rest
+        let leaves = request
+            .parse::<i64>(tag::ORDER_QTY)
+            .map(|quantity| Quantity(quantity - cum.0));
+        let price = request.get(tag::PRICE).map(parse_price);
+        if leaves.is_some_and(|leaves| leaves.0 <= 0) {
+            let reject = Reject::new(RejectCode::InvalidQuantity, "OrderQty not above CumQty");
+            return self.reject_cancel(&request, Some(id), &cl_ord_id, '2', &reject);
+        }
+        if price == Some(None) {
+            let reject = Reject::new(RejectCode::InvalidPrice, "Price is not a decimal");
+            return self.reject_cancel(&request, Some(id), &cl_ord_id, '2', &reject);
+        }
+        let price = price.flatten();
+
+        let cmd = MatchingEngineCommand::Modify(id, revision, price, leaves, Some(self.account));
+        match self.submit(cmd).await {
+            Ok(order) => {
+                let Some(tracked) = self.orders.get_mut(&id) else {
+                    return;
+                };
+                let previous = std::mem::replace(&mut tracked.cl_ord_id, cl_ord_id.clone());
+                tracked.revision = order.revision;
+                tracked.price = order.price;
+                tracked.leaves = order.quantity;
+                self.cl_ord_ids.remove(&previous);
+                self.cl_ord_ids.insert(cl_ord_id, id);
+
+                let exec_id = self.exec_id();
+                let tracked = &self.orders[&id];
+                let status = if tracked.cum.0 > 0 { '1' } else { '0' };
+                let report = tracked
+                    .report(id, exec_id, '5', status)
+                    .with(tag::ORIG_CL_ORD_ID, previous);
+                self.send(report);
+            }
+            Err(reject) => self.reject_cancel(&request, Some(id), &cl_ord_id, '2', &reject),
+        }
+    }
+
+    /// Resolves OrigClOrdID of a cancel or replace request, answering with
+    /// an OrderCancelReject when it is unknown or ClOrdID is taken.
+    fn lookup(
+        &mut self,
+        request: &FixMessage,
+        response_to: char,
+    ) -> Option<(OrderId, String, Revision)> {
+        let cl_ord_id = request.get(tag::CL_ORD_ID).unwrap_or_default().to_string();
+        let orig = request.get(tag::ORIG_CL_ORD_ID).unwrap_or_default();
+
+        let Some(&id) = self.cl_ord_ids.get(orig) else {
+            let reject = Reject::new(RejectCode::NotFound, format!("Unknown order {orig}"));
+            self.reject_cancel(request, None, &cl_ord_id, response_to, &reject);
+            return None;
+        };
+        if cl_ord_id.is_empty() || self.cl_ord_ids.contains_key(&cl_ord_id) {
+            let reject = Reject::new(RejectCode::InvalidRequest, "Duplicate ClOrdID");
+            self.reject_cancel(request, Some(id), &cl_ord_id, response_to, &reject);
+            return None;
+        }
+
+        Some((id, cl_ord_id, self.orders[&id].revision))
+    }
+
+    fn on_event(&mut self, event: MarketEvent) {
+        match event {
+            MarketEvent::Fill(fill) if fill.account == self.account => self.on_fill(fill),
+            MarketEvent::OrderDeleted { order, .. } if order.account == self.account => {
+                self.on_deleted(order)
+            }
+            _ => {}
+        }
+    }
+
+    fn on_fill(&mut self, fill: Fill) {
+        let Some(tracked) = self.orders.get_mut(&fill.order_id) else {
+            return;
+        };
+        // events queued before a replace carry older revisions
+        tracked.revision = fill.revision.max(tracked.revision);
+        tracked.leaves = fill.leaves_quantity;
+        tracked.cum = fill.cum_quantity;
+        tracked.avg_price = fill.avg_price;
+
+        let exec_id = self.exec_id();
+        let tracked = &self.orders[&fill.order_id];
+        let status = if fill.leaves_quantity.0 == 0 {
+            '2'
+        } else {
+            '1'
+        };
+        let report = tracked
+            .report(fill.order_id, exec_id, 'F', status)
+            .with(tag::LAST_QTY, fill.quantity.0)
+            .with(tag::LAST_PX, format_price(fill.price))
+            .with(tag::TRANSACT_TIME, utc_timestamp(fill.exec_time));
+        self.send(report);
+
+        if fill.leaves_quantity.0 == 0 {
+            self.untrack(fill.order_id);
+        }
+    }
+
+    fn on_deleted(&mut self, order: Order) {
+        let Some(mut tracked) = self.untrack(order.id) else {
+            return;
+        };
+        tracked.leaves = Quantity(0);
+
+        let exec_id = self.exec_id();
+        let report = match self.pending_cancels.remove(&order.id) {
+            Some(cl_ord_id) => {
+                let orig = std::mem::replace(&mut tracked.cl_ord_id, cl_ord_id);
+                tracked
+                    .report(order.id, exec_id, '4', '4')
+                    .with(tag::ORIG_CL_ORD_ID, orig)
+            }
+            None => tracked.report(order.id, exec_id, '4', '4'),
+        };
+        self.send(report);
+    }
+
+    fn untrack(&mut self, id: OrderId) -> Option<TrackedOrder> {
+        let tracked = self.orders.remove(&id)?;
+        self.cl_ord_ids.remove(&tracked.cl_ord_id);
+        Some(tracked)
+    }
+
+    async fn submit(&self, cmd: MatchingEngineCommand) -> CommandResult {
+        if let Err(throttled) = self.state.rate_limits.check(None, Some(self.account), 1) {
+            debug!("Throttled {:?}: {throttled:?}", self.account);
+            return Err(Reject::new(RejectCode::RateLimited, "Rate limited"));
+        }
+
+        self.state.request(cmd).await
+    }
+
+    fn reject_order(&mut self, request: &FixMessage, cl_ord_id: &str, reason: u32, text: &str) {
+        let mut report = FixMessage::new(msg_type::EXECUTION_REPORT)
+            .with(tag::ORDER_ID, "NONE")
+            .with(tag::CL_ORD_ID, cl_ord_id)
+            .with(tag::EXEC_ID, self.exec_id())
+            .with(tag::EXEC_TYPE, '8')
+            .with(tag::ORD_STATUS, '8');
+        for field in [tag::SYMBOL, tag::SIDE, tag::ORDER_QTY, tag::PRICE] {
+            if let Some(value) = request.get(field) {
+                report = report.with(field, value);
+            }
+        }
+        let report = report
+            .with(tag::LEAVES_QTY, 0)
+            .with(tag::CUM_QTY, 0)
+            .with(tag::AVG_PX, 0)
+            .with(tag::ORD_REJ_REASON, reason)
+            .with(tag::TEXT, text);
+        self.send(report);
+    }
+
+    fn reject_cancel(
+        &self,
+        request: &FixMessage,
+        id: Option<OrderId>,
+        cl_ord_id: &str,
+        response_to: char,
+        reject: &Reject,
+    ) {
+        let status = match id.and_then(|id| self.orders.get(&id)) {
+            Some(tracked) if tracked.cum.0 > 0 => '1',
+            Some(_) => '0',
+            None => '8',
+        };
+        // a tracked order the engine no longer knows has just been filled
+        let reason = match (reject.code, id) {
+            (RejectCode::NotFound, Some(_)) => 0,
+            (RejectCode::NotFound, None) => 1,
+            (RejectCode::InvalidRequest, _) => 6,
+            _ => 99,
+        };
+        let report = FixMessage::new(msg_type::ORDER_CANCEL_REJECT)
+            .with(
+                tag::ORDER_ID,
+                id.map_or("NONE".to_string(), |id| id.0.to_string()),
+            )
+            .with(tag::CL_ORD_ID, cl_ord_id)
+            .with(
+                tag::ORIG_CL_ORD_ID,
+                request.get(tag::ORIG_CL_ORD_ID).unwrap_or_default(),
+            )
+            .with(tag::ORD_STATUS, status)
+            .with(tag::CXL_REJ_RESPONSE_TO, response_to)
+            .with(tag::CXL_REJ_REASON, reason)
+            .with(tag::TEXT, &reject.message);
+        self.send(report);
+    }
+
+    fn exec_id(&mut self) -> String {
+        self.exec_count += 1;
+        format!("{}-{}", self.exec_prefix, self.exec_count)
+    }
+
+    fn send(&self, message: FixMessage) {
+        // the receiver lives as long as the session
+        let _ = self.outbox.send(message);
+    }
+}
+
+fn side(code: &str) -> Option<Side> {
+    match code {
+        "1" => Some(Side::Buy),
+        "2" => Some(Side::Sell),
+        _ => None,
+    }
+}
+
+/// TimeInForce (59): `0` Day, `1` Good Till Cancel, `3` Immediate Or Cancel.
+fn time_in_force(code: &str) -> Option<TimeInForce> {
+    match code {
+        "0" => Some(TimeInForce::Day),
+        "1" => Some(TimeInForce::Gtc),
+        "3" => Some(TimeInForce::Ioc),
+        _ => None,
+    }
+}
+
+fn side_code(side: Side) -> char {
+    match side {
+        Side::Buy => '1',
+        Side::Sell => '2',
+    }
+}
+
+pub fn ord_rej_reason(code: RejectCode) -> u32 {
+    match code {
+        RejectCode::Halted => 2,
+        RejectCode::RiskReject => 3,
+        RejectCode::NotFound => 5,
+        RejectCode::InvalidQuantity => 13,
+        RejectCode::InvalidRequest | RejectCode::PhaseRestricted => 11,
+        RejectCode::InvalidPrice
+        | RejectCode::EngineUnavailable
+        | RejectCode::RateLimited
+        | RejectCode::BatchAborted => 99,
+    }
+}
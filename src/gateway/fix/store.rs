@@ -0,0 +1,199 @@
+use crate::gateway::fix::message::{FixMessage, read_message, tag};
+use std::collections::BTreeMap;
+use std::io::{ErrorKind, SeekFrom};
+use std::path::{Path, PathBuf};
+use tokio::fs;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+
+/// Next outgoing and expected incoming sequence numbers of one session, and
+/// the application messages sent to the counterparty, kept in small files so
+/// they survive restarts of the exchange and can be resent.
+pub struct SequenceStore {
+    path: PathBuf,
+    journal: PathBuf,
+    /// Offset and length of every journaled message by sequence number, so
+    /// a resend only reads the messages it asks for.
+    index: BTreeMap<u64, (u64, usize)>,
+    journal_len: u64,
+    pub next_sender: u64,
+    pub next_target: u64,
+}
+
+impl SequenceStore {
+    /// Loads the numbers of the session between `sender` and `target`,
+    /// starting at 1 when the session is new.
+    pub async fn open(dir: &Path, sender: &str, target: &str) -> std::io::Result<Self> {
+        fs::create_dir_all(dir).await?;
+        let path = dir.join(format!("{sender}-{target}.seqnums"));
+        let journal = dir.join(format!("{sender}-{target}.messages"));
+
+        let (next_sender, next_target) = match fs::read_to_string(&path).await {
+            Ok(contents) => parse(&contents).ok_or(ErrorKind::InvalidData)?,
+            Err(e) if e.kind() == ErrorKind::NotFound => (1, 1),
+            Err(e) => return Err(e),
+        };
+
+        let (index, journal_len) = index(&journal).await?;
+        Ok(SequenceStore {
+            path,
+            journal,
+            index,
+            journal_len,
+            next_sender,
+            next_target,
+        })
+    }
+
+    /// Writes the numbers through a temporary file, so a crash leaves either
+    /// the old or the new numbers behind.
+    pub async fn save(&self) -> std::io::Result<()> {
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, format!("{} {}", self.next_sender, self.next_target)).await?;
+        fs::rename(tmp, &self.path).await
+    }
+
+    pub async fn reset(&mut self) -> std::io::Result<()> {
+        self.next_sender = 1;
+        self.next_target = 1;
+        match fs::remove_file(&self.journal).await {
+            Err(e) if e.kind() != ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+        self.index.clear();
+        self.journal_len = 0;
+        self.save().await
+    }
+
+    /// Appends a sent message, as encoded on the wire, to the journal.
+    pub async fn append(&mut self, sequence: u64, raw: &[u8]) -> std::io::Result<()> {
+        let mut journal = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.journal)
+            .await?;
+        journal.write_all(raw).await?;
+        journal.flush().await?;
+
+        self.index.insert(sequence, (self.journal_len, raw.len()));
+        self.journal_len += raw.len() as u64;
+        Ok(())
+    }
+
+    /// Journaled messages with sequence numbers from `begin` to `end`, in
+    /// the order they were sent.
+    pub async fn messages(&self, begin: u64, end: u64) -> std::io::Result<Vec<FixMessage>> {
+        let mut wanted = self.index.range(begin..=end).peekable();
+        if wanted.peek().is_none() {
+            return Ok(Vec::new());
+        }
+
+        let mut journal = fs::File::open(&self.journal).await?;
+        let mut messages = Vec::new();
+        for (_, &(offset, len)) in wanted {
+            let mut raw = vec![0; len];
+            journal.seek(SeekFrom::Start(offset)).await?;
+            journal.read_exact(&mut raw).await?;
+            messages.push(FixMessage::decode(&raw).map_err(|_| ErrorKind::InvalidData)?);
+        }
+        Ok(messages)
+    }
+}
+
+/// Reads the journal once to find its messages. A message cut short by a
+/// crash ends the index, it was never fully sent.
+async fn index(journal: &Path) -> std::io::Result<(BTreeMap<u64, (u64, usize)>, u64)> {
+    let contents = match fs::read(journal).await {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok((BTreeMap::new(), 0)),
+        Err(e) => return Err(e),
+    };
+
+    let mut index = BTreeMap::new();
+    let mut reader = contents.as_slice();
+    while !reader.is_empty() {
+        let offset = (contents.len() - reader.len()) as u64;
+        let Ok(raw) = read_message(&mut reader).await else {
+            break;
+        };
+        let Ok(message) = FixMessage::decode(&raw) else {
+            break;
+        };
+        let sequence = message.parse::<u64>(tag::MSG_SEQ_NUM).unwrap_or_default();
+        index.insert(sequence, (offset, raw.len()));
+    }
+    Ok((index, contents.len() as u64))
+}
+
+fn parse(contents: &str) -> Option<(u64, u64)> {
+    let mut numbers = contents.split_whitespace().map(str::parse);
+    match (numbers.next(), numbers.next()) {
+        (Some(Ok(sender)), Some(Ok(target))) => Some((sender, target)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gateway::fix::message::msg_type;
+
+    #[tokio::test]
+    async fn sequence_numbers_survive_reopening() {
+        let dir = std::env::temp_dir().join(format!("fix-store-{}", uuid::Uuid::new_v4()));
+
+        let mut store = SequenceStore::open(&dir, "EXCHANGE", "CLIENT")
+            .await
+            .unwrap();
+        assert_eq!((store.next_sender, store.next_target), (1, 1));
+        store.next_sender = 7;
+        store.next_target = 3;
+        store.save().await.unwrap();
+
+        let mut store = SequenceStore::open(&dir, "EXCHANGE", "CLIENT")
+            .await
+            .unwrap();
+        assert_eq!((store.next_sender, store.next_target), (7, 3));
+
+        store.reset().await.unwrap();
+        let store = SequenceStore::open(&dir, "EXCHANGE", "CLIENT")
+            .await
+            .unwrap();
+        assert_eq!((store.next_sender, store.next_target), (1, 1));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn journaled_messages_are_read_back_by_sequence_number() {
+        let dir = std::env::temp_dir().join(format!("fix-store-{}", uuid::Uuid::new_v4()));
+        let mut store = SequenceStore::open(&dir, "EXCHANGE", "CLIENT")
+            .await
+            .unwrap();
+
+        for sequence in [2, 3, 5] {
+            let report = FixMessage::new(msg_type::EXECUTION_REPORT)
+                .with(tag::MSG_SEQ_NUM, sequence)
+                .with(tag::TEXT, "a\nb");
+            store.append(sequence, &report.encode()).await.unwrap();
+        }
+
+        let sequences = |messages: Vec<FixMessage>| {
+            messages
+                .iter()
+                .map(|m| m.parse::<u64>(tag::MSG_SEQ_NUM).unwrap())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(sequences(store.messages(3, 5).await.unwrap()), [3, 5]);
+
+        // the index is rebuilt from the journal
+        let mut store = SequenceStore::open(&dir, "EXCHANGE", "CLIENT")
+            .await
+            .unwrap();
+        assert_eq!(sequences(store.messages(1, 2).await.unwrap()), [2]);
+
+        store.reset().await.unwrap();
+        assert!(store.messages(1, u64::MAX).await.unwrap().is_empty());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
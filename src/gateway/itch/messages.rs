@@ -0,0 +1,359 @@
+//! Fixed-width big-endian market data messages modeled on ITCH, carried in
+//! packets modeled on MoldUDP64.
+//!
+//! A packet is a session (10 bytes), the sequence number of its first message
+//! (`u64`) and a message count (`u16`), followed by every message as a `u16`
+//! length and its bytes. Packets without messages are heartbeats carrying the
+//! next sequence number.
+
+use crate::domain::order::{OrderId, Price, Quantity};
+use crate::domain::side::Side;
+use crate::domain::symbol::Symbol;
+use crate::domain::trade::TradeId;
+use crate::gateway::wire::{Reader, side, side_code};
+use tokio::io::{AsyncRead, AsyncReadExt};
+use uuid::Uuid;
+
+pub const SESSION_LEN: usize = 10;
+pub const HEADER_LEN: usize = SESSION_LEN + 8 + 2;
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum ItchMessage {
+    /// An order entered the book. Incoming orders are added before they
+    /// match, like `OrderCreated` is published before their trades.
+    AddOrder {
+        timestamp: i64,
+        order_id: OrderId,
+        side: Side,
+        quantity: Quantity,
+        symbol: Symbol,
+        price: Price,
+    },
+    /// Part of an order traded, sent for both the resting and the incoming
+    /// order of a trade.
+    OrderExecuted {
+        timestamp: i64,
+        order_id: OrderId,
+        quantity: Quantity,
+        match_number: TradeId,
+    },
+    /// `quantity` was taken off an order, which keeps its queue position. The
+    /// order is gone once nothing is left.
+    OrderCancel {
+        timestamp: i64,
+        order_id: OrderId,
+        quantity: Quantity,
+    },
+    /// The order has a new quantity and price and moved to the back of the
+    /// queue of its price level.
+    OrderReplace {
+        timestamp: i64,
+        order_id: OrderId,
+        quantity: Quantity,
+        price: Price,
+    },
+    /// Trade print, `side` is the aggressor.
+    Trade {
+        timestamp: i64,
+        symbol: Symbol,
+        side: Side,
+        quantity: Quantity,
+        price: Price,
+        match_number: TradeId,
+    },
+}
+
+impl ItchMessage {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(64);
+        match self {
+            ItchMessage::AddOrder {
+                timestamp,
+                order_id,
+                side,
+                quantity,
+                symbol,
+                price,
+            } => {
+                buf.push(b'A');
+                buf.extend(timestamp.to_be_bytes());
+                buf.extend(order_id.0.as_bytes());
+                buf.push(side_code(*side));
+                buf.extend(quantity.0.to_be_bytes());
+                buf.extend(symbol.as_bytes());
+                buf.extend(price.0.to_be_bytes());
+            }
+            ItchMessage::OrderExecuted {
+                timestamp,
+                order_id,
+                quantity,
+                match_number,
+            } => {
+                buf.push(b'E');
+                buf.extend(timestamp.to_be_bytes());
+                buf.extend(order_id.0.as_bytes());
+                buf.extend(quantity.0.to_be_bytes());
+                buf.extend(match_number.0.to_be_bytes());
+            }
+            ItchMessage::OrderCancel {
+                timestamp,
+                order_id,
+                quantity,
+            } => {
+                buf.push(b'X');
+                buf.extend(timestamp.to_be_bytes());
+                buf.extend(order_id.0.as_bytes());
+                buf.extend(quantity.0.to_be_bytes());
+            }
+            ItchMessage::OrderReplace {
+                timestamp,
+                order_id,
+                quantity,
+                price,
+            } => {
+                buf.push(b'U');
+                buf.extend(timestamp.to_be_bytes());
+                buf.extend(order_id.0.as_bytes());
+                buf.extend(quantity.0.to_be_bytes());
+                buf.extend(price.0.to_be_bytes());
+            }
+            ItchMessage::Trade {
+                timestamp,
+                symbol,
+                side,
+                quantity,
+                price,
+                match_number,
+            } => {
+                buf.push(b'P');
+                buf.extend(timestamp.to_be_bytes());
+                buf.extend(symbol.as_bytes());
+                buf.push(side_code(*side));
+                buf.extend(quantity.0.to_be_bytes());
+                buf.extend(price.0.to_be_bytes());
+                buf.extend(match_number.0.to_be_bytes());
+            }
+        }
+        buf
+    }
+
+    pub fn decode(message: &[u8]) -> Option<Self> {
+        let mut r = Reader(message);
+        let message = match r.u8()? {
+            b'A' => ItchMessage::AddOrder {
+                timestamp: r.i64()?,
+                order_id: OrderId(Uuid::from_bytes(r.bytes()?)),
+                side: side(r.u8()?)?,
+                quantity: Quantity(r.i64()?),
+                symbol: Symbol::try_from(&r.bytes::<{ Symbol::LEN }>()?[..]).ok()?,
+                price: Price(r.i64()?),
+            },
+            b'E' => ItchMessage::OrderExecuted {
+                timestamp: r.i64()?,
+                order_id: OrderId(Uuid::from_bytes(r.bytes()?)),
+                quantity: Quantity(r.i64()?),
+                match_number: TradeId(r.u64()?),
+            },
+            b'X' => ItchMessage::OrderCancel {
+                timestamp: r.i64()?,
+                order_id: OrderId(Uuid::from_bytes(r.bytes()?)),
+                quantity: Quantity(r.i64()?),
+            },
+            b'U' => ItchMessage::OrderReplace {
+                timestamp: r.i64()?,
+                order_id: OrderId(Uuid::from_bytes(r.bytes()?)),
+                quantity: Quantity(r.i64()?),
+                price: Price(r.i64()?),
+            },
+            b'P' => ItchMessage::Trade {
+                timestamp: r.i64()?,
+                symbol: Symbol::try_from(&r.bytes::<{ Symbol::LEN }>()?[..]).ok()?,
+                side: side(r.u8()?)?,
+                quantity: Quantity(r.i64()?),
+                price: Price(r.i64()?),
+                match_number: TradeId(r.u64()?),
+            },
+            _ => return None,
+        };
+        r.finish(message)
+    }
+}
+
+/// Encoded messages and the sequence number of the first one.
+#[derive(Debug, PartialEq, Clone)]
+pub struct MoldPacket {
+    pub session: [u8; SESSION_LEN],
+    pub sequence: u64,
+    pub messages: Vec<Vec<u8>>,
+}
+
+impl MoldPacket {
+    pub fn encode(&self) -> Vec<u8> {
+        let len = self.messages.iter().map(|m| m.len() + 2).sum::<usize>();
+        let mut buf = Vec::with_capacity(HEADER_LEN + len);
+        buf.extend(self.session);
+        buf.extend(self.sequence.to_be_bytes());
+        buf.extend((self.messages.len() as u16).to_be_bytes());
+        for message in &self.messages {
+            buf.extend((message.len() as u16).to_be_bytes());
+            buf.extend(message);
+        }
+        buf
+    }
+
+    pub fn decode(packet: &[u8]) -> Option<Self> {
+        let mut r = Reader(packet);
+        let session = r.bytes()?;
+        let sequence = r.u64()?;
+        let count = r.u16()?;
+        let messages = (0..count)
+            .map(|_| {
+                let len = r.u16()? as usize;
+                r.slice(len).map(<[u8]>::to_vec)
+            })
+            .collect::<Option<_>>()?;
+        r.finish(MoldPacket {
+            session,
+            sequence,
+            messages,
+        })
+    }
+
+    /// Sequence number following the last message.
+    pub fn next_sequence(&self) -> u64 {
+        self.sequence + self.messages.len() as u64
+    }
+}
+
+/// Asks the retransmission server for `count` messages from `sequence` on.
+#[derive(Debug, PartialEq, Clone)]
+pub struct RetransmitRequest {
+    pub session: [u8; SESSION_LEN],
+    pub sequence: u64,
+    pub count: u16,
+}
+
+impl RetransmitRequest {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(HEADER_LEN);
+        buf.extend(self.session);
+        buf.extend(self.sequence.to_be_bytes());
+        buf.extend(self.count.to_be_bytes());
+        buf
+    }
+
+    pub async fn read<R: AsyncRead + Unpin>(reader: &mut R) -> std::io::Result<Self> {
+        let mut session = [0; SESSION_LEN];
+        reader.read_exact(&mut session).await?;
+        Ok(RetransmitRequest {
+            session,
+            sequence: reader.read_u64().await?,
+            count: reader.read_u16().await?,
+        })
+    }
+}
+
+/// Reads one packet from a retransmission stream.
+pub async fn read_packet<R: AsyncRead + Unpin>(reader: &mut R) -> std::io::Result<MoldPacket> {
+    let mut session = [0; SESSION_LEN];
+    reader.read_exact(&mut session).await?;
+    let sequence = reader.read_u64().await?;
+    let count = reader.read_u16().await?;
+
+    let mut messages = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let len = reader.read_u16().await?;
+        let mut message = vec![0; len as usize];
+        reader.read_exact(&mut message).await?;
+        messages.push(message);
+    }
+
+    Ok(MoldPacket {
+        session,
+        sequence,
+        messages,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gateway::ouch::messages::padded;
+
+    #[test]
+    fn messages_round_trip() {
+        let order_id = OrderId(Uuid::new_v4());
+        let messages = [
+            ItchMessage::AddOrder {
+                timestamp: 1,
+                order_id,
+                side: Side::Buy,
+                quantity: Quantity(10),
+                symbol: "ABC".parse().unwrap(),
+                price: Price(250),
+            },
+            ItchMessage::OrderExecuted {
+                timestamp: 2,
+                order_id,
+                quantity: Quantity(4),
+                match_number: TradeId(7),
+            },
+            ItchMessage::OrderCancel {
+                timestamp: 3,
+                order_id,
+                quantity: Quantity(2),
+            },
+            ItchMessage::OrderReplace {
+                timestamp: 4,
+                order_id,
+                quantity: Quantity(5),
+                price: Price(251),
+            },
+            ItchMessage::Trade {
+                timestamp: 5,
+                symbol: "ABC".parse().unwrap(),
+                side: Side::Sell,
+                quantity: Quantity(4),
+                price: Price(250),
+                match_number: TradeId(7),
+            },
+        ];
+
+        for message in messages {
+            assert_eq!(ItchMessage::decode(&message.encode()), Some(message));
+        }
+    }
+
+    #[tokio::test]
+    async fn packets_round_trip_as_datagrams_and_streams() {
+        let packet = MoldPacket {
+            session: padded("ITCH"),
+            sequence: 42,
+            messages: vec![vec![b'X'; 3], vec![b'Y'; 5]],
+        };
+        let encoded = packet.encode();
+
+        assert_eq!(MoldPacket::decode(&encoded), Some(packet.clone()));
+        assert_eq!(MoldPacket::decode(&encoded[..encoded.len() - 1]), None);
+        assert_eq!(read_packet(&mut encoded.as_slice()).await.unwrap(), packet);
+        assert_eq!(packet.next_sequence(), 44);
+    }
+
+    #[tokio::test]
+    async fn retransmit_requests_round_trip() {
+        let request = RetransmitRequest {
+            session: padded("ITCH"),
+            sequence: 3,
+            count: 2,
+        };
+        let encoded = request.encode();
+
+        assert_eq!(encoded.len(), HEADER_LEN);
+        assert_eq!(
+            RetransmitRequest::read(&mut encoded.as_slice())
+                .await
+                .unwrap(),
+            request
+        );
+    }
+}
@@ -0,0 +1,293 @@
+//! Binary market data feed modeled on ITCH over MoldUDP64.
+//!
+//! Book and trade events are turned into sequenced messages and sent as UDP
+//! datagrams to a multicast group or a unicast address. Subscribers that
+//! notice a gap in the sequence numbers ask the TCP retransmission server
+//! for the missing messages.
+
+pub mod messages;
+
+use crate::configuration::ItchSettings;
+use crate::domain::order::Quantity;
+use crate::domain::order_change::ModifyReason;
+use crate::domain::trade::now_unix_ns;
+use crate::gateway::itch::messages::{
+    HEADER_LEN, ItchMessage, MoldPacket, RetransmitRequest, SESSION_LEN,
+};
+use crate::gateway::ouch::messages::padded;
+use crate::matching::engine::{EventBatch, MarketEvent};
+use log::{debug, info, warn};
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+use tokio::sync::mpsc;
+
+/// Keeps datagrams below a typical Ethernet MTU.
+const MAX_PAYLOAD: usize = 1_400;
+
+/// Messages answered by one retransmission request at most.
+const MAX_RETRANSMIT: u16 = 1_000;
+
+/// Sends every event of the lossless engine feed, so the sequence numbers
+/// only have gaps where datagrams were lost on the way.
+pub async fn serve(
+    listener: TcpListener,
+    mut events: mpsc::UnboundedReceiver<EventBatch>,
+    settings: Arc<ItchSettings>,
+) -> std::io::Result<()> {
+    let target: SocketAddr = settings
+        .target
+        .parse()
+        .map_err(|_| std::io::ErrorKind::InvalidInput)?;
+    let socket = UdpSocket::bind(&settings.bind).await?;
+    if target.ip().is_multicast() {
+        socket.set_multicast_ttl_v4(settings.multicast_ttl)?;
+    }
+
+    let session = padded::<SESSION_LEN>(&settings.session);
+    let journal = Arc::new(Journal::new(settings.journal_capacity));
+    tokio::spawn(retransmit(listener, session, journal.clone()));
+
+    let mut heartbeat = tokio::time::interval(Duration::from_millis(settings.heartbeat_ms));
+    loop {
+        tokio::select! {
+            received = events.recv() => {
+                let Some(received) = received else {
+                    return Ok(());
+                };
+                let mut batch: Vec<_> = received.events.iter().flat_map(messages).collect();
+                // whatever queued up meanwhile shares the datagrams
+                while let Ok(received) = events.try_recv() {
+                    batch.extend(received.events.iter().flat_map(messages));
+                }
+                if batch.is_empty() {
+                    continue;
+                }
+
+                let encoded: Vec<_> = batch.iter().map(ItchMessage::encode).collect();
+                let mut sequence = journal.append(encoded.clone());
+                for messages in packets(encoded) {
+                    let packet = MoldPacket { session, sequence, messages };
+                    sequence = packet.next_sequence();
+                    if let Err(e) = socket.send_to(&packet.encode(), target).await {
+                        warn!("Failed to send market data: {e}");
+                    }
+                }
+                heartbeat.reset();
+            }
+            _ = heartbeat.tick() => {
+                let packet = MoldPacket {
+                    session,
+                    sequence: journal.next_sequence(),
+                    messages: Vec::new(),
+                };
+                if let Err(e) = socket.send_to(&packet.encode(), target).await {
+                    warn!("Failed to send market data heartbeat: {e}");
+                }
+            }
+        }
+    }
+}
+
+fn messages(event: &MarketEvent) -> Vec<ItchMessage> {
+    let timestamp = now_unix_ns();
+    match event {
+        MarketEvent::OrderCreated(order) => vec![ItchMessage::AddOrder {
+            timestamp,
+            order_id: order.id,
+            side: order.side,
+            quantity: order.quantity,
+            symbol: order.symbol,
+            price: order.price,
+        }],
+        MarketEvent::TradeExecuted(trade) => {
+            let executed = |order_id| ItchMessage::OrderExecuted {
+                timestamp: trade.exec_time(),
+                order_id,
+                quantity: trade.quantity,
+                match_number: trade.id,
+            };
+            vec![
+                executed(trade.maker_id()),
+                executed(trade.taker_id()),
+                ItchMessage::Trade {
+                    timestamp: trade.exec_time(),
+                    symbol: trade.symbol,
+                    side: trade.aggressor,
+                    quantity: trade.quantity,
+                    price: trade.price,
+                    match_number: trade.id,
+                },
+            ]
+        }
+        MarketEvent::OrderDeleted { order, .. } => vec![ItchMessage::OrderCancel {
+            timestamp,
+            order_id: order.id,
+            quantity: order.quantity,
+        }],
+        // fills are already reported as executions
+        MarketEvent::OrderModified(change) if change.reason == ModifyReason::PartialFill => {
+            vec![]
+        }
+        MarketEvent::OrderModified(change) if change.priority_lost => {
+            vec![ItchMessage::OrderReplace {
+                timestamp,
+                order_id: change.id,
+                quantity: change.new_quantity,
+                price: change.new_price,
+            }]
+        }
+        MarketEvent::OrderModified(change) => vec![ItchMessage::OrderCancel {
+            timestamp,
+            order_id: change.id,
+            quantity: Quantity(change.old_quantity.0 - change.new_quantity.0),
+        }],
+        _ => vec![],
+    }
+}
+
+/// Splits messages into datagram payloads of at most `MAX_PAYLOAD` bytes.
+fn packets(messages: Vec<Vec<u8>>) -> Vec<Vec<Vec<u8>>> {
+    let mut packets = vec![Vec::new()];
+    let mut size = HEADER_LEN;
+    for message in messages {
+        let len = message.len() + 2;
+        let current = packets.last_mut().expect("Always one packet");
+        if size + len > MAX_PAYLOAD && !current.is_empty() {
+            packets.push(Vec::new());
+            size = HEADER_LEN;
+        }
+        packets.last_mut().expect("Always one packet").push(message);
+        size += len;
+    }
+    packets
+}
+
+/// The latest `capacity` messages of the feed, for retransmission.
+struct Journal {
+    capacity: usize,
+    inner: Mutex<JournalInner>,
+}
+
+struct JournalInner {
+    /// Sequence number of the oldest message kept, sequences start at 1.
+    first: u64,
+    messages: VecDeque<Vec<u8>>,
+}
+
+impl Journal {
+    fn new(capacity: usize) -> Self {
+        Journal {
+            capacity: capacity.max(1),
+            inner: Mutex::new(JournalInner {
+                first: 1,
+                messages: VecDeque::new(),
+            }),
+        }
+    }
+
+    /// Appends the messages and returns the sequence number of the first.
+    fn append(&self, messages: Vec<Vec<u8>>) -> u64 {
+        let mut inner = self.inner.lock().expect("Journal poisoned");
+        let sequence = inner.first + inner.messages.len() as u64;
+        inner.messages.extend(messages);
+        let overflow = inner.messages.len().saturating_sub(self.capacity);
+        inner.messages.drain(..overflow);
+        inner.first += overflow as u64;
+        sequence
+    }
+
+    fn next_sequence(&self) -> u64 {
+        let inner = self.inner.lock().expect("Journal poisoned");
+        inner.first + inner.messages.len() as u64
+    }
+
+    /// Up to `count` messages from `sequence` on. Messages that are no
+    /// longer kept are skipped, the returned sequence tells where the
+    /// messages start.
+    fn range(&self, sequence: u64, count: u16) -> (u64, Vec<Vec<u8>>) {
+        let inner = self.inner.lock().expect("Journal poisoned");
+        let start = sequence.max(inner.first);
+        let messages = inner
+            .messages
+            .iter()
+            .skip((start - inner.first) as usize)
+            .take(count as usize)
+            .cloned()
+            .collect();
+        (start, messages)
+    }
+}
+
+async fn retransmit(listener: TcpListener, session: [u8; SESSION_LEN], journal: Arc<Journal>) {
+    info!(
+        "Market data retransmission listening on {:?}",
+        listener.local_addr()
+    );
+    loop {
+        match listener.accept().await {
+            Ok((stream, peer)) => {
+                debug!("Retransmission connection from {peer}");
+                let journal = journal.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = answer(stream, session, journal).await {
+                        debug!("Retransmission connection from {peer} closed: {e}");
+                    }
+                });
+            }
+            Err(e) => warn!("Failed to accept retransmission connection: {e}"),
+        }
+    }
+}
+
+/// Answers every request on the connection with one packet.
+async fn answer(
+    mut stream: TcpStream,
+    session: [u8; SESSION_LEN],
+    journal: Arc<Journal>,
+) -> std::io::Result<()> {
+    stream.set_nodelay(true)?;
+    loop {
+        let request = RetransmitRequest::read(&mut stream).await?;
+        if request.session != session {
+            return Err(std::io::ErrorKind::InvalidData.into());
+        }
+
+        let (sequence, messages) =
+            journal.range(request.sequence, request.count.min(MAX_RETRANSMIT));
+        let packet = MoldPacket {
+            session,
+            sequence,
+            messages,
+        };
+        stream.write_all(&packet.encode()).await?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn journal_keeps_the_latest_messages() {
+        let journal = Journal::new(3);
+
+        assert_eq!(journal.append(vec![vec![1], vec![2]]), 1);
+        assert_eq!(journal.append(vec![vec![3], vec![4]]), 3);
+        assert_eq!(journal.next_sequence(), 5);
+
+        assert_eq!(journal.range(3, 5), (3, vec![vec![3], vec![4]]));
+        assert_eq!(journal.range(1, 2), (2, vec![vec![2], vec![3]]));
+        assert_eq!(journal.range(9, 1), (9, vec![]));
+    }
+
+    #[test]
+    fn packets_stay_below_the_payload_limit() {
+        let packets = packets(vec![vec![0; 600]; 5]);
+
+        assert_eq!(packets.iter().map(Vec::len).collect::<Vec<_>>(), [2, 2, 1]);
+    }
+}
@@ -0,0 +1,4 @@
+pub mod fix;
+pub mod itch;
+pub mod ouch;
+mod wire;
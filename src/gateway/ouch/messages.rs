@@ -0,0 +1,508 @@
+//! Fixed-width big-endian messages of the binary order entry protocol.
+//!
+//! Every packet starts with a `u16` length of the rest of the packet followed
+//! by a one byte packet type. Order messages travel inside unsequenced (`U`,
+//! client to server) and sequenced (`S`, server to client) data packets.
+
+use crate::domain::account::AccountId;
+use crate::domain::order::{OrderId, Price, Quantity, TimeInForce};
+use crate::domain::side::Side;
+use crate::domain::symbol::Symbol;
+use crate::domain::trade::TradeId;
+use crate::gateway::wire::{Reader, side, side_code};
+use tokio::io::{AsyncRead, AsyncReadExt};
+use uuid::Uuid;
+
+pub const TOKEN_LEN: usize = 14;
+pub const CREDENTIAL_LEN: usize = 16;
+pub const SESSION_LEN: usize = 10;
+
+/// Client assigned order token, unique within a session.
+pub type Token = [u8; TOKEN_LEN];
+
+/// Packets sent by the client.
+#[derive(Debug, PartialEq)]
+pub enum ClientPacket {
+    /// `sequence` is the next sequenced message the client wants to receive,
+    /// `0` to start with the next new message.
+    Login {
+        account: AccountId,
+        credential: [u8; CREDENTIAL_LEN],
+        sequence: u64,
+    },
+    Heartbeat,
+    Logout,
+    Order(Request),
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Request {
+    Enter {
+        token: Token,
+        side: Side,
+        quantity: Quantity,
+        symbol: Symbol,
+        price: Price,
+        time_in_force: TimeInForce,
+    },
+    Replace {
+        existing: Token,
+        replacement: Token,
+        quantity: Quantity,
+        price: Price,
+    },
+    Cancel {
+        token: Token,
+    },
+}
+
+/// Packets sent by the server.
+#[derive(Debug, PartialEq)]
+pub enum ServerPacket {
+    LoginAccepted {
+        session: [u8; SESSION_LEN],
+        sequence: u64,
+    },
+    LoginRejected {
+        reason: u8,
+    },
+    Heartbeat,
+    Sequenced(Response),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Response {
+    Accepted {
+        timestamp: i64,
+        token: Token,
+        side: Side,
+        quantity: Quantity,
+        symbol: Symbol,
+        price: Price,
+        order_id: OrderId,
+    },
+    Replaced {
+        timestamp: i64,
+        token: Token,
+        quantity: Quantity,
+        price: Price,
+        previous: Token,
+    },
+    Executed {
+        timestamp: i64,
+        token: Token,
+        quantity: Quantity,
+        price: Price,
+        match_number: TradeId,
+        /// `A` when the order added liquidity, `R` when it removed it.
+        liquidity: u8,
+        leaves_quantity: Quantity,
+    },
+    Canceled {
+        timestamp: i64,
+        token: Token,
+        quantity: Quantity,
+        reason: u8,
+    },
+    Rejected {
+        timestamp: i64,
+        token: Token,
+        reason: u8,
+    },
+}
+
+impl ClientPacket {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(64);
+        match self {
+            ClientPacket::Login {
+                account,
+                credential,
+                sequence,
+            } => {
+                buf.push(b'L');
+                buf.extend(account.0.to_be_bytes());
+                buf.extend(credential);
+                buf.extend(sequence.to_be_bytes());
+            }
+            ClientPacket::Heartbeat => buf.push(b'R'),
+            ClientPacket::Logout => buf.push(b'O'),
+            ClientPacket::Order(request) => {
+                buf.push(b'U');
+                request.encode(&mut buf);
+            }
+        }
+        frame(buf)
+    }
+
+    pub fn decode(packet: &[u8]) -> Option<Self> {
+        let mut r = Reader(packet);
+        let packet = match r.u8()? {
+            b'L' => ClientPacket::Login {
+                account: AccountId(r.u64()?),
+                credential: r.bytes()?,
+                sequence: r.u64()?,
+            },
+            b'R' => ClientPacket::Heartbeat,
+            b'O' => ClientPacket::Logout,
+            b'U' => ClientPacket::Order(Request::decode(&mut r)?),
+            _ => return None,
+        };
+        r.finish(packet)
+    }
+}
+
+impl Request {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            Request::Enter {
+                token,
+                side,
+                quantity,
+                symbol,
+                price,
+                time_in_force,
+            } => {
+                buf.push(b'O');
+                buf.extend(token);
+                buf.push(side_code(*side));
+                buf.extend(quantity.0.to_be_bytes());
+                buf.extend(symbol.as_bytes());
+                buf.extend(price.0.to_be_bytes());
+                buf.push(time_in_force_code(*time_in_force));
+            }
+            Request::Replace {
+                existing,
+                replacement,
+                quantity,
+                price,
+            } => {
+                buf.push(b'U');
+                buf.extend(existing);
+                buf.extend(replacement);
+                buf.extend(quantity.0.to_be_bytes());
+                buf.extend(price.0.to_be_bytes());
+            }
+            Request::Cancel { token } => {
+                buf.push(b'X');
+                buf.extend(token);
+            }
+        }
+    }
+
+    fn decode(r: &mut Reader) -> Option<Self> {
+        Some(match r.u8()? {
+            b'O' => Request::Enter {
+                token: r.bytes()?,
+                side: side(r.u8()?)?,
+                quantity: Quantity(r.i64()?),
+                symbol: Symbol::try_from(&r.bytes::<{ Symbol::LEN }>()?[..]).ok()?,
+                price: Price(r.i64()?),
+                time_in_force: time_in_force(r.u8()?)?,
+            },
+            b'U' => Request::Replace {
+                existing: r.bytes()?,
+                replacement: r.bytes()?,
+                quantity: Quantity(r.i64()?),
+                price: Price(r.i64()?),
+            },
+            b'X' => Request::Cancel { token: r.bytes()? },
+            _ => return None,
+        })
+    }
+}
+
+impl ServerPacket {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(96);
+        match self {
+            ServerPacket::LoginAccepted { session, sequence } => {
+                buf.push(b'A');
+                buf.extend(session);
+                buf.extend(sequence.to_be_bytes());
+            }
+            ServerPacket::LoginRejected { reason } => {
+                buf.push(b'J');
+                buf.push(*reason);
+            }
+            ServerPacket::Heartbeat => buf.push(b'H'),
+            ServerPacket::Sequenced(response) => {
+                buf.push(b'S');
+                response.encode(&mut buf);
+            }
+        }
+        frame(buf)
+    }
+
+    pub fn decode(packet: &[u8]) -> Option<Self> {
+        let mut r = Reader(packet);
+        let packet = match r.u8()? {
+            b'A' => ServerPacket::LoginAccepted {
+                session: r.bytes()?,
+                sequence: r.u64()?,
+            },
+            b'J' => ServerPacket::LoginRejected { reason: r.u8()? },
+            b'H' => ServerPacket::Heartbeat,
+            b'S' => ServerPacket::Sequenced(Response::decode(&mut r)?),
+            _ => return None,
+        };
+        r.finish(packet)
+    }
+}
+
+impl Response {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            Response::Accepted {
+                timestamp,
+                token,
+                side,
+                quantity,
+                symbol,
+                price,
+                order_id,
+            } => {
+                buf.push(b'A');
+                buf.extend(timestamp.to_be_bytes());
+                buf.extend(token);
+                buf.push(side_code(*side));
+                buf.extend(quantity.0.to_be_bytes());
+                buf.extend(symbol.as_bytes());
+                buf.extend(price.0.to_be_bytes());
+                buf.extend(order_id.0.as_bytes());
+            }
+            Response::Replaced {
+                timestamp,
+                token,
+                quantity,
+                price,
+                previous,
+            } => {
+                buf.push(b'U');
+                buf.extend(timestamp.to_be_bytes());
+                buf.extend(token);
+                buf.extend(quantity.0.to_be_bytes());
+                buf.extend(price.0.to_be_bytes());
+                buf.extend(previous);
+            }
+            Response::Executed {
+                timestamp,
+                token,
+                quantity,
+                price,
+                match_number,
+                liquidity,
+                leaves_quantity,
+            } => {
+                buf.push(b'E');
+                buf.extend(timestamp.to_be_bytes());
+                buf.extend(token);
+                buf.extend(quantity.0.to_be_bytes());
+                buf.extend(price.0.to_be_bytes());
+                buf.extend(match_number.0.to_be_bytes());
+                buf.push(*liquidity);
+                buf.extend(leaves_quantity.0.to_be_bytes());
+            }
+            Response::Canceled {
+                timestamp,
+                token,
+                quantity,
+                reason,
+            } => {
+                buf.push(b'C');
+                buf.extend(timestamp.to_be_bytes());
+                buf.extend(token);
+                buf.extend(quantity.0.to_be_bytes());
+                buf.push(*reason);
+            }
+            Response::Rejected {
+                timestamp,
+                token,
+                reason,
+            } => {
+                buf.push(b'J');
+                buf.extend(timestamp.to_be_bytes());
+                buf.extend(token);
+                buf.push(*reason);
+            }
+        }
+    }
+
+    fn decode(r: &mut Reader) -> Option<Self> {
+        Some(match r.u8()? {
+            b'A' => Response::Accepted {
+                timestamp: r.i64()?,
+                token: r.bytes()?,
+                side: side(r.u8()?)?,
+                quantity: Quantity(r.i64()?),
+                symbol: Symbol::try_from(&r.bytes::<{ Symbol::LEN }>()?[..]).ok()?,
+                price: Price(r.i64()?),
+                order_id: OrderId(Uuid::from_bytes(r.bytes()?)),
+            },
+            b'U' => Response::Replaced {
+                timestamp: r.i64()?,
+                token: r.bytes()?,
+                quantity: Quantity(r.i64()?),
+                price: Price(r.i64()?),
+                previous: r.bytes()?,
+            },
+            b'E' => Response::Executed {
+                timestamp: r.i64()?,
+                token: r.bytes()?,
+                quantity: Quantity(r.i64()?),
+                price: Price(r.i64()?),
+                match_number: TradeId(r.u64()?),
+                liquidity: r.u8()?,
+                leaves_quantity: Quantity(r.i64()?),
+            },
+            b'C' => Response::Canceled {
+                timestamp: r.i64()?,
+                token: r.bytes()?,
+                quantity: Quantity(r.i64()?),
+                reason: r.u8()?,
+            },
+            b'J' => Response::Rejected {
+                timestamp: r.i64()?,
+                token: r.bytes()?,
+                reason: r.u8()?,
+            },
+            _ => return None,
+        })
+    }
+}
+
+/// Reads one packet without its length prefix.
+pub async fn read_packet<R: AsyncRead + Unpin>(reader: &mut R) -> std::io::Result<Vec<u8>> {
+    let len = reader.read_u16().await?;
+    let mut packet = vec![0; len as usize];
+    reader.read_exact(&mut packet).await?;
+    Ok(packet)
+}
+
+/// Pads `value` with spaces, e.g. for tokens and credentials.
+pub fn padded<const N: usize>(value: &str) -> [u8; N] {
+    let mut bytes = [b' '; N];
+    let len = value.len().min(N);
+    bytes[..len].copy_from_slice(&value.as_bytes()[..len]);
+    bytes
+}
+
+fn frame(buf: Vec<u8>) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(buf.len() + 2);
+    packet.extend((buf.len() as u16).to_be_bytes());
+    packet.extend(buf);
+    packet
+}
+
+fn time_in_force_code(time_in_force: TimeInForce) -> u8 {
+    match time_in_force {
+        TimeInForce::Day => b'D',
+        TimeInForce::Gtc => b'G',
+        TimeInForce::Ioc => b'I',
+    }
+}
+
+fn time_in_force(code: u8) -> Option<TimeInForce> {
+    match code {
+        b'D' => Some(TimeInForce::Day),
+        b'G' => Some(TimeInForce::Gtc),
+        b'I' => Some(TimeInForce::Ioc),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode_framed<T>(packet: Vec<u8>, decode: impl Fn(&[u8]) -> Option<T>) -> Option<T> {
+        let len = u16::from_be_bytes([packet[0], packet[1]]) as usize;
+        assert_eq!(len, packet.len() - 2, "Length prefix mismatch");
+        decode(&packet[2..])
+    }
+
+    #[test]
+    fn client_packets_round_trip() {
+        let packets = [
+            ClientPacket::Login {
+                account: AccountId(42),
+                credential: padded("secret"),
+                sequence: 7,
+            },
+            ClientPacket::Heartbeat,
+            ClientPacket::Order(Request::Enter {
+                token: padded("T1"),
+                side: Side::Sell,
+                quantity: Quantity(10),
+                symbol: "ABC".parse().unwrap(),
+                price: Price(250),
+                time_in_force: TimeInForce::Ioc,
+            }),
+            ClientPacket::Order(Request::Replace {
+                existing: padded("T1"),
+                replacement: padded("T2"),
+                quantity: Quantity(5),
+                price: Price(251),
+            }),
+            ClientPacket::Order(Request::Cancel {
+                token: padded("T2"),
+            }),
+        ];
+
+        for packet in packets {
+            assert_eq!(
+                decode_framed(packet.encode(), ClientPacket::decode),
+                Some(packet)
+            );
+        }
+    }
+
+    #[test]
+    fn server_packets_round_trip() {
+        let packets = [
+            ServerPacket::LoginAccepted {
+                session: padded("42"),
+                sequence: 1,
+            },
+            ServerPacket::Sequenced(Response::Executed {
+                timestamp: 1,
+                token: padded("T1"),
+                quantity: Quantity(4),
+                price: Price(250),
+                match_number: TradeId(9),
+                liquidity: b'A',
+                leaves_quantity: Quantity(6),
+            }),
+            ServerPacket::Sequenced(Response::Accepted {
+                timestamp: 1,
+                token: padded("T1"),
+                side: Side::Buy,
+                quantity: Quantity(10),
+                symbol: "ABC".parse().unwrap(),
+                price: Price(250),
+                order_id: OrderId(Uuid::new_v4()),
+            }),
+        ];
+
+        for packet in packets {
+            assert_eq!(
+                decode_framed(packet.encode(), ServerPacket::decode),
+                Some(packet)
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_truncated_and_oversized_packets() {
+        let packet = ClientPacket::Order(Request::Cancel {
+            token: padded("T1"),
+        })
+        .encode();
+
+        assert_eq!(ClientPacket::decode(&packet[2..packet.len() - 1]), None);
+
+        let mut oversized = packet[2..].to_vec();
+        oversized.push(0);
+        assert_eq!(ClientPacket::decode(&oversized), None);
+    }
+}
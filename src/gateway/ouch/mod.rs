@@ -0,0 +1,183 @@
+//! Binary order entry gateway modeled on OUCH over SoupBinTCP.
+//!
+//! A client logs in with its account credential and then sends Enter,
+//! Replace and Cancel requests. Accepted, Replaced, Executed, Canceled and
+//! Rejected responses are sequenced per account and can be replayed by
+//! logging in with an earlier sequence number.
+
+pub mod messages;
+mod session;
+
+use crate::configuration::OuchSettings;
+use crate::gateway::ouch::messages::{
+    CREDENTIAL_LEN, ClientPacket, SESSION_LEN, ServerPacket, padded, read_packet,
+};
+use crate::gateway::ouch::session::Sessions;
+use crate::matching::state::AppState;
+use log::{debug, info, warn};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+
+const LOGIN_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub const LOGIN_NOT_AUTHORIZED: u8 = b'A';
+pub const LOGIN_SESSION_UNAVAILABLE: u8 = b'S';
+
+pub async fn serve(listener: TcpListener, state: AppState, settings: Arc<OuchSettings>) {
+    let sessions = Arc::new(Sessions::default());
+
+    loop {
+        match listener.accept().await {
+            Ok((stream, peer)) => {
+                debug!("Binary gateway connection from {peer}");
+                let (state, settings, sessions) =
+                    (state.clone(), settings.clone(), sessions.clone());
+                tokio::spawn(async move {
+                    if let Err(e) = connection(stream, state, settings, sessions).await {
+                        debug!("Binary gateway connection from {peer} closed: {e}");
+                    }
+                });
+            }
+            Err(e) => warn!("Failed to accept binary gateway connection: {e}"),
+        }
+    }
+}
+
+async fn connection(
+    stream: TcpStream,
+    state: AppState,
+    settings: Arc<OuchSettings>,
+    sessions: Arc<Sessions>,
+) -> std::io::Result<()> {
+    stream.set_nodelay(true)?;
+    let (mut reader, mut writer) = stream.into_split();
+
+    let login = tokio::time::timeout(LOGIN_TIMEOUT, read_packet(&mut reader))
+        .await
+        .map_err(|_| std::io::ErrorKind::TimedOut)??;
+    let Some(ClientPacket::Login {
+        account,
+        credential,
+        sequence,
+    }) = ClientPacket::decode(&login)
+    else {
+        return Err(std::io::ErrorKind::InvalidData.into());
+    };
+
+    let authorized = settings
+        .credentials
+        .iter()
+        .any(|c| c.account == account && matches(&credential, &c.password));
+    if !authorized {
+        warn!("Rejected binary gateway login of {account:?}");
+        let rejected = ServerPacket::LoginRejected {
+            reason: LOGIN_NOT_AUTHORIZED,
+        };
+        writer.write_all(&rejected.encode()).await?;
+        return Ok(());
+    }
+
+    let session = sessions.get_or_start(account, &state);
+    let Ok(_connected) = session.connected.try_lock() else {
+        warn!("Binary gateway session of {account:?} is already logged in");
+        let rejected = ServerPacket::LoginRejected {
+            reason: LOGIN_SESSION_UNAVAILABLE,
+        };
+        writer.write_all(&rejected.encode()).await?;
+        return Ok(());
+    };
+    let end = session.next_sequence();
+    let mut next = match sequence {
+        0 => end,
+        requested => requested.clamp(session.first_sequence(), end),
+    };
+    // a client ahead of the log cannot have received what it would confirm
+    if sequence <= end {
+        session.acknowledge(next);
+    }
+    let accepted = ServerPacket::LoginAccepted {
+        session: padded::<SESSION_LEN>(&account.0.to_string()),
+        sequence: next,
+    };
+    writer.write_all(&accepted.encode()).await?;
+    info!("Binary gateway session of {account:?} started at {next}");
+
+    // Reads run in their own task, a read cancelled halfway by select! would
+    // lose the start of a packet.
+    let (packets_tx, mut packets) = mpsc::channel(64);
+    let read_task = tokio::spawn(async move {
+        while let Ok(packet) = read_packet(&mut reader).await {
+            if packets_tx.send(packet).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    let interval = Duration::from_millis(settings.heartbeat_ms);
+    let mut last_sent = Instant::now();
+    let mut last_received = Instant::now();
+
+    let result = loop {
+        let appended = session.appended();
+        let (first, missed) = session.packets_from(next);
+        if first > next {
+            // the client logs in again and learns where the log resumes
+            warn!("Binary gateway client {account:?} fell behind the session log at {next}");
+            break Ok(());
+        }
+        for packet in missed {
+            writer.write_all(&packet).await?;
+            next += 1;
+            last_sent = Instant::now();
+        }
+
+        tokio::select! {
+            _ = appended => {}
+            packet = packets.recv() => {
+                let Some(packet) = packet else {
+                    break Ok(());
+                };
+                last_received = Instant::now();
+                match ClientPacket::decode(&packet) {
+                    Some(ClientPacket::Order(request)) => {
+                        if !session.submit(request).await {
+                            break Ok(());
+                        }
+                    }
+                    Some(ClientPacket::Heartbeat) => {}
+                    Some(ClientPacket::Logout) => break Ok(()),
+                    Some(ClientPacket::Login { .. }) | None => {
+                        break Err(std::io::ErrorKind::InvalidData.into());
+                    }
+                }
+            }
+            _ = tokio::time::sleep_until(last_sent + interval) => {
+                if last_received.elapsed() > interval * 3 {
+                    info!("Binary gateway client {account:?} timed out");
+                    break Ok(());
+                }
+                writer.write_all(&ServerPacket::Heartbeat.encode()).await?;
+                last_sent = Instant::now();
+            }
+        }
+    };
+
+    read_task.abort();
+    info!("Binary gateway session of {account:?} ended at {next}");
+    result
+}
+
+/// Compares every byte so the response time does not leak the password.
+fn matches(credential: &[u8; CREDENTIAL_LEN], password: &str) -> bool {
+    let expected = padded::<CREDENTIAL_LEN>(password);
+    password.len() <= CREDENTIAL_LEN
+        && credential
+            .iter()
+            .zip(expected.iter())
+            .fold(0, |acc, (a, b)| acc | (a ^ b))
+            == 0
+}
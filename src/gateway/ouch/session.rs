@@ -0,0 +1,397 @@
+use crate::domain::account::AccountId;
+use crate::domain::fill::{Fill, Liquidity};
+use crate::domain::order::{Order, OrderId, Revision};
+use crate::domain::order_change::CancelReason;
+use crate::domain::order_entry::OrderEntry;
+use crate::domain::trade::now_unix_ns;
+use crate::gateway::ouch::messages::{Request, Response, ServerPacket, Token};
+use crate::matching::command::{CommandResult, MatchingEngineCommand};
+use crate::matching::engine::MarketEvent;
+use crate::matching::reject::{Reject, RejectCode};
+use crate::matching::state::AppState;
+use log::debug;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use tokio::sync::{Notify, mpsc};
+
+const INBOX_SIZE: usize = 1_024;
+/// Messages kept for replay. Older ones are dropped, a client asking for
+/// them resumes at the oldest message still kept.
+const LOG_CAPACITY: usize = 100_000;
+
+/// Sequenced session of one account. It outlives the connections of the
+/// account, so a client that reconnects can ask for the messages it missed.
+pub struct Session {
+    inbox: mpsc::Sender<Request>,
+    log: Mutex<Log>,
+    appended: Notify,
+    /// Held by the connection that is logged in, which keeps a second login
+    /// of the same account out.
+    pub connected: tokio::sync::Mutex<()>,
+}
+
+/// Encoded packets of a session, `packets[0]` has sequence number `first`.
+struct Log {
+    first: u64,
+    packets: VecDeque<Vec<u8>>,
+}
+
+impl Default for Log {
+    fn default() -> Self {
+        Log {
+            first: 1,
+            packets: VecDeque::new(),
+        }
+    }
+}
+
+impl Session {
+    fn new(inbox: mpsc::Sender<Request>) -> Self {
+        Session {
+            inbox,
+            log: Mutex::default(),
+            appended: Notify::new(),
+            connected: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    pub async fn submit(&self, request: Request) -> bool {
+        self.inbox.send(request).await.is_ok()
+    }
+
+    /// Sequence number of the next message, sequences start at 1.
+    pub fn next_sequence(&self) -> u64 {
+        let log = self.log.lock().expect("Session log poisoned");
+        log.first + log.packets.len() as u64
+    }
+
+    /// Sequence number of the oldest message kept for replay.
+    pub fn first_sequence(&self) -> u64 {
+        self.log.lock().expect("Session log poisoned").first
+    }
+
+    /// Encoded packets from `sequence` on, or from the oldest one kept, and
+    /// the sequence number of the first of them.
+    pub fn packets_from(&self, sequence: u64) -> (u64, Vec<Vec<u8>>) {
+        let log = self.log.lock().expect("Session log poisoned");
+        let first = sequence.max(log.first);
+        let start = (first - log.first) as usize;
+        let packets = log.packets.range(start.min(log.packets.len())..);
+        (first, packets.cloned().collect())
+    }
+
+    /// Drops the packets before `sequence`, which the client confirmed to
+    /// have received by asking for the ones after.
+    pub fn acknowledge(&self, sequence: u64) {
+        let mut log = self.log.lock().expect("Session log poisoned");
+        let received = (sequence.saturating_sub(log.first) as usize).min(log.packets.len());
+        log.packets.drain(..received);
+        log.first += received as u64;
+    }
+
+    /// Resolves once a message is appended after the call.
+    pub fn appended(&self) -> tokio::sync::futures::Notified<'_> {
+        self.appended.notified()
+    }
+
+    fn append(&self, response: Response) {
+        let packet = ServerPacket::Sequenced(response).encode();
+        let mut log = self.log.lock().expect("Session log poisoned");
+        log.packets.push_back(packet);
+        if log.packets.len() > LOG_CAPACITY {
+            log.packets.pop_front();
+            log.first += 1;
+        }
+        drop(log);
+        self.appended.notify_waiters();
+    }
+}
+
+#[derive(Default)]
+pub struct Sessions {
+    sessions: Mutex<HashMap<AccountId, Arc<Session>>>,
+}
+
+impl Sessions {
+    /// Returns the session of the account, starting it on the first login.
+    pub fn get_or_start(&self, account: AccountId, state: &AppState) -> Arc<Session> {
+        let mut sessions = self.sessions.lock().expect("Sessions poisoned");
+        sessions
+            .entry(account)
+            .or_insert_with(|| {
+                let (inbox, requests) = mpsc::channel(INBOX_SIZE);
+                let session = Arc::new(Session::new(inbox));
+                let worker = SessionWorker {
+                    account,
+                    state: state.clone(),
+                    session: session.clone(),
+                    tokens: HashMap::new(),
+                    orders: HashMap::new(),
+                };
+                tokio::spawn(worker.run(requests));
+                session
+            })
+            .clone()
+    }
+}
+
+/// Turns requests into engine commands and engine events into responses.
+/// Requests are handled one at a time, and the events they caused are
+/// buffered in the subscription meanwhile, so an order is always accepted
+/// before it is reported as executed. The subscription to the events of the
+/// account is lossless, a slow session never misses an execution.
+struct SessionWorker {
+    account: AccountId,
+    state: AppState,
+    session: Arc<Session>,
+    tokens: HashMap<Token, (OrderId, Revision)>,
+    orders: HashMap<OrderId, Token>,
+}
+
+impl SessionWorker {
+    async fn run(mut self, mut requests: mpsc::Receiver<Request>) {
+        let Some(mut events) = self.state.subscribe(self.account).await else {
+            return;
+        };
+
+        loop {
+            // Pending events go first, they may carry revisions the next
+            // request needs.
+            tokio::select! {
+                biased;
+                event = events.recv() => match event {
+                    Some(event) => self.on_event(event),
+                    None => break,
+                },
+                request = requests.recv() => match request {
+                    Some(request) => self.handle(request).await,
+                    None => break,
+                },
+            }
+        }
+    }
+
+    async fn handle(&mut self, request: Request) {
+        match request {
+            Request::Enter {
+                token,
+                side,
+                quantity,
+                symbol,
+                price,
+                time_in_force,
+            } => {
+                if self.tokens.contains_key(&token) {
+                    return self.reject(token, RejectCode::InvalidRequest);
+                }
+
+                let entry = OrderEntry::new(price, quantity, side)
+                    .with_symbol(symbol)
+                    .with_account(self.account)
+                    .with_time_in_force(time_in_force);
+                match self.submit(MatchingEngineCommand::Create(entry)).await {
+                    Ok(order) => {
+                        self.track(token, &order);
+                        self.session.append(Response::Accepted {
+                            timestamp: now_unix_ns(),
+                            token,
+                            side,
+                            quantity,
+                            symbol,
+                            price,
+                            order_id: order.id,
+                        });
+                    }
+                    Err(reject) => self.reject(token, reject.code),
+                }
+            }
+            Request::Replace {
+                existing,
+                replacement,
+                quantity,
+                price,
+            } => {
+                let Some(&(id, revision)) = self.tokens.get(&existing) else {
+                    return self.reject(existing, RejectCode::NotFound);
+                };
+                if self.tokens.contains_key(&replacement) {
+                    return self.reject(replacement, RejectCode::InvalidRequest);
+                }
+
+                let cmd = MatchingEngineCommand::Modify(
+                    id,
+                    revision,
+                    Some(price),
+                    Some(quantity),
+                    Some(self.account),
+                );
+                match self.submit(cmd).await {
+                    Ok(order) => {
+                        self.tokens.remove(&existing);
+                        self.track(replacement, &order);
+                        self.session.append(Response::Replaced {
+                            timestamp: now_unix_ns(),
+                            token: replacement,
+                            quantity,
+                            price,
+                            previous: existing,
+                        });
+                    }
+                    Err(reject) => self.reject(existing, reject.code),
+                }
+            }
+            Request::Cancel { token } => {
+                let Some(&(id, revision)) = self.tokens.get(&token) else {
+                    return self.reject(token, RejectCode::NotFound);
+                };
+                // the cancel is reported from the OrderDeleted event
+                if let Err(reject) = self
+                    .submit(MatchingEngineCommand::Delete(
+                        id,
+                        revision,
+                        Some(self.account),
+                    ))
+                    .await
+                {
+                    self.reject(token, reject.code);
+                }
+            }
+        }
+    }
+
+    fn on_event(&mut self, event: MarketEvent) {
+        match event {
+            MarketEvent::Fill(fill) if fill.account == self.account => self.on_fill(fill),
+            MarketEvent::OrderDeleted { order, reason } if order.account == self.account => {
+                let Some(token) = self.untrack(order.id) else {
+                    return;
+                };
+                self.session.append(Response::Canceled {
+                    timestamp: now_unix_ns(),
+                    token,
+                    quantity: order.quantity,
+                    reason: cancel_reason(reason),
+                });
+            }
+            _ => {}
+        }
+    }
+
+    fn on_fill(&mut self, fill: Fill) {
+        let Some(&token) = self.orders.get(&fill.order_id) else {
+            return;
+        };
+
+        if fill.leaves_quantity.0 == 0 {
+            self.untrack(fill.order_id);
+        } else if let Some((_, revision)) = self.tokens.get_mut(&token) {
+            // events queued before a replace carry older revisions
+            *revision = fill.revision.max(*revision);
+        }
+
+        self.session.append(Response::Executed {
+            timestamp: fill.exec_time,
+            token,
+            quantity: fill.quantity,
+            price: fill.price,
+            match_number: fill.trade_id,
+            liquidity: match fill.liquidity {
+                Liquidity::Maker => b'A',
+                Liquidity::Taker => b'R',
+            },
+            leaves_quantity: fill.leaves_quantity,
+        });
+    }
+
+    async fn submit(&self, cmd: MatchingEngineCommand) -> CommandResult {
+        if let Err(throttled) = self.state.rate_limits.check(None, Some(self.account), 1) {
+            debug!("Throttled {:?}: {throttled:?}", self.account);
+            return Err(Reject::new(RejectCode::RateLimited, "Rate limited"));
+        }
+
+        self.state.request(cmd).await
+    }
+
+    fn track(&mut self, token: Token, order: &Order) {
+        self.tokens.insert(token, (order.id, order.revision));
+        self.orders.insert(order.id, token);
+    }
+
+    fn untrack(&mut self, id: OrderId) -> Option<Token> {
+        let token = self.orders.remove(&id)?;
+        self.tokens.remove(&token);
+        Some(token)
+    }
+
+    fn reject(&self, token: Token, code: RejectCode) {
+        self.session.append(Response::Rejected {
+            timestamp: now_unix_ns(),
+            token,
+            reason: reject_reason(code),
+        });
+    }
+}
+
+pub fn reject_reason(code: RejectCode) -> u8 {
+    match code {
+        RejectCode::NotFound => b'N',
+        RejectCode::InvalidPrice => b'X',
+        RejectCode::InvalidQuantity => b'Z',
+        RejectCode::RiskReject => b'R',
+        RejectCode::Halted => b'H',
+        RejectCode::EngineUnavailable => b'U',
+        RejectCode::InvalidRequest => b'I',
+        RejectCode::RateLimited => b'L',
+        RejectCode::BatchAborted => b'B',
+        RejectCode::PhaseRestricted => b'P',
+    }
+}
+
+fn cancel_reason(reason: CancelReason) -> u8 {
+    match reason {
+        CancelReason::UserCancel => b'U',
+        CancelReason::MassCancel => b'M',
+        CancelReason::CancelOnDisconnect => b'D',
+        CancelReason::Expired => b'E',
+        CancelReason::Unfilled => b'I',
+        CancelReason::System => b'S',
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session_with(responses: usize) -> Session {
+        let session = Session::new(mpsc::channel(1).0);
+        for _ in 0..responses {
+            session.append(Response::Rejected {
+                timestamp: 0,
+                token: [b' '; 14],
+                reason: b'N',
+            });
+        }
+        session
+    }
+
+    #[test]
+    fn the_log_keeps_the_latest_responses() {
+        let session = session_with(LOG_CAPACITY + 2);
+
+        assert_eq!(session.next_sequence(), LOG_CAPACITY as u64 + 3);
+        let (first, packets) = session.packets_from(1);
+        assert_eq!(first, 3);
+        assert_eq!(packets.len(), LOG_CAPACITY);
+    }
+
+    #[test]
+    fn acknowledged_responses_are_dropped() {
+        let session = session_with(5);
+
+        session.acknowledge(4);
+
+        assert_eq!(session.first_sequence(), 4);
+        assert_eq!(session.packets_from(1).1.len(), 2);
+        assert_eq!(session.next_sequence(), 6);
+    }
+}
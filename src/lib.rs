@@ -1,5 +1,9 @@
+pub mod auth;
 pub mod configuration;
 pub mod domain;
+pub mod gateway;
+pub mod market_data;
 pub mod matching;
+pub mod rate_limit;
 mod routes;
 pub mod startup;
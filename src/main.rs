@@ -1,6 +1,7 @@
 use exchange::configuration::get_configuration;
-use exchange::matching::engine::{MarketEvent, matching_engine};
+use exchange::matching::engine::{MarketEvent, MatchingEngine};
 use exchange::matching::state::AppState;
+use exchange::rate_limit::RateLimits;
 use exchange::startup::run;
 use std::net::TcpListener;
 use tokio::sync::{broadcast, mpsc};
@@ -14,8 +15,21 @@ async fn main() -> std::io::Result<()> {
 
     let (ws_tx, _) = broadcast::channel::<MarketEvent>(1000);
 
-    tokio::spawn(matching_engine(rx, ws_tx.clone()));
+    let engine = MatchingEngine::new(ws_tx.clone())
+        .with_instruments(configuration.instruments)
+        .with_fees(configuration.fees);
+    tokio::spawn(engine.run(rx));
 
-    let state = AppState { tx, ws_tx };
+    let state = AppState::new(tx, ws_tx)
+        .with_admin_token(configuration.application.admin_token)
+        .with_api_keys(configuration.application.api_keys)
+        .with_ouch(configuration.application.ouch)
+        .with_fix(configuration.application.fix)
+        .with_itch(configuration.application.itch)
+        .with_candles(configuration.application.candles)
+        .with_trade_history(configuration.application.trades)
+        .with_ticker(configuration.application.ticker)
+        .with_depth(configuration.application.depth)
+        .with_rate_limits(RateLimits::new(configuration.application.rate_limit));
     run(listener, state)?.await
 }
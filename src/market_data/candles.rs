@@ -0,0 +1,395 @@
+//! OHLCV bars of the trades of every instrument.
+//!
+//! Every trade updates the bar in progress of each interval. Finished bars
+//! move into a ring of the latest `capacity` bars per instrument and
+//! interval. Bars leaving the ring are dropped, or appended to a JSON lines
+//! file per series when a spill directory is configured. Files are written
+//! and read on blocking threads, never while holding the series lock.
+
+use crate::configuration::CandleSettings;
+use crate::domain::order::{Price, Quantity};
+use crate::domain::symbol::Symbol;
+use crate::matching::engine::{EventBatch, MarketEvent};
+use crate::routes::encoding::Encoded;
+use log::{error, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tokio::sync::{broadcast, mpsc};
+
+const UPDATE_BUFFER: usize = 1_000;
+
+const SECOND: i64 = 1_000_000_000;
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Interval {
+    #[serde(rename = "1s")]
+    OneSecond,
+    #[serde(rename = "1m")]
+    OneMinute,
+    #[serde(rename = "5m")]
+    FiveMinutes,
+    #[serde(rename = "1h")]
+    OneHour,
+    #[serde(rename = "1d")]
+    OneDay,
+}
+
+impl Interval {
+    pub const ALL: [Interval; 5] = [
+        Interval::OneSecond,
+        Interval::OneMinute,
+        Interval::FiveMinutes,
+        Interval::OneHour,
+        Interval::OneDay,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Interval::OneSecond => "1s",
+            Interval::OneMinute => "1m",
+            Interval::FiveMinutes => "5m",
+            Interval::OneHour => "1h",
+            Interval::OneDay => "1d",
+        }
+    }
+
+    pub fn nanos(&self) -> i64 {
+        match self {
+            Interval::OneSecond => SECOND,
+            Interval::OneMinute => 60 * SECOND,
+            Interval::FiveMinutes => 300 * SECOND,
+            Interval::OneHour => 3_600 * SECOND,
+            Interval::OneDay => 86_400 * SECOND,
+        }
+    }
+
+    /// Start of the bar containing `time`, bars are aligned to the epoch.
+    pub fn open_time(&self, time: i64) -> i64 {
+        time - time.rem_euclid(self.nanos())
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct Candle {
+    pub symbol: Symbol,
+    pub interval: Interval,
+    /// Start of the bar in unix nanoseconds.
+    pub open_time: i64,
+    pub open: Price,
+    pub high: Price,
+    pub low: Price,
+    pub close: Price,
+    /// Traded quantity.
+    pub volume: Quantity,
+    pub trades: u64,
+}
+
+impl Candle {
+    /// A bar opened by a trade.
+    fn new(
+        symbol: Symbol,
+        interval: Interval,
+        open_time: i64,
+        price: Price,
+        quantity: Quantity,
+    ) -> Self {
+        Candle {
+            symbol,
+            interval,
+            open_time,
+            open: price,
+            high: price,
+            low: price,
+            close: price,
+            volume: quantity,
+            trades: 1,
+        }
+    }
+
+    fn add(&mut self, price: Price, quantity: Quantity) {
+        self.high = self.high.max(price);
+        self.low = self.low.min(price);
+        self.close = price;
+        self.volume.0 += quantity.0;
+        self.trades += 1;
+    }
+}
+
+#[derive(Default)]
+struct Series {
+    /// Finished bars, oldest first.
+    closed: VecDeque<Candle>,
+    current: Option<Candle>,
+}
+
+pub struct CandleStore {
+    capacity: usize,
+    spill_dir: Option<PathBuf>,
+    series: Mutex<HashMap<(Symbol, Interval), Series>>,
+    /// Every change of a bar in progress.
+    updates: broadcast::Sender<Arc<Encoded<Candle>>>,
+}
+
+impl CandleStore {
+    pub fn new(settings: &CandleSettings) -> Self {
+        let (updates, _) = broadcast::channel(UPDATE_BUFFER);
+        CandleStore {
+            capacity: settings.capacity.max(1),
+            spill_dir: settings.spill_dir.as_ref().map(PathBuf::from),
+            series: Mutex::default(),
+            updates,
+        }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Arc<Encoded<Candle>>> {
+        self.updates.subscribe()
+    }
+
+    /// Adds a trade to the bars of every interval and publishes them.
+    /// Returns the bars that left their ring, to be passed to `spill`.
+    pub fn record(
+        &self,
+        symbol: Symbol,
+        price: Price,
+        quantity: Quantity,
+        time: i64,
+    ) -> Vec<Candle> {
+        let mut evicted = Vec::new();
+        let mut series = self.series.lock().expect("Candle store poisoned");
+        for interval in Interval::ALL {
+            let series = series.entry((symbol, interval)).or_default();
+            let open_time = interval.open_time(time);
+
+            let finished = match &mut series.current {
+                // a trade stamped before the bar in progress still counts
+                // toward it, finished bars never change
+                Some(candle) if candle.open_time >= open_time => {
+                    candle.add(price, quantity);
+                    None
+                }
+                current => {
+                    current.replace(Candle::new(symbol, interval, open_time, price, quantity))
+                }
+            };
+            if let Some(finished) = finished {
+                series.closed.push_back(finished);
+                if series.closed.len() > self.capacity {
+                    evicted.extend(series.closed.pop_front());
+                }
+            }
+
+            let update = series.current.clone().expect("Bar was just updated");
+            // nobody may be listening
+            drop(self.updates.send(Arc::new(Encoded::new(update))));
+        }
+        evicted
+    }
+
+    /// Bars of the series opened within `from..to`, oldest first, including
+    /// the bar in progress. Spilled bars are read when the range starts
+    /// before the bars kept in memory.
+    pub fn range(
+        &self,
+        symbol: Symbol,
+        interval: Interval,
+        from: i64,
+        to: i64,
+    ) -> std::io::Result<Vec<Candle>> {
+        let in_range = |candle: &&Candle| (from..to).contains(&candle.open_time);
+        let (oldest, mut kept) = {
+            let series = self.series.lock().expect("Candle store poisoned");
+            match series.get(&(symbol, interval)) {
+                Some(series) => {
+                    let kept = series.closed.iter().chain(&series.current);
+                    let oldest = kept.clone().next().map(|candle| candle.open_time);
+                    (oldest, kept.filter(in_range).cloned().collect())
+                }
+                None => (None, Vec::new()),
+            }
+        };
+
+        let mut candles = match &self.spill_dir {
+            Some(dir) if oldest.is_none_or(|oldest| from < oldest) => {
+                let to = oldest.map_or(to, |oldest| to.min(oldest));
+                read_spilled(&spill_path(dir, symbol, interval), from, to)?
+            }
+            _ => Vec::new(),
+        };
+        candles.append(&mut kept);
+        Ok(candles)
+    }
+
+    /// Appends bars that left their ring to the files of their series, or
+    /// drops them without a spill directory. Blocks on the file system.
+    pub fn spill(&self, candles: &[Candle]) {
+        let Some(dir) = &self.spill_dir else {
+            return;
+        };
+        let written = std::fs::create_dir_all(dir).and_then(|_| {
+            for candle in candles {
+                let mut line = serde_json::to_vec(candle)?;
+                line.push(b'\n');
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(spill_path(dir, candle.symbol, candle.interval))?
+                    .write_all(&line)?;
+            }
+            Ok(())
+        });
+        if let Err(e) = written {
+            warn!("Failed to spill candles to {dir:?}: {e}");
+        }
+    }
+}
+
+fn spill_path(dir: &Path, symbol: Symbol, interval: Interval) -> PathBuf {
+    dir.join(format!("{}-{}.jsonl", symbol.as_str(), interval.as_str()))
+}
+
+fn read_spilled(path: &Path, from: i64, to: i64) -> std::io::Result<Vec<Candle>> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+
+    let mut reader = BufReader::new(file);
+    let mut line = Vec::new();
+    let mut candles = Vec::new();
+    // a line without its newline is still being appended
+    while reader.read_until(b'\n', &mut line)? > 0 && line.ends_with(b"\n") {
+        let candle: Candle = serde_json::from_slice(&line)?;
+        if (from..to).contains(&candle.open_time) {
+            candles.push(candle);
+        }
+        line.clear();
+    }
+    Ok(candles)
+}
+
+/// Feeds the trades of the engine into the store. Takes the lossless feed,
+/// a missed trade would leave its bars wrong for good.
+pub async fn aggregate(mut batches: mpsc::UnboundedReceiver<EventBatch>, store: Arc<CandleStore>) {
+    while let Some(batch) = batches.recv().await {
+        let mut evicted = Vec::new();
+        for event in &batch.events {
+            if let MarketEvent::TradeExecuted(trade) = event {
+                evicted.extend(store.record(
+                    trade.symbol,
+                    trade.price,
+                    trade.quantity,
+                    trade.exec_time(),
+                ));
+            }
+        }
+        if evicted.is_empty() {
+            continue;
+        }
+
+        // waiting keeps the files in the order the bars closed
+        let store = store.clone();
+        if let Err(e) = tokio::task::spawn_blocking(move || store.spill(&evicted)).await {
+            error!("Failed to spill candles: {e}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store(capacity: usize, spill_dir: Option<&Path>) -> CandleStore {
+        CandleStore::new(&CandleSettings {
+            capacity,
+            spill_dir: spill_dir.map(|dir| dir.to_string_lossy().into_owned()),
+        })
+    }
+
+    fn record(store: &CandleStore, price: i64, quantity: i64, seconds: i64) {
+        let evicted = store.record(
+            Symbol::default(),
+            Price(price),
+            Quantity(quantity),
+            seconds * SECOND,
+        );
+        store.spill(&evicted);
+    }
+
+    fn range(store: &CandleStore, interval: Interval, from: i64, to: i64) -> Vec<Candle> {
+        store
+            .range(Symbol::default(), interval, from * SECOND, to * SECOND)
+            .unwrap()
+    }
+
+    #[test]
+    fn trades_are_aggregated_per_interval() {
+        let store = store(10, None);
+        let mut updates = store.subscribe();
+
+        record(&store, 100, 5, 60);
+        record(&store, 105, 1, 61);
+        record(&store, 95, 2, 61);
+        record(&store, 101, 3, 120);
+
+        let minutes = range(&store, Interval::OneMinute, 0, 1_000);
+        assert_eq!(minutes.len(), 2);
+        let first = &minutes[0];
+        assert_eq!(first.open_time, 60 * SECOND);
+        assert_eq!(
+            (first.open, first.high, first.low, first.close),
+            (Price(100), Price(105), Price(95), Price(95))
+        );
+        assert_eq!((first.volume, first.trades), (Quantity(8), 3));
+        assert_eq!(minutes[1].open, Price(101));
+
+        let five_minutes = range(&store, Interval::FiveMinutes, 0, 1_000);
+        assert_eq!(five_minutes.len(), 1);
+        assert_eq!(five_minutes[0].volume, Quantity(11));
+
+        assert_eq!(range(&store, Interval::OneSecond, 61, 62).len(), 1);
+
+        let update = updates.try_recv().unwrap();
+        assert_eq!(update.event.interval, Interval::OneSecond);
+        assert_eq!(update.event.close, Price(100));
+    }
+
+    #[test]
+    fn bars_leaving_the_ring_are_spilled_to_disk() {
+        let dir = std::env::temp_dir().join(format!("candles-{}", uuid::Uuid::new_v4()));
+        let store = store(2, Some(&dir));
+
+        for second in 0..5 {
+            record(&store, 100 + second, 1, second);
+        }
+
+        let opens = |candles: Vec<Candle>| candles.iter().map(|c| c.open.0).collect::<Vec<_>>();
+        assert_eq!(
+            opens(range(&store, Interval::OneSecond, 0, 10)),
+            [100, 101, 102, 103, 104]
+        );
+        assert_eq!(opens(range(&store, Interval::OneSecond, 1, 3)), [101, 102]);
+        assert_eq!(opens(range(&store, Interval::OneSecond, 3, 10)), [103, 104]);
+
+        // a bar being appended is not read yet
+        let path = spill_path(&dir, Symbol::default(), Interval::OneSecond);
+        let mut file = OpenOptions::new().append(true).open(path).unwrap();
+        file.write_all(b"{\"symbol\":").unwrap();
+        assert_eq!(opens(range(&store, Interval::OneSecond, 0, 2)), [100, 101]);
+
+        let without_spill = self::store(2, None);
+        for second in 0..5 {
+            record(&without_spill, 100 + second, 1, second);
+        }
+        assert_eq!(
+            opens(range(&without_spill, Interval::OneSecond, 0, 10)),
+            [102, 103, 104]
+        );
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
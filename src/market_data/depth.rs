@@ -0,0 +1,331 @@
+//! Price levels of the books, conflated per subscriber.
+//!
+//! The store keeps the quantity of every price level. Each subscriber
+//! collects the latest quantity of the levels that changed since it was last
+//! served and receives them at most once per interval, so a slow consumer
+//! sees the book with a delay instead of missing changes. A new subscriber
+//! starts with every level of the book. The store follows the lossless feed
+//! of the engine and applies the events of a command at once, so
+//! subscribers never see an aggressor resting before its trades.
+
+use crate::configuration::DepthSettings;
+use crate::domain::order::{OrderId, Price, Quantity};
+use crate::domain::order_book_level::OrderBookLevel;
+use crate::domain::side::Side;
+use crate::domain::symbol::Symbol;
+use crate::market_data::l3::{L3Message, messages};
+use crate::matching::engine::EventBatch;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct DepthUpdate {
+    pub symbol: Symbol,
+    /// Changed bid levels, best first. A quantity of 0 removes the level.
+    pub bids: Vec<OrderBookLevel>,
+    /// Changed ask levels, best first. A quantity of 0 removes the level.
+    pub asks: Vec<OrderBookLevel>,
+}
+
+#[derive(Default, Debug, Clone)]
+struct Depth {
+    bids: BTreeMap<Price, Quantity>,
+    asks: BTreeMap<Price, Quantity>,
+}
+
+impl Depth {
+    fn side(&mut self, side: Side) -> &mut BTreeMap<Price, Quantity> {
+        match side {
+            Side::Buy => &mut self.bids,
+            Side::Sell => &mut self.asks,
+        }
+    }
+
+    fn update(self, symbol: Symbol) -> DepthUpdate {
+        let level = |(price, quantity)| OrderBookLevel::new(price, quantity);
+        DepthUpdate {
+            symbol,
+            bids: self.bids.into_iter().rev().map(level).collect(),
+            asks: self.asks.into_iter().map(level).collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct RestingOrder {
+    symbol: Symbol,
+    side: Side,
+    price: Price,
+    quantity: Quantity,
+}
+
+/// Levels that changed since the subscriber was last served.
+pub struct DepthSubscription {
+    symbol: Option<Symbol>,
+    pending: Mutex<HashMap<Symbol, Depth>>,
+}
+
+impl DepthSubscription {
+    fn wants(&self, symbol: Symbol) -> bool {
+        self.symbol.is_none_or(|wanted| wanted == symbol)
+    }
+
+    /// The latest state of every level that changed, one update per
+    /// instrument.
+    pub fn take(&self) -> Vec<DepthUpdate> {
+        let pending = std::mem::take(&mut *self.pending.lock().expect("Subscription poisoned"));
+        pending
+            .into_iter()
+            .map(|(symbol, depth)| depth.update(symbol))
+            .collect()
+    }
+}
+
+#[derive(Default)]
+struct Books {
+    orders: HashMap<OrderId, RestingOrder>,
+    levels: HashMap<Symbol, Depth>,
+    subscribers: Vec<Weak<DepthSubscription>>,
+}
+
+impl Books {
+    /// Applies the change of one order and returns the level it left and
+    /// the level it joined.
+    fn apply(&mut self, message: &L3Message) -> [Option<(Symbol, Side, Price)>; 2] {
+        let (order_id, after) = match *message {
+            L3Message::Add {
+                symbol,
+                order_id,
+                side,
+                price,
+                quantity,
+                ..
+            } => (
+                order_id,
+                Some(RestingOrder {
+                    symbol,
+                    side,
+                    price,
+                    quantity,
+                }),
+            ),
+            L3Message::Execute {
+                order_id,
+                leaves_quantity: quantity,
+                ..
+            }
+            | L3Message::Reduce {
+                order_id, quantity, ..
+            } => (
+                order_id,
+                self.orders
+                    .get(&order_id)
+                    .map(|order| RestingOrder { quantity, ..*order }),
+            ),
+            L3Message::Replace {
+                order_id,
+                price,
+                quantity,
+                ..
+            } => (
+                order_id,
+                self.orders.get(&order_id).map(|order| RestingOrder {
+                    price,
+                    quantity,
+                    ..*order
+                }),
+            ),
+            L3Message::Delete { order_id, .. } => (order_id, None),
+        };
+
+        let after = after.filter(|order| order.quantity.0 > 0);
+        let before = match after {
+            Some(order) => self.orders.insert(order_id, order),
+            None => self.orders.remove(&order_id),
+        };
+        if let Some(order) = before {
+            self.add(order, -order.quantity.0);
+        }
+        if let Some(order) = after {
+            self.add(order, order.quantity.0);
+        }
+
+        [before, after].map(|order| order.map(|o| (o.symbol, o.side, o.price)))
+    }
+
+    fn add(&mut self, order: RestingOrder, quantity: i64) {
+        let levels = self
+            .levels
+            .entry(order.symbol)
+            .or_default()
+            .side(order.side);
+        let level = levels.entry(order.price).or_default();
+        level.0 += quantity;
+        if level.0 <= 0 {
+            levels.remove(&order.price);
+        }
+    }
+
+    fn quantity(&self, symbol: Symbol, side: Side, price: Price) -> Quantity {
+        let levels = self.levels.get(&symbol);
+        let level = levels.and_then(|depth| match side {
+            Side::Buy => depth.bids.get(&price),
+            Side::Sell => depth.asks.get(&price),
+        });
+        level.copied().unwrap_or_default()
+    }
+
+    /// Hands the current quantity of the levels to every subscriber of their
+    /// instrument.
+    fn notify(&mut self, levels: &[(Symbol, Side, Price)]) {
+        self.subscribers
+            .retain(|subscriber| subscriber.strong_count() > 0);
+        for subscriber in self.subscribers.iter().filter_map(Weak::upgrade) {
+            let mut pending = subscriber.pending.lock().expect("Subscription poisoned");
+            for &(symbol, side, price) in levels {
+                if subscriber.wants(symbol) {
+                    let quantity = self.quantity(symbol, side, price);
+                    pending
+                        .entry(symbol)
+                        .or_default()
+                        .side(side)
+                        .insert(price, quantity);
+                }
+            }
+        }
+    }
+}
+
+pub struct DepthStore {
+    interval: Duration,
+    books: Mutex<Books>,
+}
+
+impl DepthStore {
+    pub fn new(settings: &DepthSettings) -> Self {
+        DepthStore {
+            interval: Duration::from_millis(settings.interval_ms.max(1)),
+            books: Mutex::default(),
+        }
+    }
+
+    /// Time between two updates of a subscriber asking for `interval_ms`,
+    /// never shorter than the configured interval.
+    pub fn interval(&self, interval_ms: Option<u64>) -> Duration {
+        interval_ms
+            .map(Duration::from_millis)
+            .map_or(self.interval, |interval| interval.max(self.interval))
+    }
+
+    /// Follows the levels of `symbol`, or of every instrument, starting with
+    /// all of their current levels. Dropping the subscription ends it.
+    pub fn subscribe(&self, symbol: Option<Symbol>) -> Arc<DepthSubscription> {
+        let mut books = self.books.lock().expect("Depth store poisoned");
+        let pending = books
+            .levels
+            .iter()
+            .filter(|(s, _)| symbol.is_none_or(|wanted| wanted == **s))
+            .map(|(&symbol, depth)| (symbol, depth.clone()))
+            .collect();
+
+        let subscription = Arc::new(DepthSubscription {
+            symbol,
+            pending: Mutex::new(pending),
+        });
+        books.subscribers.push(Arc::downgrade(&subscription));
+        subscription
+    }
+
+    fn apply(&self, batch: &EventBatch) {
+        let messages: Vec<_> = batch
+            .sequenced()
+            .flat_map(|(sequence, event)| messages(sequence, event))
+            .collect();
+        if messages.is_empty() {
+            return;
+        }
+        let mut books = self.books.lock().expect("Depth store poisoned");
+        let changed: Vec<_> = messages
+            .iter()
+            .flat_map(|message| books.apply(message))
+            .flatten()
+            .collect();
+        books.notify(&changed);
+    }
+}
+
+/// Keeps the price levels up to date with the engine events.
+pub async fn track(mut batches: mpsc::UnboundedReceiver<EventBatch>, store: Arc<DepthStore>) {
+    while let Some(batch) = batches.recv().await {
+        store.apply(&batch);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::order::Order;
+    use crate::domain::order_book::OrderBook;
+    use crate::domain::order_entry::OrderEntry;
+    use crate::matching::engine::MarketEvent;
+
+    fn levels(levels: &[(i64, i64)]) -> Vec<OrderBookLevel> {
+        levels
+            .iter()
+            .map(|&(price, quantity)| OrderBookLevel::new(price, quantity))
+            .collect()
+    }
+
+    #[test]
+    fn subscribers_receive_the_latest_state_of_changed_levels() {
+        let store = DepthStore::new(&DepthSettings::default());
+        let mut book = OrderBook::default();
+        let mut place = |entry: OrderEntry| {
+            let order = Order::from(entry);
+            let mut events = vec![MarketEvent::OrderCreated(order.clone())];
+            let trades = book.match_order(order).unwrap_or_default();
+            events.extend(trades.into_iter().map(MarketEvent::TradeExecuted));
+            store.apply(&EventBatch {
+                sequence: 1,
+                events,
+            });
+        };
+
+        place(OrderEntry::new(100, 10, Side::Sell));
+        place(OrderEntry::new(101, 5, Side::Sell));
+        place(OrderEntry::new(99, 3, Side::Buy));
+        let subscription = store.subscribe(None);
+        let other = store.subscribe(Some("XYZ".parse().unwrap()));
+
+        // a new subscriber starts with the whole book
+        assert_eq!(
+            subscription.take(),
+            [DepthUpdate {
+                symbol: Symbol::default(),
+                bids: levels(&[(99, 3)]),
+                asks: levels(&[(100, 10), (101, 5)]),
+            }]
+        );
+        assert!(subscription.take().is_empty());
+
+        // the buy order is added at 101, then trades away the level at 100
+        place(OrderEntry::new(101, 12, Side::Buy));
+        place(OrderEntry::new(98, 1, Side::Buy));
+        assert_eq!(
+            subscription.take(),
+            [DepthUpdate {
+                symbol: Symbol::default(),
+                bids: levels(&[(101, 0), (98, 1)]),
+                asks: levels(&[(100, 0), (101, 3)]),
+            }]
+        );
+        assert!(other.take().is_empty());
+
+        drop(subscription);
+        place(OrderEntry::new(97, 1, Side::Buy));
+        assert_eq!(store.books.lock().unwrap().subscribers.len(), 1);
+    }
+}
@@ -0,0 +1,253 @@
+//! Order-by-order view of the books.
+//!
+//! Every change of a resting order is published as an [`L3Message`] carrying
+//! the sequence number of the engine event causing it, and the revision of
+//! the order after the change. Clients subscribe first, then take a snapshot
+//! of the book and skip messages whose sequence number is not above the one
+//! of the snapshot. The feed never skips events of the engine. A client
+//! falling behind is disconnected instead of missing messages, and resyncs
+//! the same way after reconnecting.
+
+use crate::domain::order::{OrderId, Price, Quantity, Revision};
+use crate::domain::order_change::ModifyReason;
+use crate::domain::side::Side;
+use crate::domain::symbol::Symbol;
+use crate::domain::trade::{TradeId, TradeParty};
+use crate::matching::engine::{EventBatch, MarketEvent};
+use crate::routes::encoding::Encoded;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tokio::sync::{broadcast, mpsc};
+
+const UPDATE_BUFFER: usize = 10_000;
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type")]
+pub enum L3Message {
+    /// The order joined the back of the queue at its price. Incoming orders
+    /// are added before they match, like `OrderCreated` is published before
+    /// their trades.
+    Add {
+        sequence: u64,
+        symbol: Symbol,
+        order_id: OrderId,
+        revision: Revision,
+        side: Side,
+        price: Price,
+        quantity: Quantity,
+    },
+    /// Part of the order traded, sent for both orders of a trade. The order
+    /// is gone once `leaves_quantity` is 0.
+    Execute {
+        sequence: u64,
+        symbol: Symbol,
+        order_id: OrderId,
+        revision: Revision,
+        trade_id: TradeId,
+        price: Price,
+        quantity: Quantity,
+        leaves_quantity: Quantity,
+    },
+    /// The order kept its place in the queue with a smaller `quantity`.
+    Reduce {
+        sequence: u64,
+        symbol: Symbol,
+        order_id: OrderId,
+        revision: Revision,
+        quantity: Quantity,
+    },
+    /// The order moved to the back of the queue at `price`, with `quantity`.
+    Replace {
+        sequence: u64,
+        symbol: Symbol,
+        order_id: OrderId,
+        revision: Revision,
+        price: Price,
+        quantity: Quantity,
+    },
+    /// The order left the book without trading.
+    Delete {
+        sequence: u64,
+        symbol: Symbol,
+        order_id: OrderId,
+        revision: Revision,
+    },
+}
+
+impl L3Message {
+    /// Sequence number of the engine event causing the change.
+    pub fn sequence(&self) -> u64 {
+        match self {
+            L3Message::Add { sequence, .. }
+            | L3Message::Execute { sequence, .. }
+            | L3Message::Reduce { sequence, .. }
+            | L3Message::Replace { sequence, .. }
+            | L3Message::Delete { sequence, .. } => *sequence,
+        }
+    }
+
+    pub fn symbol(&self) -> Symbol {
+        match self {
+            L3Message::Add { symbol, .. }
+            | L3Message::Execute { symbol, .. }
+            | L3Message::Reduce { symbol, .. }
+            | L3Message::Replace { symbol, .. }
+            | L3Message::Delete { symbol, .. } => *symbol,
+        }
+    }
+}
+
+/// Changes of resting orders caused by the event with number `sequence`.
+pub(crate) fn messages(sequence: u64, event: &MarketEvent) -> Vec<L3Message> {
+    match event {
+        MarketEvent::OrderCreated(order) => vec![L3Message::Add {
+            sequence,
+            symbol: order.symbol,
+            order_id: order.id,
+            revision: order.revision,
+            side: order.side,
+            price: order.price,
+            quantity: order.quantity,
+        }],
+        MarketEvent::TradeExecuted(trade) => {
+            let executed = |order_id, party: &TradeParty| L3Message::Execute {
+                sequence,
+                symbol: trade.symbol,
+                order_id,
+                revision: party.revision,
+                trade_id: trade.id,
+                price: trade.price,
+                quantity: trade.quantity,
+                leaves_quantity: party.leaves_quantity,
+            };
+            vec![
+                executed(trade.maker_id(), &trade.maker),
+                executed(trade.taker_id(), &trade.taker),
+            ]
+        }
+        // fills are already reported as executions
+        MarketEvent::OrderModified(change) if change.reason == ModifyReason::PartialFill => {
+            vec![]
+        }
+        MarketEvent::OrderModified(change) if change.priority_lost => vec![L3Message::Replace {
+            sequence,
+            symbol: change.symbol,
+            order_id: change.id,
+            revision: change.new_revision,
+            price: change.new_price,
+            quantity: change.new_quantity,
+        }],
+        MarketEvent::OrderModified(change) => vec![L3Message::Reduce {
+            sequence,
+            symbol: change.symbol,
+            order_id: change.id,
+            revision: change.new_revision,
+            quantity: change.new_quantity,
+        }],
+        MarketEvent::OrderDeleted { order, .. } => vec![L3Message::Delete {
+            sequence,
+            symbol: order.symbol,
+            order_id: order.id,
+            revision: order.revision,
+        }],
+        _ => vec![],
+    }
+}
+
+pub struct L3Feed {
+    updates: broadcast::Sender<Arc<Encoded<L3Message>>>,
+}
+
+impl Default for L3Feed {
+    fn default() -> Self {
+        let (updates, _) = broadcast::channel(UPDATE_BUFFER);
+        L3Feed { updates }
+    }
+}
+
+impl L3Feed {
+    pub fn subscribe(&self) -> broadcast::Receiver<Arc<Encoded<L3Message>>> {
+        self.updates.subscribe()
+    }
+}
+
+/// Turns the engine events into L3 messages.
+pub async fn publish(mut batches: mpsc::UnboundedReceiver<EventBatch>, feed: Arc<L3Feed>) {
+    while let Some(batch) = batches.recv().await {
+        for (sequence, event) in batch.sequenced() {
+            for message in messages(sequence, event) {
+                // nobody may be listening
+                drop(feed.updates.send(Arc::new(Encoded::new(message))));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::order::Order;
+    use crate::domain::order_book::OrderBook;
+    use crate::domain::order_change::OrderChange;
+    use crate::domain::order_entry::OrderEntry;
+
+    #[test]
+    fn trades_execute_both_orders() {
+        let mut book = OrderBook::default();
+        let maker = Order::from(OrderEntry::new(100, 10, Side::Sell));
+        book.match_order(maker.clone());
+        let trades = book
+            .match_order(OrderEntry::new(100, 4, Side::Buy))
+            .expect("Orders cross");
+
+        let messages = messages(7, &MarketEvent::TradeExecuted(trades[0].clone()));
+
+        match &messages[..] {
+            [
+                L3Message::Execute {
+                    sequence: 7,
+                    order_id,
+                    revision: Revision(1),
+                    leaves_quantity: Quantity(6),
+                    ..
+                },
+                L3Message::Execute {
+                    revision: Revision(0),
+                    leaves_quantity: Quantity(0),
+                    ..
+                },
+            ] => assert_eq!(*order_id, maker.id),
+            other => panic!("Unexpected messages: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn modifications_reduce_or_replace() {
+        let before = Order::from(OrderEntry::new(100, 10, Side::Buy));
+        let mut after = before.clone();
+        after.update(None::<Price>, Some(Quantity(6)));
+
+        let reduce = OrderChange::new(&before, &after, false, ModifyReason::UserModify);
+        assert!(matches!(
+            messages(1, &MarketEvent::OrderModified(reduce))[..],
+            [L3Message::Reduce {
+                revision: Revision(1),
+                quantity: Quantity(6),
+                ..
+            }]
+        ));
+
+        let replace = OrderChange::new(&before, &after, true, ModifyReason::UserModify);
+        assert!(matches!(
+            messages(1, &MarketEvent::OrderModified(replace))[..],
+            [L3Message::Replace {
+                revision: Revision(1),
+                price: Price(100),
+                ..
+            }]
+        ));
+
+        let fill = OrderChange::new(&before, &after, false, ModifyReason::PartialFill);
+        assert!(messages(1, &MarketEvent::OrderModified(fill)).is_empty());
+    }
+}
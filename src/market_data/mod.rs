@@ -0,0 +1,8 @@
+//! Public market data derived from the engine events, kept next to the engine
+//! so clients can query it over HTTP and follow it over WebSockets.
+
+pub mod candles;
+pub mod depth;
+pub mod l3;
+pub mod ticker;
+pub mod trades;
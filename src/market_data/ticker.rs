@@ -0,0 +1,301 @@
+//! Rolling 24 hour statistics of every instrument.
+//!
+//! Every trade updates running totals and trades are expired as the window
+//! moves, so the window is never scanned again. Tickers combine them with the
+//! best bid and ask of the engine and are published at most once per
+//! `interval_ms` for every instrument that changed.
+
+use crate::configuration::TickerSettings;
+use crate::domain::order::{PRICE_SCALE, Price, Quantity};
+use crate::domain::order_book_level::OrderBookLevel;
+use crate::domain::symbol::Symbol;
+use crate::domain::trade::{Trade, now_unix_ns};
+use crate::matching::engine::{EventBatch, MarketEvent};
+use crate::matching::reject::Reject;
+use crate::matching::state::AppState;
+use crate::routes::encoding::Encoded;
+use log::error;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{broadcast, mpsc};
+use tokio::time::MissedTickBehavior;
+
+const UPDATE_BUFFER: usize = 1_000;
+
+const WINDOW_NS: i64 = 24 * 3_600 * 1_000_000_000;
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct Ticker {
+    pub symbol: Symbol,
+    /// Price of the latest trade, also when it left the window.
+    pub last: Option<Price>,
+    pub open: Option<Price>,
+    pub high: Option<Price>,
+    pub low: Option<Price>,
+    /// Traded quantity.
+    pub volume: Quantity,
+    /// Traded notional in whole units, see `PRICE_SCALE`.
+    pub quote_volume: i64,
+    pub vwap: Option<Price>,
+    pub trades: u64,
+    /// Change of `last` against `open` in basis points, 100 is one percent.
+    pub change_bps: Option<i64>,
+    pub best_bid: Option<OrderBookLevel>,
+    pub best_ask: Option<OrderBookLevel>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct WindowTrade {
+    time: i64,
+    price: Price,
+    quantity: Quantity,
+}
+
+impl WindowTrade {
+    /// Price times quantity, scaled only once the totals are reported so
+    /// that small trades are not rounded away one by one.
+    fn notional(&self) -> i128 {
+        self.price.0 as i128 * self.quantity.0 as i128
+    }
+}
+
+/// Trades of the window of one instrument with their running totals. The
+/// high and low are the fronts of queues holding only the prices that can
+/// still become the extreme once older trades expire.
+#[derive(Default, Debug)]
+struct RollingStats {
+    last: Option<Price>,
+    window: VecDeque<WindowTrade>,
+    highs: VecDeque<(i64, Price)>,
+    lows: VecDeque<(i64, Price)>,
+    volume: i64,
+    notional: i128,
+}
+
+impl RollingStats {
+    fn record(&mut self, trade: WindowTrade) {
+        self.expire(trade.time);
+        self.last = Some(trade.price);
+        self.volume += trade.quantity.0;
+        self.notional += trade.notional();
+        self.window.push_back(trade);
+
+        while self.highs.back().is_some_and(|&(_, p)| p <= trade.price) {
+            self.highs.pop_back();
+        }
+        self.highs.push_back((trade.time, trade.price));
+        while self.lows.back().is_some_and(|&(_, p)| p >= trade.price) {
+            self.lows.pop_back();
+        }
+        self.lows.push_back((trade.time, trade.price));
+    }
+
+    /// Drops the trades that are older than the window as of `now`.
+    fn expire(&mut self, now: i64) {
+        let cutoff = now - WINDOW_NS;
+        while let Some(trade) = self.window.front().filter(|t| t.time <= cutoff) {
+            self.volume -= trade.quantity.0;
+            self.notional -= trade.notional();
+            self.window.pop_front();
+        }
+        while self.highs.front().is_some_and(|&(time, _)| time <= cutoff) {
+            self.highs.pop_front();
+        }
+        while self.lows.front().is_some_and(|&(time, _)| time <= cutoff) {
+            self.lows.pop_front();
+        }
+    }
+
+    fn ticker(&self, symbol: Symbol) -> Ticker {
+        let open = self.window.front().map(|t| t.price);
+        let vwap = (self.volume > 0).then(|| Price((self.notional / self.volume as i128) as i64));
+        let change_bps = match (self.last, open) {
+            (Some(last), Some(open)) if open.0 > 0 => Some((last.0 - open.0) * 10_000 / open.0),
+            _ => None,
+        };
+
+        Ticker {
+            symbol,
+            last: self.last,
+            open,
+            high: self.highs.front().map(|&(_, p)| p),
+            low: self.lows.front().map(|&(_, p)| p),
+            volume: Quantity(self.volume),
+            quote_volume: (self.notional / PRICE_SCALE as i128) as i64,
+            vwap,
+            trades: self.window.len() as u64,
+            change_bps,
+            best_bid: None,
+            best_ask: None,
+        }
+    }
+}
+
+pub struct TickerStore {
+    interval: Duration,
+    stats: Mutex<HashMap<Symbol, RollingStats>>,
+    updates: broadcast::Sender<Arc<Encoded<Ticker>>>,
+}
+
+impl TickerStore {
+    pub fn new(settings: &TickerSettings) -> Self {
+        let (updates, _) = broadcast::channel(UPDATE_BUFFER);
+        TickerStore {
+            interval: Duration::from_millis(settings.interval_ms.max(1)),
+            stats: Mutex::default(),
+            updates,
+        }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Arc<Encoded<Ticker>>> {
+        self.updates.subscribe()
+    }
+
+    /// Instruments that traded or had orders since the start.
+    pub fn symbols(&self) -> Vec<Symbol> {
+        let stats = self.stats.lock().expect("Ticker store poisoned");
+        stats.keys().copied().collect()
+    }
+
+    /// Updates the statistics and returns the instrument whose ticker
+    /// changed, if any.
+    fn apply(&self, event: &MarketEvent) -> Option<Symbol> {
+        let symbol = match event {
+            MarketEvent::TradeExecuted(trade) => trade.symbol,
+            MarketEvent::OrderCreated(order) => order.symbol,
+            MarketEvent::OrderDeleted { order, .. } => order.symbol,
+            MarketEvent::OrderModified(change) => change.symbol,
+            _ => return None,
+        };
+
+        let mut stats = self.stats.lock().expect("Ticker store poisoned");
+        let stats = stats.entry(symbol).or_default();
+        if let MarketEvent::TradeExecuted(trade) = event {
+            stats.record(WindowTrade::from(trade));
+        }
+        Some(symbol)
+    }
+
+    /// Statistics of the last 24 hours as of `now`, without the book.
+    fn statistics(&self, symbol: Symbol, now: i64) -> Ticker {
+        let mut stats = self.stats.lock().expect("Ticker store poisoned");
+        match stats.get_mut(&symbol) {
+            Some(stats) => {
+                stats.expire(now);
+                stats.ticker(symbol)
+            }
+            None => RollingStats::default().ticker(symbol),
+        }
+    }
+}
+
+impl From<&Trade> for WindowTrade {
+    fn from(trade: &Trade) -> Self {
+        WindowTrade {
+            time: trade.exec_time(),
+            price: trade.price,
+            quantity: trade.quantity,
+        }
+    }
+}
+
+/// The current ticker of the instrument, with the best bid and ask.
+pub async fn ticker(state: &AppState, symbol: Symbol) -> Result<Ticker, Reject> {
+    let (best_bid, best_ask) = state.best_of_book(symbol).await?;
+    Ok(Ticker {
+        best_bid,
+        best_ask,
+        ..state.tickers.statistics(symbol, now_unix_ns())
+    })
+}
+
+/// Keeps the statistics up to date from the lossless engine feed and publishes
+/// the tickers of the instruments that changed, at most once per interval.
+pub async fn publish(mut batches: mpsc::UnboundedReceiver<EventBatch>, state: AppState) {
+    let store = state.tickers.clone();
+    let mut changed = HashSet::new();
+    let mut throttle = tokio::time::interval(store.interval);
+    throttle.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        tokio::select! {
+            batch = batches.recv() => match batch {
+                Some(batch) => changed.extend(batch.events.iter().filter_map(|e| store.apply(e))),
+                None => break,
+            },
+            _ = throttle.tick(), if !changed.is_empty() => {
+                for symbol in changed.drain() {
+                    match ticker(&state, symbol).await {
+                        // nobody may be listening
+                        Ok(ticker) => drop(store.updates.send(Arc::new(Encoded::new(ticker)))),
+                        Err(reject) => error!("Failed to publish ticker of {symbol}: {reject:?}"),
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOUR: i64 = 3_600 * 1_000_000_000;
+
+    fn trade(hour: i64, price: i64, quantity: i64) -> WindowTrade {
+        WindowTrade {
+            time: hour * HOUR,
+            price: Price(price),
+            quantity: Quantity(quantity),
+        }
+    }
+
+    #[test]
+    fn statistics_cover_the_last_24_hours() {
+        let mut stats = RollingStats::default();
+        stats.record(trade(0, 300, 100));
+        stats.record(trade(1, 100, 100));
+        stats.record(trade(2, 200, 300));
+
+        let ticker = stats.ticker(Symbol::default());
+        assert_eq!(ticker.open, Some(Price(300)));
+        assert_eq!(
+            (ticker.high, ticker.low),
+            (Some(Price(300)), Some(Price(100)))
+        );
+        assert_eq!((ticker.volume, ticker.quote_volume), (Quantity(500), 1_000));
+        assert_eq!((ticker.vwap, ticker.trades), (Some(Price(200)), 3));
+        assert_eq!(ticker.change_bps, Some(-3_333));
+
+        // the first trade leaves the window
+        stats.expire(24 * HOUR);
+        let ticker = stats.ticker(Symbol::default());
+        assert_eq!(ticker.open, Some(Price(100)));
+        assert_eq!(
+            (ticker.high, ticker.low),
+            (Some(Price(200)), Some(Price(100)))
+        );
+        assert_eq!((ticker.volume, ticker.trades), (Quantity(400), 2));
+        assert_eq!(ticker.change_bps, Some(10_000));
+
+        stats.expire(48 * HOUR);
+        let ticker = stats.ticker(Symbol::default());
+        assert_eq!(ticker.last, Some(Price(200)));
+        assert_eq!((ticker.open, ticker.high, ticker.vwap), (None, None, None));
+        assert_eq!((ticker.volume, ticker.trades), (Quantity(0), 0));
+    }
+
+    #[test]
+    fn notionals_are_scaled_once() {
+        let mut stats = RollingStats::default();
+        for hour in 0..3 {
+            stats.record(trade(hour, 101, 1));
+        }
+
+        // every trade alone is worth 1.01, a single whole unit
+        let ticker = stats.ticker(Symbol::default());
+        assert_eq!((ticker.quote_volume, ticker.vwap), (3, Some(Price(101))));
+    }
+}
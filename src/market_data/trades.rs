@@ -0,0 +1,169 @@
+//! The latest public trades of the engine, for clients that missed them on
+//! the feed.
+
+use crate::configuration::TradeHistorySettings;
+use crate::domain::symbol::Symbol;
+use crate::domain::trade::{Trade, TradeId};
+use crate::matching::engine::{EventBatch, MarketEvent};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc;
+
+/// Trades returned by one query at most.
+pub const MAX_LIMIT: usize = 1_000;
+
+/// Selects trades, every field is optional.
+#[derive(Debug, Default, Clone)]
+pub struct TradeQuery {
+    pub symbol: Option<Symbol>,
+    /// Only trades after this one.
+    pub since_id: Option<TradeId>,
+    /// Unix nanoseconds, inclusive.
+    pub from: Option<i64>,
+    /// Unix nanoseconds, exclusive.
+    pub to: Option<i64>,
+}
+
+pub struct TradeHistory {
+    capacity: usize,
+    /// Ordered by id.
+    trades: Mutex<VecDeque<Trade>>,
+}
+
+impl TradeHistory {
+    pub fn new(settings: &TradeHistorySettings) -> Self {
+        TradeHistory {
+            capacity: settings.capacity.max(1),
+            trades: Mutex::default(),
+        }
+    }
+
+    pub fn record(&self, trade: Trade) {
+        let mut trades = self.trades.lock().expect("Trade history poisoned");
+        if trades.len() == self.capacity {
+            trades.pop_front();
+        }
+        trades.push_back(trade);
+    }
+
+    /// Up to `limit` matching trades in id order. Queries with `since_id` or
+    /// `from` page forward from there, others return the latest trades.
+    pub fn query(&self, query: &TradeQuery, limit: usize) -> Vec<Trade> {
+        let limit = limit.min(MAX_LIMIT);
+        let trades = self.trades.lock().expect("Trade history poisoned");
+        let start = query
+            .since_id
+            .map_or(0, |since| trades.partition_point(|t| t.id <= since));
+        let matching = trades.range(start..).filter(|t| {
+            query.symbol.is_none_or(|symbol| t.symbol == symbol)
+                && query.from.is_none_or(|from| t.exec_time() >= from)
+                && query.to.is_none_or(|to| t.exec_time() < to)
+        });
+
+        if query.since_id.is_some() || query.from.is_some() {
+            matching.take(limit).cloned().collect()
+        } else {
+            let mut latest: Vec<_> = matching.rev().take(limit).cloned().collect();
+            latest.reverse();
+            latest
+        }
+    }
+}
+
+/// Feeds the trades of the engine into the history, from the lossless feed so
+/// that paging by id never skips a trade.
+pub async fn record(mut batches: mpsc::UnboundedReceiver<EventBatch>, history: Arc<TradeHistory>) {
+    while let Some(batch) = batches.recv().await {
+        for event in batch.events {
+            if let MarketEvent::TradeExecuted(trade) = event {
+                history.record(trade);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::side::Side;
+    use uuid::Uuid;
+
+    fn history(capacity: usize, count: u64) -> TradeHistory {
+        let history = TradeHistory::new(&TradeHistorySettings { capacity });
+        for id in 1..=count {
+            let mut trade = Trade::new(100, 1, Side::Buy, Uuid::nil(), Uuid::nil());
+            trade.id = TradeId(id);
+            if id % 2 == 0 {
+                trade.symbol = "ABC".parse().unwrap();
+            }
+            history.record(trade);
+        }
+        history
+    }
+
+    fn ids(trades: Vec<Trade>) -> Vec<u64> {
+        trades.iter().map(|t| t.id.0).collect()
+    }
+
+    #[test]
+    fn only_the_latest_trades_are_kept() {
+        let history = history(3, 5);
+
+        assert_eq!(ids(history.query(&TradeQuery::default(), 10)), [3, 4, 5]);
+    }
+
+    #[test]
+    fn trades_are_paged_by_id() {
+        let history = history(10, 8);
+        let since = |id| TradeQuery {
+            since_id: Some(TradeId(id)),
+            ..Default::default()
+        };
+
+        assert_eq!(ids(history.query(&TradeQuery::default(), 2)), [7, 8]);
+        assert_eq!(ids(history.query(&since(0), 3)), [1, 2, 3]);
+        assert_eq!(ids(history.query(&since(3), 3)), [4, 5, 6]);
+        assert_eq!(ids(history.query(&since(8), 3)), Vec::<u64>::new());
+
+        let abc = TradeQuery {
+            symbol: Some("ABC".parse().unwrap()),
+            ..since(3)
+        };
+        assert_eq!(ids(history.query(&abc, 2)), [4, 6]);
+    }
+
+    #[tokio::test]
+    async fn every_trade_of_the_feed_is_recorded() {
+        let history = Arc::new(TradeHistory::new(&TradeHistorySettings { capacity: 10 }));
+        let (tx, rx) = mpsc::unbounded_channel();
+        for sequence in [1, 4] {
+            let events = (0..3)
+                .map(|_| Trade::new(100, 1, Side::Buy, Uuid::nil(), Uuid::nil()))
+                .map(MarketEvent::TradeExecuted)
+                .collect();
+            tx.send(EventBatch { sequence, events }).unwrap();
+        }
+        drop(tx);
+
+        record(rx, history.clone()).await;
+
+        assert_eq!(history.query(&TradeQuery::default(), 10).len(), 6);
+    }
+
+    #[test]
+    fn trades_are_selected_by_time() {
+        let history = history(10, 3);
+        let all = history.query(&TradeQuery::default(), 10);
+        let query = TradeQuery {
+            from: Some(all[1].exec_time()),
+            to: Some(all[2].exec_time() + 1),
+            ..Default::default()
+        };
+
+        let selected = history.query(&query, 10);
+
+        // trades may share a timestamp
+        assert!(ids(selected.clone()).ends_with(&[2, 3]));
+        assert!(selected.iter().all(|t| t.exec_time() >= all[1].exec_time()));
+    }
+}
@@ -0,0 +1,167 @@
+use crate::configuration::CircuitBreakerSettings;
+use crate::domain::order::Price;
+use std::collections::VecDeque;
+
+#[derive(Debug, PartialEq)]
+pub struct BreakerTrip {
+    pub reference_price: Price,
+    pub trigger_price: Price,
+    /// Clock time at which trading resumes.
+    pub resume_at: i64,
+}
+
+/// Prices an instrument may trade at before its breaker trips.
+#[derive(Debug, Clone, Copy)]
+pub struct PriceBand {
+    reference: Option<Price>,
+    threshold_bps: i64,
+}
+
+impl PriceBand {
+    /// Whether a trade at `price` stays within `threshold_bps` of the
+    /// reference price. Without trades in the window the first admitted
+    /// price becomes the reference.
+    pub fn admits(&mut self, price: Price) -> bool {
+        let reference = *self.reference.get_or_insert(price);
+        let moved_bps = (price.0 - reference.0).abs() * 10_000;
+        reference.0 <= 0 || moved_bps <= self.threshold_bps * reference.0
+    }
+}
+
+/// Tracks the trades of one instrument over a rolling window and keeps its
+/// price within a band around the oldest trade still inside it. Times are
+/// nanoseconds of the engine clock.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    settings: CircuitBreakerSettings,
+    window: VecDeque<(i64, Price)>,
+    resume_at: Option<i64>,
+}
+
+impl CircuitBreaker {
+    pub fn new(settings: CircuitBreakerSettings) -> Self {
+        CircuitBreaker {
+            settings,
+            window: VecDeque::new(),
+            resume_at: None,
+        }
+    }
+
+    pub fn resume_at(&self) -> Option<i64> {
+        self.resume_at
+    }
+
+    /// Prices that may trade at `now`, checked level by level while an
+    /// order is matched.
+    pub fn band(&mut self, now: i64) -> PriceBand {
+        self.expire(now);
+        PriceBand {
+            reference: self.window.front().map(|&(_, price)| price),
+            threshold_bps: self.settings.threshold_bps,
+        }
+    }
+
+    pub fn on_trade(&mut self, price: Price, now: i64) {
+        self.expire(now);
+        self.window.push_back((now, price));
+    }
+
+    /// Halts trading for the cooldown after an order was stopped at
+    /// `trigger_price`, outside of `band`.
+    pub fn trip(&mut self, band: &PriceBand, trigger_price: Price, now: i64) -> BreakerTrip {
+        let resume_at = now + self.settings.cooldown_ms as i64 * 1_000_000;
+        self.window.clear();
+        self.resume_at = Some(resume_at);
+
+        BreakerTrip {
+            reference_price: band.reference.unwrap_or(trigger_price),
+            trigger_price,
+            resume_at,
+        }
+    }
+
+    fn expire(&mut self, now: i64) {
+        let window_ns = self.settings.window_ms as i64 * 1_000_000;
+        while self
+            .window
+            .front()
+            .is_some_and(|(time, _)| now - time > window_ns)
+        {
+            self.window.pop_front();
+        }
+    }
+
+    /// Clears an elapsed cooldown and reports whether trading should resume.
+    pub fn poll_resume(&mut self, now: i64) -> bool {
+        if self.resume_at.is_some_and(|at| at <= now) {
+            self.resume_at = None;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Drops a pending resume, e.g. when an operator took over the instrument.
+    pub fn cancel_resume(&mut self) {
+        self.resume_at = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn breaker() -> CircuitBreaker {
+        CircuitBreaker::new(CircuitBreakerSettings {
+            threshold_bps: 1_000,
+            window_ms: 1_000,
+            cooldown_ms: 5_000,
+        })
+    }
+
+    #[test]
+    fn band_admits_prices_within_threshold_of_the_window() {
+        let mut breaker = breaker();
+
+        breaker.on_trade(Price(100), 0);
+        breaker.on_trade(Price(110), 100_000_000);
+        let mut band = breaker.band(200_000_000);
+
+        assert!(band.admits(Price(110)));
+        assert!(band.admits(Price(90)));
+        assert!(!band.admits(Price(111)));
+    }
+
+    #[test]
+    fn first_price_of_an_empty_window_is_the_reference() {
+        let mut band = breaker().band(0);
+
+        assert!(band.admits(Price(200)));
+        assert!(!band.admits(Price(100)));
+    }
+
+    #[test]
+    fn reference_price_rolls_with_the_window() {
+        let mut breaker = breaker();
+
+        breaker.on_trade(Price(100), 0);
+        breaker.on_trade(Price(108), 900_000_000);
+        // the first trade left the window, 115 is within 10% of 108
+        assert!(breaker.band(1_500_000_000).admits(Price(115)));
+    }
+
+    #[test]
+    fn resumes_only_after_cooldown() {
+        let mut breaker = breaker();
+        breaker.on_trade(Price(100), 0);
+        let band = breaker.band(1);
+
+        let trip = breaker.trip(&band, Price(50), 1);
+
+        assert_eq!(trip.reference_price, Price(100));
+        assert_eq!(breaker.resume_at(), Some(trip.resume_at));
+        assert!(!breaker.poll_resume(trip.resume_at - 1));
+        assert!(breaker.poll_resume(trip.resume_at));
+        assert!(breaker.resume_at().is_none());
+    }
+}
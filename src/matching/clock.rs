@@ -0,0 +1,17 @@
+use crate::domain::trade::now_unix_ns;
+
+/// Source of the wall clock time the engine follows its schedules by, so
+/// tests can move through a trading day without waiting for it.
+pub trait Clock: Send + Sync {
+    /// Current time in unix nanoseconds.
+    fn now(&self) -> i64;
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> i64 {
+        now_unix_ns()
+    }
+}
@@ -1,9 +1,56 @@
-use crate::domain::order::{OrderId, Price, Quantity, Revision};
+use crate::domain::account::AccountId;
+use crate::domain::order::{Order, OrderId, Price, Quantity, Revision};
+use crate::domain::order_book_level::{OrderBookLevel, OrderBookSnapshot};
 use crate::domain::order_entry::OrderEntry;
+use crate::domain::order_filter::OrderFilter;
+use crate::domain::symbol::Symbol;
+use crate::domain::trading_state::TradingState;
+use crate::matching::engine::{EventBatch, MarketEvent};
+use crate::matching::reject::Reject;
+use tokio::sync::{mpsc, oneshot};
 
-#[derive(Clone, Debug)]
+pub type Responder<T> = oneshot::Sender<T>;
+
+/// The order as created, modified or deleted by the command.
+pub type CommandResult = Result<Order, Reject>;
+
+/// Best bid and best ask of an instrument.
+pub type BestOfBook = (Option<OrderBookLevel>, Option<OrderBookLevel>);
+
+#[derive(Debug)]
 pub enum MatchingEngineCommand {
     Create(OrderEntry),
-    Modify(OrderId, Revision, Option<Price>, Option<Quantity>),
-    Delete(OrderId, Revision),
+    /// Changes an order. When the requesting account is given, orders of
+    /// other accounts are reported as not found.
+    Modify(
+        OrderId,
+        Revision,
+        Option<Price>,
+        Option<Quantity>,
+        Option<AccountId>,
+    ),
+    /// Cancels an order, checking its account like `Modify`.
+    Delete(OrderId, Revision, Option<AccountId>),
+    /// Cancels every resting order of the account when its session dropped.
+    CancelAll(AccountId),
+    MassCancel(OrderFilter, Responder<Vec<Order>>),
+    /// Moves a single instrument, or the whole engine when no symbol is given,
+    /// into a new trading state.
+    SetTradingState(Option<Symbol>, TradingState),
+    /// Places all orders in one step if every one of them passes validation,
+    /// otherwise none. Results are in the order of the entries.
+    Batch(Vec<OrderEntry>, Responder<Vec<CommandResult>>),
+    /// Reports the best levels of the instrument, empty for unknown ones.
+    BestOfBook(Symbol, Responder<BestOfBook>),
+    /// Reports every resting order of the instrument.
+    Snapshot(Symbol, Responder<OrderBookSnapshot>),
+    /// Sends every later event about orders of the account to the sender.
+    /// Unlike the broadcast feed it never drops events for slow receivers.
+    Subscribe(AccountId, mpsc::UnboundedSender<MarketEvent>),
+    /// Sends every later event to the sender, batched by the command that
+    /// caused them and never dropped.
+    SubscribeAll(mpsc::UnboundedSender<EventBatch>),
+    /// Runs an order entry command (`Create`, `Modify` or `Delete`) and
+    /// reports its outcome. The responder of other commands is dropped.
+    Request(Box<MatchingEngineCommand>, Responder<CommandResult>),
 }
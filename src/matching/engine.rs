@@ -1,64 +1,893 @@
-use crate::domain::order::Order;
+use crate::configuration::{FeeSettings, InstrumentSettings};
+use crate::domain::account::AccountId;
+use crate::domain::auction::Equilibrium;
+use crate::domain::fill::Fill;
+use crate::domain::order::{Order, OrderId, Price, Quantity, Revision, TimeInForce};
 use crate::domain::order_book::OrderBook;
-use crate::domain::trade::Trade;
-use crate::matching::command::MatchingEngineCommand;
+use crate::domain::order_book_level::OrderBookSnapshot;
+use crate::domain::order_change::{CancelReason, ModifyReason, OrderChange};
+use crate::domain::order_entry::OrderEntry;
+use crate::domain::order_filter::OrderFilter;
+use crate::domain::symbol::Symbol;
+use crate::domain::trade::{Trade, TradeId};
+use crate::domain::trading_phase::TradingPhase;
+use crate::domain::trading_state::TradingState;
+use crate::matching::circuit_breaker::{CircuitBreaker, PriceBand};
+use crate::matching::clock::{Clock, SystemClock};
+use crate::matching::command::{CommandResult, MatchingEngineCommand};
 use crate::matching::engine::MarketEvent::{OrderDeleted, OrderModified};
-use log::error;
+use crate::matching::fees::FeeEngine;
+use crate::matching::reject::{Reject, RejectCode};
+use crate::matching::schedule::Schedule;
+use log::{debug, error, info, warn};
 use serde::{Deserialize, Serialize};
-use tokio::sync::broadcast;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::mpsc::Receiver;
+use tokio::sync::{broadcast, mpsc};
+use tokio::time::Instant;
 
 #[derive(Clone, Deserialize, Serialize, Debug)]
 pub enum MarketEvent {
     TradeExecuted(Trade),
-    OrderDeleted(Order),
-    OrderModified,
-    OrderCreated(Order),
+    OrderDeleted {
+        #[serde(with = "crate::domain::order::public")]
+        order: Order,
+        reason: CancelReason,
+    },
+    OrderModified(OrderChange),
+    OrderCreated(#[serde(with = "crate::domain::order::public")] Order),
+    TradingStateChanged {
+        symbol: Option<Symbol>,
+        state: TradingState,
+    },
+    /// A scheduled instrument moved into the next phase of its trading day.
+    PhaseChanged {
+        symbol: Symbol,
+        phase: TradingPhase,
+    },
+    CircuitBreakerTriggered {
+        symbol: Symbol,
+        reference_price: Price,
+        trigger_price: Price,
+        resume_at: i64,
+    },
+    /// Where the book of an instrument in an auction would be uncrossed,
+    /// published whenever the book changed. `None` while it is not crossed.
+    IndicativeEquilibrium {
+        symbol: Symbol,
+        equilibrium: Option<Equilibrium>,
+    },
+    /// Private to the account of the filled order.
+    Fill(Fill),
+    /// Private to the account of the rejected order.
+    OrderRejected {
+        order_id: OrderId,
+        account: AccountId,
+        reject: Reject,
+    },
+}
+
+impl MarketEvent {
+    /// The only account allowed to see the event, `None` for public events.
+    pub fn recipient(&self) -> Option<AccountId> {
+        match self {
+            MarketEvent::Fill(fill) => Some(fill.account),
+            MarketEvent::OrderRejected { account, .. } => Some(*account),
+            _ => None,
+        }
+    }
+
+    /// Account of the order the event is about, `None` for market wide
+    /// events.
+    pub fn owner(&self) -> Option<AccountId> {
+        match self {
+            MarketEvent::OrderCreated(order) | MarketEvent::OrderDeleted { order, .. } => {
+                Some(order.account)
+            }
+            MarketEvent::OrderModified(change) => Some(change.account),
+            _ => self.recipient(),
+        }
+    }
+}
+
+/// Events published while running one command, or while following the
+/// clock, in the order they were published.
+#[derive(Clone, Debug)]
+pub struct EventBatch {
+    /// Sequence number of the first event, the others follow without gaps.
+    pub sequence: u64,
+    pub events: Vec<MarketEvent>,
+}
+
+impl EventBatch {
+    /// Events with their sequence numbers.
+    pub fn sequenced(&self) -> impl Iterator<Item = (u64, &MarketEvent)> {
+        (self.sequence..).zip(&self.events)
+    }
+}
+
+pub struct MatchingEngine {
+    books: HashMap<Symbol, OrderBook>,
+    trading_state: TradingState,
+    instrument_states: HashMap<Symbol, TradingState>,
+    breakers: HashMap<Symbol, CircuitBreaker>,
+    schedules: HashMap<Symbol, Schedule>,
+    phases: HashMap<Symbol, TradingPhase>,
+    clock: Arc<dyn Clock>,
+    max_order_quantity: HashMap<Symbol, i64>,
+    /// Price of the latest trade, the reference price of auctions.
+    last_prices: HashMap<Symbol, Price>,
+    fees: FeeEngine,
+    last_trade_id: TradeId,
+    ws_tx: broadcast::Sender<MarketEvent>,
+    subscribers: HashMap<AccountId, Vec<mpsc::UnboundedSender<MarketEvent>>>,
+    /// Sequence number of the latest published event, counting from 1.
+    sequence: u64,
+    /// Events of the command running, sent to `feeds` once it finished.
+    batch: Vec<MarketEvent>,
+    feeds: Vec<mpsc::UnboundedSender<EventBatch>>,
 }
 
 pub async fn matching_engine(
-    mut rx: Receiver<MatchingEngineCommand>,
+    rx: Receiver<MatchingEngineCommand>,
     ws_tx: broadcast::Sender<MarketEvent>,
 ) {
-    let mut book = OrderBook::default();
+    MatchingEngine::new(ws_tx).run(rx).await
+}
 
-    while let Some(cmd) = rx.recv().await {
-        match cmd {
-            MatchingEngineCommand::Create(order_entry) => {
-                if let Err(e) = ws_tx.send(MarketEvent::OrderCreated(order_entry.clone().into())) {
-                    error!("Failed to broadcast message: {e}")
-                };
+impl MatchingEngine {
+    pub fn new(ws_tx: broadcast::Sender<MarketEvent>) -> Self {
+        MatchingEngine {
+            books: HashMap::new(),
+            trading_state: TradingState::Open,
+            instrument_states: HashMap::new(),
+            breakers: HashMap::new(),
+            schedules: HashMap::new(),
+            phases: HashMap::new(),
+            clock: Arc::new(SystemClock),
+            max_order_quantity: HashMap::new(),
+            last_prices: HashMap::new(),
+            fees: FeeEngine::default(),
+            last_trade_id: TradeId::default(),
+            ws_tx,
+            subscribers: HashMap::new(),
+            sequence: 0,
+            batch: Vec::new(),
+            feeds: Vec::new(),
+        }
+    }
+
+    pub fn with_instruments(mut self, instruments: HashMap<Symbol, InstrumentSettings>) -> Self {
+        self.max_order_quantity = instruments
+            .iter()
+            .filter_map(|(&symbol, settings)| Some((symbol, settings.max_order_quantity?)))
+            .collect();
+        self.schedules = instruments
+            .iter()
+            .filter_map(|(&symbol, settings)| {
+                Some((symbol, Schedule::new(settings.schedule.clone()?)?))
+            })
+            .collect();
+        self.breakers = instruments
+            .into_iter()
+            .filter_map(|(symbol, settings)| {
+                settings
+                    .circuit_breaker
+                    .map(|cb| (symbol, CircuitBreaker::new(cb)))
+            })
+            .collect();
+        self
+    }
+
+    pub fn with_fees(mut self, fees: FeeSettings) -> Self {
+        self.fees = FeeEngine::new(fees);
+        self
+    }
 
-                if let Some(trades) = book.match_order(order_entry) {
-                    for trade in trades {
-                        if let Err(e) = ws_tx.send(MarketEvent::TradeExecuted(trade)) {
-                            error!("Failed to broadcast message: {e}")
-                        };
+    /// Follows the schedules and breaker cooldowns by `clock` instead of the
+    /// system time.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub async fn run(mut self, mut rx: Receiver<MatchingEngineCommand>) {
+        self.follow_clock();
+        loop {
+            let cmd = match self.next_deadline() {
+                Some(deadline) => tokio::select! {
+                    biased;
+                    cmd = rx.recv() => cmd,
+                    _ = tokio::time::sleep_until(deadline) => {
+                        self.follow_clock();
+                        self.flush();
+                        continue;
                     }
+                },
+                None => rx.recv().await,
+            };
+
+            match cmd {
+                Some(cmd) => self.handle(cmd),
+                None => break,
+            }
+        }
+    }
+
+    /// Runs the command in the phase of the current time, which may have
+    /// started while the engine was busy.
+    pub fn handle(&mut self, cmd: MatchingEngineCommand) {
+        self.follow_clock();
+        match cmd {
+            MatchingEngineCommand::Request(cmd, respond_to) => {
+                if let Some(result) = self.execute(*cmd)
+                    && respond_to.send(result).is_err()
+                {
+                    debug!("Requester went away before the response");
                 }
             }
-            MatchingEngineCommand::Delete(id, rev) => {
-                if let Ok(o) = book.delete_order(&(id, rev)) {
-                    if let Err(e) = ws_tx.send(OrderDeleted(o)) {
-                        error!("Failed to broadcast message: {e}")
-                    };
+            cmd => {
+                self.execute(cmd);
+            }
+        }
+        self.flush();
+    }
+
+    /// Runs the command, returning the outcome of order entry commands.
+    /// Rejections are also published as `OrderRejected` to the account that
+    /// sent the command, when it is known.
+    fn execute(&mut self, cmd: MatchingEngineCommand) -> Option<CommandResult> {
+        let (order_id, account, result) = match cmd {
+            MatchingEngineCommand::Create(order_entry) => {
+                let order: Order = order_entry.into();
+                (order.id, Some(order.account), self.create(order))
+            }
+            MatchingEngineCommand::Modify(id, rev, price, quantity, requester) => (
+                id,
+                requester,
+                self.modify(id, rev, price, quantity, requester),
+            ),
+            MatchingEngineCommand::Delete(id, rev, requester) => {
+                (id, requester, self.delete(id, rev, requester))
+            }
+            MatchingEngineCommand::CancelAll(account) => {
+                self.cancel_orders(
+                    &OrderFilter::account(account),
+                    CancelReason::CancelOnDisconnect,
+                );
+                return None;
+            }
+            MatchingEngineCommand::MassCancel(filter, respond_to) => {
+                let cancelled = self.cancel_orders(&filter, CancelReason::MassCancel);
+                if respond_to.send(cancelled).is_err() {
+                    debug!("Mass cancel requester went away before the response");
                 }
+                return None;
             }
-            MatchingEngineCommand::Modify(id, rev, price, quantity) => {
-                if let Ok(trades) = book.modify_order(id, rev, price, quantity) {
-                    if let Err(e) = ws_tx.send(OrderModified) {
-                        error!("Failed to broadcast message: {e}")
-                    };
-
-                    if let Some(executed_trades) = trades {
-                        for trade in executed_trades {
-                            if let Err(e) = ws_tx.send(MarketEvent::TradeExecuted(trade)) {
-                                error!("Failed to broadcast message: {e}")
-                            };
-                        }
-                    }
+            MatchingEngineCommand::SetTradingState(symbol, state) => {
+                self.set_trading_state(symbol, state);
+                return None;
+            }
+            MatchingEngineCommand::Batch(entries, respond_to) => {
+                let results = self.batch(entries);
+                if respond_to.send(results).is_err() {
+                    debug!("Batch requester went away before the response");
+                }
+                return None;
+            }
+            MatchingEngineCommand::BestOfBook(symbol, respond_to) => {
+                let best = self
+                    .books
+                    .get(&symbol)
+                    .map(OrderBook::best_of_book)
+                    .unwrap_or_default();
+                if respond_to.send(best).is_err() {
+                    debug!("Best of book requester went away before the response");
+                }
+                return None;
+            }
+            MatchingEngineCommand::Snapshot(symbol, respond_to) => {
+                let snapshot = OrderBookSnapshot {
+                    sequence: self.sequence,
+                    ..self
+                        .books
+                        .get(&symbol)
+                        .map(OrderBook::snapshot)
+                        .unwrap_or_default()
+                };
+                if respond_to.send(snapshot).is_err() {
+                    debug!("Snapshot requester went away before the response");
+                }
+                return None;
+            }
+            MatchingEngineCommand::Subscribe(account, subscriber) => {
+                let subscribers = self.subscribers.entry(account).or_default();
+                subscribers.retain(|s| !s.is_closed());
+                subscribers.push(subscriber);
+                return None;
+            }
+            MatchingEngineCommand::SubscribeAll(feed) => {
+                self.feeds.retain(|f| !f.is_closed());
+                self.feeds.push(feed);
+                return None;
+            }
+            // only `handle` answers requests, once per command
+            MatchingEngineCommand::Request(..) => {
+                return Some(Err(Reject::new(
+                    RejectCode::InvalidRequest,
+                    "Requests cannot be nested",
+                )));
+            }
+        };
+
+        // without a requesting account the rejection only goes to the responder
+        if let Err(reject) = &result
+            && let Some(account) = account
+        {
+            self.reject(order_id, account, reject);
+        }
+
+        Some(result)
+    }
+
+    fn create(&mut self, order: Order) -> CommandResult {
+        self.validate_order(&order)?;
+        Ok(self.place(order))
+    }
+
+    /// Validates every order before placing any of them, so either the whole
+    /// batch is placed or none of it.
+    fn batch(&mut self, entries: Vec<OrderEntry>) -> Vec<CommandResult> {
+        let orders: Vec<Order> = entries.into_iter().map(Order::from).collect();
+        let checks: Vec<Result<(), Reject>> =
+            orders.iter().map(|o| self.validate_order(o)).collect();
+
+        if checks.iter().all(Result::is_ok) {
+            return orders.into_iter().map(|o| Ok(self.place(o))).collect();
+        }
+
+        orders
+            .into_iter()
+            .zip(checks)
+            .map(|(order, check)| {
+                let reject = check.err().unwrap_or_else(|| {
+                    Reject::new(
+                        RejectCode::BatchAborted,
+                        "Another order of the batch was rejected",
+                    )
+                });
+                self.reject(order.id, order.account, &reject);
+                Err(reject)
+            })
+            .collect()
+    }
+
+    fn place(&mut self, order: Order) -> Order {
+        self.publish(MarketEvent::OrderCreated(order.clone()));
+
+        let symbol = order.symbol;
+        let auction = self.trading_state(symbol) == TradingState::Auction;
+        let book = self.books.entry(symbol).or_default();
+        if auction {
+            book.add_to_book(order.clone());
+            self.publish_indicative(symbol);
+        } else {
+            let mut band = self
+                .breakers
+                .get_mut(&symbol)
+                .map(|b| b.band(self.clock.now()));
+            let sweep = book.match_order_within(order.clone(), &mut |price| {
+                band.as_mut().is_none_or(|band| band.admits(price))
+            });
+            let filled: i64 = sweep.trades.iter().map(|t| t.quantity.0).sum();
+            let taker_revision = sweep.trades.last().map(|t| t.taker.revision);
+            if !sweep.trades.is_empty() {
+                self.publish_trades(symbol, sweep.trades, false);
+            }
+            if let (Some(trigger_price), Some(band)) = (sweep.stopped_at, band) {
+                self.trip_breaker(symbol, &band, trigger_price);
+            }
+            if order.time_in_force == TimeInForce::Ioc && filled < order.quantity.0 {
+                // the remainder is cancelled as it stands after the trades
+                let mut unfilled = order.clone();
+                unfilled.quantity = Quantity(order.quantity.0 - filled);
+                unfilled.executed = Quantity(filled);
+                unfilled.revision = taker_revision.unwrap_or(order.revision);
+                self.publish(OrderDeleted {
+                    order: unfilled,
+                    reason: CancelReason::Unfilled,
+                });
+            }
+        }
+
+        order
+    }
+
+    fn modify(
+        &mut self,
+        id: OrderId,
+        rev: Revision,
+        price: Option<Price>,
+        quantity: Option<Quantity>,
+        requester: Option<AccountId>,
+    ) -> CommandResult {
+        let Some((symbol, _)) = self.find_owned_order(id, rev, requester) else {
+            return Err(not_found(id, rev));
+        };
+
+        self.validate(symbol, price, quantity)?;
+
+        let auction = self.trading_state(symbol) == TradingState::Auction;
+        let mut band = self
+            .breakers
+            .get_mut(&symbol)
+            .map(|b| b.band(self.clock.now()));
+        let book = self.books.get_mut(&symbol).expect("Book must exist");
+        let modification = if auction {
+            book.modify_order(id, rev, price, quantity, false)
+        } else {
+            book.modify_order_within(id, rev, price, quantity, &mut |price| {
+                band.as_mut().is_none_or(|band| band.admits(price))
+            })
+        }
+        .map_err(|_| not_found(id, rev))?;
+
+        self.publish(OrderModified(OrderChange::new(
+            &modification.previous,
+            &modification.order,
+            modification.priority_lost,
+            ModifyReason::UserModify,
+        )));
+
+        if let Some(trades) = modification.trades {
+            self.publish_trades(symbol, trades, false);
+        }
+        if let (Some(trigger_price), Some(band)) = (modification.stopped_at, band) {
+            self.trip_breaker(symbol, &band, trigger_price);
+        }
+        if auction {
+            self.publish_indicative(symbol);
+        }
+
+        Ok(modification.order)
+    }
+
+    fn delete(
+        &mut self,
+        id: OrderId,
+        rev: Revision,
+        requester: Option<AccountId>,
+    ) -> CommandResult {
+        let (symbol, _) = self
+            .find_owned_order(id, rev, requester)
+            .ok_or_else(|| not_found(id, rev))?;
+        let book = self.books.get_mut(&symbol).expect("Book must exist");
+        let order = book
+            .delete_order(&(id, rev))
+            .map_err(|_| not_found(id, rev))?;
+
+        self.publish(OrderDeleted {
+            order: order.clone(),
+            reason: CancelReason::UserCancel,
+        });
+        self.book_changed(order.symbol);
+
+        Ok(order)
+    }
+
+    /// Checks the parts of an order that are set by a create or modify
+    /// against the instrument limits and trading state.
+    fn validate(
+        &self,
+        symbol: Symbol,
+        price: Option<Price>,
+        quantity: Option<Quantity>,
+    ) -> Result<(), Reject> {
+        if let Some(price) = price
+            && price.0 <= 0
+        {
+            return Err(Reject::new(
+                RejectCode::InvalidPrice,
+                format!("Price must be positive, got {}", price.0),
+            ));
+        }
+        if let Some(quantity) = quantity {
+            if quantity.0 <= 0 {
+                return Err(Reject::new(
+                    RejectCode::InvalidQuantity,
+                    format!("Quantity must be positive, got {}", quantity.0),
+                ));
+            }
+            if let Some(&max) = self.max_order_quantity.get(&symbol)
+                && quantity.0 > max
+            {
+                return Err(Reject::new(
+                    RejectCode::RiskReject,
+                    format!(
+                        "Quantity {} exceeds the limit of {max} for {symbol}",
+                        quantity.0
+                    ),
+                ));
+            }
+        }
+
+        let state = self.trading_state(symbol);
+        if !state.accepts_orders() {
+            warn!("Rejected order entry for {symbol}: {state:?}");
+            return Err(Reject::new(
+                RejectCode::Halted,
+                format!("{symbol} is {state:?}"),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Checks a new order, including whether the instrument currently
+    /// accepts its time in force. Orders rest without trading in auctions,
+    /// so IOC orders are never accepted there.
+    fn validate_order(&self, order: &Order) -> Result<(), Reject> {
+        let symbol = order.symbol;
+        self.validate(symbol, Some(order.price), Some(order.quantity))?;
+
+        let time_in_force = order.time_in_force;
+        if let Some(phase) = self.phases.get(&symbol)
+            && !phase.accepts(time_in_force)
+        {
+            return Err(Reject::new(
+                RejectCode::PhaseRestricted,
+                format!("{time_in_force:?} orders are not accepted in {phase:?}"),
+            ));
+        }
+        if time_in_force == TimeInForce::Ioc && self.trading_state(symbol) == TradingState::Auction
+        {
+            return Err(Reject::new(
+                RejectCode::PhaseRestricted,
+                format!("{time_in_force:?} orders are not accepted in an auction"),
+            ));
+        }
+
+        Ok(())
+    }
+
+    fn reject(&mut self, order_id: OrderId, account: AccountId, reject: &Reject) {
+        debug!("Rejected {order_id:?}: {reject}");
+        self.publish(MarketEvent::OrderRejected {
+            order_id,
+            account,
+            reject: reject.clone(),
+        });
+    }
+
+    fn set_trading_state(&mut self, symbol: Option<Symbol>, state: TradingState) {
+        match symbol {
+            Some(s) => {
+                self.instrument_states.insert(s, state);
+                // an operator decision overrides a pending automatic resume
+                if let Some(breaker) = self.breakers.get_mut(&s) {
+                    breaker.cancel_resume();
                 }
             }
+            None => self.trading_state = state,
+        }
+        info!("Trading state of {symbol:?} changed to {state:?}");
+
+        // the phase of a scheduled instrument may still restrict it
+        let state = symbol.map_or(state, |s| self.trading_state(s));
+        self.publish(MarketEvent::TradingStateChanged { symbol, state });
+
+        let symbols: Vec<Symbol> = match symbol {
+            Some(s) => vec![s],
+            None => self.books.keys().copied().collect(),
+        };
+        for symbol in symbols {
+            self.follow_state(symbol);
+        }
+    }
+
+    /// Executes the crossed part of the book collected in an auction.
+    fn uncross(&mut self, symbol: Symbol) {
+        let reference = self.last_prices.get(&symbol).copied();
+        let Some(trades) = self
+            .books
+            .get_mut(&symbol)
+            .and_then(|book| book.uncross(reference))
+        else {
+            return;
+        };
+
+        info!("Uncrossed {symbol} with {} trades", trades.len());
+        self.publish_trades(symbol, trades, true);
+    }
+
+    fn publish_indicative(&mut self, symbol: Symbol) {
+        let reference = self.last_prices.get(&symbol).copied();
+        let equilibrium = self
+            .books
+            .get(&symbol)
+            .and_then(|book| book.equilibrium(reference));
+        self.publish(MarketEvent::IndicativeEquilibrium {
+            symbol,
+            equilibrium,
+        });
+    }
+
+    /// Updates the indicative equilibrium of an instrument in an auction
+    /// after orders left its book.
+    fn book_changed(&mut self, symbol: Symbol) {
+        if self.trading_state(symbol) == TradingState::Auction {
+            self.publish_indicative(symbol);
+        }
+    }
+
+    // Orders are addressed by id only, so every book is searched; the number
+    // of instruments is small compared to the number of orders.
+    fn find_order(&self, id: OrderId, rev: Revision) -> Option<(Symbol, &Order)> {
+        self.books
+            .iter()
+            .find_map(|(&symbol, book)| Some((symbol, book.get_order(id, rev)?)))
+    }
+
+    /// Like `find_order`, but hides the orders of other accounts from a
+    /// requester, so they can neither be changed nor probed.
+    fn find_owned_order(
+        &self,
+        id: OrderId,
+        rev: Revision,
+        requester: Option<AccountId>,
+    ) -> Option<(Symbol, &Order)> {
+        self.find_order(id, rev)
+            .filter(|(_, order)| requester.is_none_or(|account| account == order.account))
+    }
+
+    /// Publishes the trades of one order entry or auction. In an auction
+    /// both orders rested in the book, so partial fills of takers are
+    /// reported like those of makers.
+    fn publish_trades(&mut self, symbol: Symbol, trades: Vec<Trade>, auction: bool) {
+        // the fee tier of a trade depends on its day
+        let now = self.clock.now();
+        let mut trades: Vec<Trade> = trades.into_iter().map(|t| t.with_exec_time(now)).collect();
+        for trade in &mut trades {
+            trade.id = self.last_trade_id.increment();
+            self.fees.apply(trade);
+        }
+        if let Some(last) = trades.last() {
+            self.last_prices.insert(symbol, last.price);
+        }
+
+        if let Some(breaker) = self.breakers.get_mut(&symbol) {
+            for trade in &trades {
+                breaker.on_trade(trade.price, now);
+            }
+        }
+
+        for trade in trades {
+            let (maker_fill, taker_fill) = Fill::from_trade(&trade);
+            let maker_change = OrderChange::partial_fill(&trade, symbol);
+            let taker_change = auction
+                .then(|| OrderChange::taker_partial_fill(&trade, symbol))
+                .flatten();
+            self.publish(MarketEvent::TradeExecuted(trade));
+            for change in maker_change.into_iter().chain(taker_change) {
+                self.publish(OrderModified(change));
+            }
+            self.publish(MarketEvent::Fill(maker_fill));
+            self.publish(MarketEvent::Fill(taker_fill));
         }
     }
+
+    /// Halts the instrument after an order was stopped at `trigger_price`,
+    /// outside of the price band. The order rests crossed with that level
+    /// and trades when the book is uncrossed on the resume.
+    fn trip_breaker(&mut self, symbol: Symbol, band: &PriceBand, trigger_price: Price) {
+        let now = self.clock.now();
+        let Some(breaker) = self.breakers.get_mut(&symbol) else {
+            return;
+        };
+        let trip = breaker.trip(band, trigger_price, now);
+
+        warn!(
+            "Circuit breaker tripped for {symbol}: {:?} -> {:?}",
+            trip.reference_price, trip.trigger_price
+        );
+        self.instrument_states.insert(symbol, TradingState::Halted);
+        self.publish(MarketEvent::CircuitBreakerTriggered {
+            symbol,
+            reference_price: trip.reference_price,
+            trigger_price: trip.trigger_price,
+            resume_at: trip.resume_at,
+        });
+        self.publish(MarketEvent::TradingStateChanged {
+            symbol: Some(symbol),
+            state: TradingState::Halted,
+        });
+    }
+
+    /// When the next breaker resumes or the next phase starts.
+    fn next_deadline(&self) -> Option<Instant> {
+        let now = self.clock.now();
+        let resume = self.breakers.values().filter_map(|b| b.resume_at());
+        let phase_change = self
+            .schedules
+            .values()
+            .map(|schedule| schedule.next_change(now));
+
+        let at = resume.chain(phase_change).min()?;
+        Some(Instant::now() + Duration::from_nanos((at - now).max(0) as u64))
+    }
+
+    /// Catches up with the clock: resumes the instruments whose breaker
+    /// cooldown elapsed and moves scheduled instruments into their phase.
+    fn follow_clock(&mut self) {
+        self.resume_breakers(self.clock.now());
+        self.advance_schedules();
+        self.flush();
+    }
+
+    /// Moves every scheduled instrument into the phase of the current time.
+    fn advance_schedules(&mut self) {
+        if self.schedules.is_empty() {
+            return;
+        }
+        let now = self.clock.now();
+        let changed: Vec<(Symbol, TradingPhase)> = self
+            .schedules
+            .iter()
+            .map(|(&symbol, schedule)| (symbol, schedule.phase_at(now)))
+            .filter(|(symbol, phase)| self.phases.get(symbol) != Some(phase))
+            .collect();
+
+        for (symbol, phase) in changed {
+            self.enter_phase(symbol, phase);
+        }
+    }
+
+    /// Uncrosses the book when its auction ended and expires the DAY orders
+    /// at the close, before the instrument trades under the new phase. A
+    /// halted instrument stays halted, its auction is uncrossed once it
+    /// resumes into a phase that trades.
+    fn enter_phase(&mut self, symbol: Symbol, phase: TradingPhase) {
+        let previous = self.phases.insert(symbol, phase);
+        info!("{symbol} moved from {previous:?} to {phase:?}");
+
+        if previous.is_some_and(|p| p.collects()) && !phase.collects() && !self.halted(symbol) {
+            self.uncross(symbol);
+        }
+        if phase.ends_day() && previous.is_some_and(|p| !p.ends_day()) {
+            let day_orders = OrderFilter {
+                symbol: Some(symbol),
+                time_in_force: Some(TimeInForce::Day),
+                ..Default::default()
+            };
+            self.cancel_orders(&day_orders, CancelReason::Expired);
+        }
+
+        self.publish(MarketEvent::PhaseChanged { symbol, phase });
+        self.publish(MarketEvent::TradingStateChanged {
+            symbol: Some(symbol),
+            state: self.trading_state(symbol),
+        });
+        self.follow_state(symbol);
+    }
+
+    fn resume_breakers(&mut self, now: i64) {
+        let resumed: Vec<Symbol> = self
+            .breakers
+            .iter_mut()
+            .filter_map(|(&symbol, breaker)| breaker.poll_resume(now).then_some(symbol))
+            .collect();
+
+        for symbol in resumed {
+            info!("Circuit breaker cooldown elapsed, resuming {symbol}");
+            self.instrument_states.insert(symbol, TradingState::Open);
+            self.publish(MarketEvent::TradingStateChanged {
+                symbol: Some(symbol),
+                state: self.trading_state(symbol),
+            });
+            self.follow_state(symbol);
+        }
+    }
+
+    /// The most restrictive of the engine-wide state, the state set for the
+    /// instrument by the operator or its circuit breaker, and the state of its
+    /// phase. Halts are kept apart from phases, so a phase change never lifts
+    /// them.
+    fn trading_state(&self, symbol: Symbol) -> TradingState {
+        let phase_state = self
+            .phases
+            .get(&symbol)
+            .map(TradingPhase::trading_state)
+            .unwrap_or_default();
+
+        self.halt_state(symbol).max(phase_state)
+    }
+
+    fn halt_state(&self, symbol: Symbol) -> TradingState {
+        let instrument_state = self
+            .instrument_states
+            .get(&symbol)
+            .copied()
+            .unwrap_or_default();
+
+        self.trading_state.max(instrument_state)
+    }
+
+    /// Whether the operator or a circuit breaker stopped the instrument.
+    fn halted(&self, symbol: Symbol) -> bool {
+        !self.halt_state(symbol).accepts_orders()
+    }
+
+    /// Trades the book once the instrument is open, or updates the
+    /// indicative equilibrium while it collects orders.
+    fn follow_state(&mut self, symbol: Symbol) {
+        match self.trading_state(symbol) {
+            TradingState::Open => self.uncross(symbol),
+            TradingState::Auction => self.publish_indicative(symbol),
+            TradingState::Halted | TradingState::Closed => {}
+        }
+    }
+
+    fn cancel_orders(&mut self, filter: &OrderFilter, reason: CancelReason) -> Vec<Order> {
+        let cancelled: Vec<Order> = self
+            .books
+            .iter_mut()
+            .filter(|(symbol, _)| filter.symbol.is_none_or(|s| s == **symbol))
+            .flat_map(|(_, book)| book.cancel_orders(filter))
+            .collect();
+
+        for o in &cancelled {
+            self.publish(OrderDeleted {
+                order: o.clone(),
+                reason,
+            });
+        }
+        let mut symbols: Vec<Symbol> = cancelled.iter().map(|o| o.symbol).collect();
+        symbols.sort();
+        symbols.dedup();
+        for symbol in symbols {
+            self.book_changed(symbol);
+        }
+
+        cancelled
+    }
+
+    fn publish(&mut self, event: MarketEvent) {
+        self.sequence += 1;
+        if !self.feeds.is_empty() {
+            self.batch.push(event.clone());
+        }
+        if let Some(subscribers) = event.owner().and_then(|a| self.subscribers.get(&a)) {
+            for subscriber in subscribers {
+                // closed subscribers are dropped on the next subscription
+                let _ = subscriber.send(event.clone());
+            }
+        }
+        if let Err(e) = self.ws_tx.send(event) {
+            error!("Failed to broadcast message: {e}")
+        };
+    }
+
+    /// Sends the events of the finished command to the feeds.
+    fn flush(&mut self) {
+        if self.batch.is_empty() {
+            return;
+        }
+        let batch = EventBatch {
+            sequence: self.sequence + 1 - self.batch.len() as u64,
+            events: std::mem::take(&mut self.batch),
+        };
+        for feed in &self.feeds {
+            // closed feeds are dropped on the next subscription
+            let _ = feed.send(batch.clone());
+        }
+    }
+}
+
+fn not_found(id: OrderId, rev: Revision) -> Reject {
+    Reject::new(
+        RejectCode::NotFound,
+        format!("Order {} revision {} not found", id.0, rev.0),
+    )
 }
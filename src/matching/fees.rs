@@ -0,0 +1,202 @@
+use crate::configuration::{FeeSettings, FeeTier};
+use crate::domain::account::AccountId;
+use crate::domain::order::PRICE_SCALE;
+use crate::domain::trade::Trade;
+use log::warn;
+use std::collections::{HashMap, VecDeque};
+
+const WINDOW_DAYS: i64 = 30;
+const NANOS_PER_DAY: i64 = 86_400 * 1_000_000_000;
+
+/// Traded notional of one account, bucketed by day.
+#[derive(Default, Debug)]
+struct RollingVolume {
+    days: VecDeque<(i64, i64)>,
+    total: i64,
+}
+
+impl RollingVolume {
+    fn total(&mut self, day: i64) -> i64 {
+        while let Some(&(oldest, volume)) = self.days.front() {
+            if day - oldest < WINDOW_DAYS {
+                break;
+            }
+            self.total -= volume;
+            self.days.pop_front();
+        }
+        self.total
+    }
+
+    fn add(&mut self, day: i64, volume: i64) {
+        match self.days.back_mut() {
+            Some((last, total)) if *last == day => *total += volume,
+            _ => self.days.push_back((day, volume)),
+        }
+        self.total += volume;
+    }
+}
+
+#[derive(Default, Debug)]
+pub struct FeeEngine {
+    tiers: Vec<FeeTier>,
+    pinned: HashMap<AccountId, usize>,
+    volumes: HashMap<AccountId, RollingVolume>,
+}
+
+impl FeeEngine {
+    pub fn new(settings: FeeSettings) -> Self {
+        let mut tiers = settings.tiers;
+        tiers.sort_by_key(|t| t.min_volume);
+
+        let pinned = settings
+            .accounts
+            .into_iter()
+            .filter_map(|a| match tiers.iter().position(|t| t.name == a.tier) {
+                Some(tier) => Some((a.account, tier)),
+                None => {
+                    warn!("Unknown fee tier {} for {:?}", a.tier, a.account);
+                    None
+                }
+            })
+            .collect();
+
+        FeeEngine {
+            tiers,
+            pinned,
+            volumes: HashMap::new(),
+        }
+    }
+
+    /// Charges maker and taker fees on the trade and adds its notional to
+    /// the rolling volume of both accounts.
+    pub fn apply(&mut self, trade: &mut Trade) {
+        let day = trade.exec_time() / NANOS_PER_DAY;
+        let notional = trade.notional();
+
+        if let Some(tier) = self.tier(trade.maker.account, day) {
+            trade.maker.fee = fee(trade, tier.maker_bps);
+        }
+        if let Some(tier) = self.tier(trade.taker.account, day) {
+            trade.taker.fee = fee(trade, tier.taker_bps);
+        }
+
+        for account in [trade.maker.account, trade.taker.account] {
+            self.volumes
+                .entry(account)
+                .or_default()
+                .add(day, notional.abs());
+        }
+    }
+
+    fn tier(&mut self, account: AccountId, day: i64) -> Option<&FeeTier> {
+        if let Some(&pinned) = self.pinned.get(&account) {
+            return self.tiers.get(pinned);
+        }
+
+        let volume = self.volumes.get_mut(&account).map_or(0, |v| v.total(day));
+        self.tiers.iter().rev().find(|t| t.min_volume <= volume)
+    }
+}
+
+/// `bps` of the traded value, rounded up in favour of the exchange: small
+/// trades still pay a fee, and rebates never exceed their rate.
+fn fee(trade: &Trade, bps: i64) -> i64 {
+    let charged = trade.price.0 as i128 * trade.quantity.0 as i128 * bps as i128;
+    let scale = PRICE_SCALE as i128 * 10_000;
+    (charged / scale + (charged % scale > 0) as i128) as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::configuration::AccountFeeTier;
+    use crate::domain::side::Side;
+    use uuid::Uuid;
+
+    fn settings() -> FeeSettings {
+        FeeSettings {
+            tiers: vec![
+                FeeTier {
+                    name: "vip".to_string(),
+                    min_volume: 1_000_000,
+                    maker_bps: -2,
+                    taker_bps: 5,
+                },
+                FeeTier {
+                    name: "base".to_string(),
+                    min_volume: 0,
+                    maker_bps: 10,
+                    taker_bps: 20,
+                },
+            ],
+            accounts: vec![AccountFeeTier {
+                account: AccountId(9),
+                tier: "vip".to_string(),
+            }],
+        }
+    }
+
+    fn trade(price: i64, quantity: i64, maker: u64, taker: u64) -> Trade {
+        Trade::new(price, quantity, Side::Buy, Uuid::new_v4(), Uuid::new_v4())
+            .with_accounts(AccountId(maker), AccountId(taker))
+    }
+
+    #[test]
+    fn charges_maker_and_taker_rates_of_the_tier() {
+        let mut fees = FeeEngine::new(settings());
+        // notional 10_000 * 100_000 / PRICE_SCALE = 10_000_000
+        let mut t = trade(10_000, 100_000, 1, 2);
+
+        fees.apply(&mut t);
+
+        assert_eq!(t.maker.fee, 10_000);
+        assert_eq!(t.taker.fee, 20_000);
+    }
+
+    #[test]
+    fn volume_moves_accounts_into_higher_tiers() {
+        let mut fees = FeeEngine::new(settings());
+        let mut first = trade(10_000, 100_000, 1, 2);
+        let mut second = trade(10_000, 100_000, 1, 2);
+
+        fees.apply(&mut first);
+        fees.apply(&mut second);
+
+        assert_eq!(second.maker.fee, -2_000, "Maker earns the rebate");
+        assert_eq!(second.taker.fee, 5_000);
+    }
+
+    #[test]
+    fn pinned_accounts_use_their_tier() {
+        let mut fees = FeeEngine::new(settings());
+        let mut t = trade(10_000, 100_000, 9, 2);
+
+        fees.apply(&mut t);
+
+        assert_eq!(t.maker.fee, -2_000);
+    }
+
+    #[test]
+    fn sub_unit_fees_are_rounded_in_favour_of_the_exchange() {
+        let mut fees = FeeEngine::new(settings());
+        // notional 0.5, 20 bps of it are 0.001
+        let mut t = trade(50, 1, 9, 2);
+
+        fees.apply(&mut t);
+
+        assert_eq!(t.taker.fee, 1, "Fees are rounded up");
+        assert_eq!(t.maker.fee, 0, "Rebates are rounded down");
+    }
+
+    #[test]
+    fn volume_older_than_the_window_expires() {
+        let mut volume = RollingVolume::default();
+
+        volume.add(0, 100);
+        volume.add(10, 50);
+
+        assert_eq!(volume.total(29), 150);
+        assert_eq!(volume.total(30), 50);
+        assert_eq!(volume.total(40), 0);
+    }
+}
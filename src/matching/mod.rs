@@ -1,3 +1,8 @@
+pub mod circuit_breaker;
+pub mod clock;
 pub mod command;
 pub mod engine;
+pub mod fees;
+pub mod reject;
+pub mod schedule;
 pub mod state;
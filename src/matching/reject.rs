@@ -0,0 +1,52 @@
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+
+#[derive(Deserialize, Serialize, Debug, Eq, PartialEq, Copy, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum RejectCode {
+    NotFound,
+    InvalidPrice,
+    InvalidQuantity,
+    /// Failed a pre-trade risk check such as the maximum order size.
+    RiskReject,
+    Halted,
+    EngineUnavailable,
+    /// The request could not be parsed.
+    InvalidRequest,
+    RateLimited,
+    /// Valid on its own, but not placed because another order of the same
+    /// atomic batch was rejected.
+    BatchAborted,
+    /// The time in force is not accepted in the current trading phase.
+    PhaseRestricted,
+}
+
+/// Why a command was refused, returned to the requester and published as
+/// `OrderRejected`.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct Reject {
+    pub code: RejectCode,
+    pub message: String,
+}
+
+impl Reject {
+    pub fn new<M: Into<String>>(code: RejectCode, message: M) -> Self {
+        Reject {
+            code,
+            message: message.into(),
+        }
+    }
+
+    pub fn engine_unavailable() -> Self {
+        Reject::new(
+            RejectCode::EngineUnavailable,
+            "Matching engine is not available",
+        )
+    }
+}
+
+impl Display for Reject {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}: {}", self.code, self.message)
+    }
+}
@@ -0,0 +1,145 @@
+use crate::configuration::ScheduleSettings;
+use crate::domain::trading_phase::TradingPhase;
+use serde::Deserialize;
+use std::fmt::{Display, Formatter};
+
+const SECOND: i64 = 1_000_000_000;
+const DAY: i64 = 86_400 * SECOND;
+
+/// Time of day in UTC, written as `HH:MM` or `HH:MM:SS`.
+#[derive(Deserialize, Debug, Eq, PartialEq, Ord, PartialOrd, Copy, Clone)]
+#[serde(try_from = "String")]
+pub struct TimeOfDay(i64);
+
+#[derive(Debug)]
+pub struct InvalidTimeOfDay(pub String);
+
+impl Display for InvalidTimeOfDay {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Invalid time of day {:?}, expected HH:MM or HH:MM:SS",
+            self.0
+        )
+    }
+}
+
+impl TryFrom<String> for TimeOfDay {
+    type Error = InvalidTimeOfDay;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let parts: Vec<Option<i64>> = value.split(':').map(|p| p.parse().ok()).collect();
+        let seconds = match parts[..] {
+            [Some(h), Some(m)] if h < 24 && m < 60 => h * 3_600 + m * 60,
+            [Some(h), Some(m), Some(s)] if h < 24 && m < 60 && s < 60 => h * 3_600 + m * 60 + s,
+            _ => return Err(InvalidTimeOfDay(value)),
+        };
+        Ok(TimeOfDay(seconds * SECOND))
+    }
+}
+
+impl TimeOfDay {
+    pub fn hms(hours: i64, minutes: i64, seconds: i64) -> Self {
+        TimeOfDay((hours * 3_600 + minutes * 60 + seconds) * SECOND)
+    }
+
+    /// Nanoseconds since midnight.
+    pub fn nanos(&self) -> i64 {
+        self.0
+    }
+}
+
+/// Phases of the trading day of one instrument. The day repeats, so the
+/// last phase lasts until the first one of the next day.
+#[derive(Debug)]
+pub struct Schedule {
+    /// Phases by start, at least one.
+    phases: Vec<(TimeOfDay, TradingPhase)>,
+}
+
+impl Schedule {
+    /// `None` for a schedule without phases.
+    pub fn new(settings: ScheduleSettings) -> Option<Self> {
+        let mut phases: Vec<_> = settings
+            .phases
+            .into_iter()
+            .map(|p| (p.start, p.phase))
+            .collect();
+        phases.sort_by_key(|&(start, _)| start);
+        (!phases.is_empty()).then_some(Schedule { phases })
+    }
+
+    /// Phase at `now` in unix nanoseconds.
+    pub fn phase_at(&self, now: i64) -> TradingPhase {
+        let time = now.rem_euclid(DAY);
+        let (_, phase) = self
+            .phases
+            .iter()
+            .rev()
+            .find(|(start, _)| start.nanos() <= time)
+            .or(self.phases.last())
+            .expect("Schedules have phases");
+        *phase
+    }
+
+    /// When the phase following the one at `now` starts.
+    pub fn next_change(&self, now: i64) -> i64 {
+        let midnight = now - now.rem_euclid(DAY);
+        let next = self
+            .phases
+            .iter()
+            .map(|(start, _)| midnight + start.nanos())
+            .find(|&start| start > now);
+        next.unwrap_or(midnight + DAY + self.phases[0].0.nanos())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::configuration::PhaseStart;
+
+    fn schedule() -> Schedule {
+        let start = |hours, phase| PhaseStart {
+            start: TimeOfDay::hms(hours, 0, 0),
+            phase,
+        };
+        Schedule::new(ScheduleSettings {
+            phases: vec![
+                start(17, TradingPhase::Closed),
+                start(8, TradingPhase::PreOpen),
+                start(9, TradingPhase::Continuous),
+            ],
+        })
+        .expect("Schedule has phases")
+    }
+
+    fn at(day: i64, hours: i64, minutes: i64) -> i64 {
+        day * DAY + TimeOfDay::hms(hours, minutes, 0).nanos()
+    }
+
+    #[test]
+    fn phases_repeat_every_day() {
+        let schedule = schedule();
+
+        assert_eq!(schedule.phase_at(at(3, 8, 30)), TradingPhase::PreOpen);
+        assert_eq!(schedule.phase_at(at(3, 9, 0)), TradingPhase::Continuous);
+        assert_eq!(schedule.phase_at(at(3, 20, 0)), TradingPhase::Closed);
+        // the last phase of the day lasts past midnight
+        assert_eq!(schedule.phase_at(at(4, 2, 0)), TradingPhase::Closed);
+
+        assert_eq!(schedule.next_change(at(3, 8, 30)), at(3, 9, 0));
+        assert_eq!(schedule.next_change(at(3, 9, 0)), at(3, 17, 0));
+        assert_eq!(schedule.next_change(at(3, 20, 0)), at(4, 8, 0));
+    }
+
+    #[test]
+    fn times_of_day_are_parsed() {
+        let parse = |time: &str| TimeOfDay::try_from(time.to_string()).ok();
+
+        assert_eq!(parse("08:30"), Some(TimeOfDay::hms(8, 30, 0)));
+        assert_eq!(parse("17:35:10"), Some(TimeOfDay::hms(17, 35, 10)));
+        assert_eq!(parse("24:00"), None);
+        assert_eq!(parse("8h"), None);
+    }
+}
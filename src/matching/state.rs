@@ -1,10 +1,216 @@
-use crate::matching::command::MatchingEngineCommand;
-use crate::matching::engine::MarketEvent;
-use tokio::sync::broadcast;
+use crate::auth::ApiKeys;
+use crate::configuration::{
+    ApiKey, CandleSettings, DepthSettings, FixSettings, ItchSettings, OuchSettings, TickerSettings,
+    TradeHistorySettings,
+};
+use crate::domain::account::AccountId;
+use crate::domain::order_book_level::OrderBookSnapshot;
+use crate::domain::symbol::Symbol;
+use crate::market_data::candles::CandleStore;
+use crate::market_data::depth::DepthStore;
+use crate::market_data::l3::L3Feed;
+use crate::market_data::ticker::TickerStore;
+use crate::market_data::trades::TradeHistory;
+use crate::matching::command::{BestOfBook, CommandResult, MatchingEngineCommand, Responder};
+use crate::matching::engine::{EventBatch, MarketEvent};
+use crate::matching::reject::Reject;
+use crate::rate_limit::RateLimits;
+use crate::routes::encoding::EncodedEvent;
+use log::error;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
 use tokio::sync::mpsc::Sender;
+use tokio::sync::{broadcast, mpsc, oneshot};
+
+const WS_FRAME_BUFFER: usize = 1_000;
 
 #[derive(Clone)]
 pub struct AppState {
     pub tx: Sender<MatchingEngineCommand>,
     pub ws_tx: broadcast::Sender<MarketEvent>,
+    /// Engine events wrapped once for all WebSocket subscribers.
+    pub ws_frames: broadcast::Sender<Arc<EncodedEvent>>,
+    pub sessions: Arc<SessionRegistry>,
+    pub admin_token: Option<String>,
+    pub api_keys: Arc<ApiKeys>,
+    pub rate_limits: Arc<RateLimits>,
+    pub ouch: Option<Arc<OuchSettings>>,
+    pub fix: Option<Arc<FixSettings>>,
+    pub itch: Option<Arc<ItchSettings>>,
+    pub candles: Arc<CandleStore>,
+    pub trades: Arc<TradeHistory>,
+    pub tickers: Arc<TickerStore>,
+    pub l3: Arc<L3Feed>,
+    pub depth: Arc<DepthStore>,
+}
+
+impl AppState {
+    pub fn new(tx: Sender<MatchingEngineCommand>, ws_tx: broadcast::Sender<MarketEvent>) -> Self {
+        let (ws_frames, _) = broadcast::channel(WS_FRAME_BUFFER);
+        AppState {
+            tx,
+            ws_tx,
+            ws_frames,
+            sessions: Arc::default(),
+            admin_token: None,
+            api_keys: Arc::default(),
+            rate_limits: Arc::default(),
+            ouch: None,
+            fix: None,
+            itch: None,
+            candles: Arc::new(CandleStore::new(&CandleSettings::default())),
+            trades: Arc::new(TradeHistory::new(&TradeHistorySettings::default())),
+            tickers: Arc::new(TickerStore::new(&TickerSettings::default())),
+            l3: Arc::default(),
+            depth: Arc::new(DepthStore::new(&DepthSettings::default())),
+        }
+    }
+
+    pub fn with_rate_limits(mut self, rate_limits: RateLimits) -> Self {
+        self.rate_limits = Arc::new(rate_limits);
+        self
+    }
+
+    /// Starts the binary order entry gateway next to the HTTP server.
+    pub fn with_ouch(mut self, settings: Option<OuchSettings>) -> Self {
+        self.ouch = settings.map(Arc::new);
+        self
+    }
+
+    /// Starts the FIX order entry gateway next to the HTTP server.
+    pub fn with_fix(mut self, settings: Option<FixSettings>) -> Self {
+        self.fix = settings.map(Arc::new);
+        self
+    }
+
+    /// Publishes the binary market data feed.
+    pub fn with_itch(mut self, settings: Option<ItchSettings>) -> Self {
+        self.itch = settings.map(Arc::new);
+        self
+    }
+
+    pub fn with_candles(mut self, settings: CandleSettings) -> Self {
+        self.candles = Arc::new(CandleStore::new(&settings));
+        self
+    }
+
+    pub fn with_trade_history(mut self, settings: TradeHistorySettings) -> Self {
+        self.trades = Arc::new(TradeHistory::new(&settings));
+        self
+    }
+
+    pub fn with_ticker(mut self, settings: TickerSettings) -> Self {
+        self.tickers = Arc::new(TickerStore::new(&settings));
+        self
+    }
+
+    pub fn with_depth(mut self, settings: DepthSettings) -> Self {
+        self.depth = Arc::new(DepthStore::new(&settings));
+        self
+    }
+
+    pub fn with_admin_token(mut self, admin_token: Option<String>) -> Self {
+        self.admin_token = admin_token;
+        self
+    }
+
+    pub fn with_api_keys(mut self, keys: Vec<ApiKey>) -> Self {
+        self.api_keys = Arc::new(ApiKeys::new(keys));
+        self
+    }
+
+    /// Runs an order entry command and waits for its outcome.
+    pub async fn request(&self, cmd: MatchingEngineCommand) -> CommandResult {
+        let (respond_to, response) = oneshot::channel();
+
+        if let Err(e) = self
+            .tx
+            .send(MatchingEngineCommand::Request(Box::new(cmd), respond_to))
+            .await
+        {
+            error!("Failed to send command: {e}");
+            return Err(Reject::engine_unavailable());
+        }
+
+        response.await.unwrap_or_else(|e| {
+            error!("Matching engine dropped the request: {e}");
+            Err(Reject::engine_unavailable())
+        })
+    }
+
+    pub async fn best_of_book(&self, symbol: Symbol) -> Result<BestOfBook, Reject> {
+        self.query(|respond_to| MatchingEngineCommand::BestOfBook(symbol, respond_to))
+            .await
+    }
+
+    pub async fn snapshot(&self, symbol: Symbol) -> Result<OrderBookSnapshot, Reject> {
+        self.query(|respond_to| MatchingEngineCommand::Snapshot(symbol, respond_to))
+            .await
+    }
+
+    /// Every later event about orders of the account, without losing any.
+    /// `None` once the engine is gone.
+    pub async fn subscribe(
+        &self,
+        account: AccountId,
+    ) -> Option<mpsc::UnboundedReceiver<MarketEvent>> {
+        let (subscriber, events) = mpsc::unbounded_channel();
+        let cmd = MatchingEngineCommand::Subscribe(account, subscriber);
+        if let Err(e) = self.tx.send(cmd).await {
+            error!("Failed to subscribe to the events of {account:?}: {e}");
+            return None;
+        }
+        Some(events)
+    }
+
+    /// Every later event of the engine in batches, without losing any.
+    /// Subscribes without waiting, so feeds created before the server starts
+    /// see every order.
+    pub fn subscribe_all(&self) -> mpsc::UnboundedReceiver<EventBatch> {
+        let (subscriber, events) = mpsc::unbounded_channel();
+        let cmd = MatchingEngineCommand::SubscribeAll(subscriber);
+        if let Err(e) = self.tx.try_send(cmd) {
+            error!("Failed to subscribe to the events of the engine: {e}");
+        }
+        events
+    }
+
+    /// Asks the engine about its state between two commands.
+    async fn query<T>(
+        &self,
+        cmd: impl FnOnce(Responder<T>) -> MatchingEngineCommand,
+    ) -> Result<T, Reject> {
+        let (respond_to, response) = oneshot::channel();
+
+        if let Err(e) = self.tx.send(cmd(respond_to)).await {
+            error!("Failed to send command: {e}");
+            return Err(Reject::engine_unavailable());
+        }
+
+        response.await.map_err(|e| {
+            error!("Matching engine dropped the request: {e}");
+            Reject::engine_unavailable()
+        })
+    }
+}
+
+/// Tracks the latest WebSocket session of every account, so a session that
+/// reconnects within the cancel-on-disconnect grace period keeps its orders.
+#[derive(Default)]
+pub struct SessionRegistry {
+    generations: Mutex<HashMap<AccountId, u64>>,
+}
+
+impl SessionRegistry {
+    pub fn connect(&self, account: AccountId) -> u64 {
+        let mut generations = self.generations.lock().expect("Session registry poisoned");
+        let generation = generations.entry(account).or_default();
+        *generation += 1;
+        *generation
+    }
+
+    pub fn is_latest(&self, account: AccountId, generation: u64) -> bool {
+        let generations = self.generations.lock().expect("Session registry poisoned");
+        generations.get(&account) == Some(&generation)
+    }
 }
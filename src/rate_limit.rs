@@ -0,0 +1,272 @@
+use crate::configuration::{RateLimitSettings, TokenBucketSettings};
+use crate::domain::account::AccountId;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::net::IpAddr;
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+/// Buckets that are full again carry no state worth keeping, so they are
+/// dropped once this many keys are tracked. The next pruning waits until the
+/// remaining keys doubled, so its cost is spread over the keys added since.
+const PRUNE_THRESHOLD: usize = 10_000;
+
+#[derive(Debug)]
+struct TokenBucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+#[derive(Debug)]
+struct Buckets<K> {
+    buckets: HashMap<K, TokenBucket>,
+    prune_at: usize,
+}
+
+impl<K> Default for Buckets<K> {
+    fn default() -> Self {
+        Buckets {
+            buckets: HashMap::new(),
+            prune_at: PRUNE_THRESHOLD,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Throttled {
+    RetryAfter(Duration),
+    /// The request costs more than the bucket can ever hold.
+    ExceedsBurst,
+}
+
+#[derive(Debug)]
+pub struct RateLimiter<K> {
+    settings: TokenBucketSettings,
+    buckets: Mutex<Buckets<K>>,
+}
+
+impl<K: Hash + Eq> RateLimiter<K> {
+    pub fn new(settings: TokenBucketSettings) -> Self {
+        RateLimiter {
+            settings,
+            buckets: Mutex::default(),
+        }
+    }
+
+    /// Takes `cost` tokens from the bucket of `key`, or returns how long the
+    /// caller has to wait until enough tokens are available.
+    pub fn try_acquire(&self, key: K, cost: u32) -> Result<(), Throttled> {
+        self.try_acquire_at(key, cost, Instant::now())
+    }
+
+    fn try_acquire_at(&self, key: K, cost: u32, now: Instant) -> Result<(), Throttled> {
+        let mut buckets = self.lock();
+        let bucket = self.refill(&mut buckets, key, now);
+        self.check(bucket, cost)?;
+        bucket.tokens -= cost as f64;
+        Ok(())
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Buckets<K>> {
+        self.buckets.lock().expect("Rate limiter poisoned")
+    }
+
+    /// Bucket of `key` with the tokens earned since it was last used.
+    fn refill<'a>(&self, buckets: &'a mut Buckets<K>, key: K, now: Instant) -> &'a mut TokenBucket {
+        let burst = self.settings.burst as f64;
+        let rate = self.settings.refill_per_second as f64;
+
+        if buckets.buckets.len() >= buckets.prune_at && !buckets.buckets.contains_key(&key) {
+            buckets.buckets.retain(|_, b| {
+                b.tokens + now.duration_since(b.updated).as_secs_f64() * rate < burst
+            });
+            buckets.prune_at = PRUNE_THRESHOLD.max(2 * buckets.buckets.len());
+        }
+
+        let bucket = buckets.buckets.entry(key).or_insert(TokenBucket {
+            tokens: burst,
+            updated: now,
+        });
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * rate).min(burst);
+        bucket.updated = now;
+        bucket
+    }
+
+    /// Whether the bucket holds `cost` tokens, without taking them.
+    fn check(&self, bucket: &TokenBucket, cost: u32) -> Result<(), Throttled> {
+        let burst = self.settings.burst as f64;
+        let rate = self.settings.refill_per_second as f64;
+        let cost = cost as f64;
+
+        if bucket.tokens >= cost {
+            return Ok(());
+        }
+        if cost > burst || rate <= 0.0 {
+            return Err(Throttled::ExceedsBurst);
+        }
+        Err(Throttled::RetryAfter(Duration::from_secs_f64(
+            (cost - bucket.tokens) / rate,
+        )))
+    }
+}
+
+/// Order entry limits, applied per authenticated account and per client IP.
+#[derive(Debug)]
+pub struct RateLimits {
+    pub account: RateLimiter<AccountId>,
+    pub ip: RateLimiter<IpAddr>,
+}
+
+impl RateLimits {
+    pub fn new(settings: RateLimitSettings) -> Self {
+        RateLimits {
+            account: RateLimiter::new(settings.account),
+            ip: RateLimiter::new(settings.ip),
+        }
+    }
+
+    /// Charges one token per request to the client IP and one token per
+    /// order to the authenticated account. Nothing is charged unless both
+    /// buckets hold enough tokens.
+    pub fn check(
+        &self,
+        ip: Option<IpAddr>,
+        account: Option<AccountId>,
+        orders: u32,
+    ) -> Result<(), Throttled> {
+        self.check_at(ip, account, orders, Instant::now())
+    }
+
+    fn check_at(
+        &self,
+        ip: Option<IpAddr>,
+        account: Option<AccountId>,
+        orders: u32,
+        now: Instant,
+    ) -> Result<(), Throttled> {
+        // always locked in this order, so concurrent checks cannot deadlock
+        let mut accounts = self.account.lock();
+        let mut ips = self.ip.lock();
+
+        let ip = ip.map(|ip| self.ip.refill(&mut ips, ip, now));
+        let account = account.map(|account| self.account.refill(&mut accounts, account, now));
+
+        if let Some(bucket) = &ip {
+            self.ip.check(bucket, 1)?;
+        }
+        if let Some(bucket) = &account {
+            self.account.check(bucket, orders)?;
+        }
+
+        if let Some(bucket) = ip {
+            bucket.tokens -= 1.0;
+        }
+        if let Some(bucket) = account {
+            bucket.tokens -= orders as f64;
+        }
+        Ok(())
+    }
+}
+
+impl Default for RateLimits {
+    fn default() -> Self {
+        RateLimits::new(RateLimitSettings::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter(burst: u32, refill_per_second: u32) -> RateLimiter<u8> {
+        RateLimiter::new(TokenBucketSettings {
+            burst,
+            refill_per_second,
+        })
+    }
+
+    #[test]
+    fn allows_bursts_up_to_capacity() {
+        let limiter = limiter(3, 1);
+        let now = Instant::now();
+
+        for _ in 0..3 {
+            assert!(limiter.try_acquire_at(1, 1, now).is_ok());
+        }
+        let retry_after = limiter
+            .try_acquire_at(1, 1, now)
+            .expect_err("Bucket should be empty");
+
+        assert_eq!(retry_after, Throttled::RetryAfter(Duration::from_secs(1)));
+        assert!(
+            limiter.try_acquire_at(2, 1, now).is_ok(),
+            "Keys are independent"
+        );
+    }
+
+    #[test]
+    fn refills_over_time() {
+        let limiter = limiter(2, 10);
+        let now = Instant::now();
+
+        assert!(limiter.try_acquire_at(1, 2, now).is_ok());
+        assert!(limiter.try_acquire_at(1, 1, now).is_err());
+        assert!(
+            limiter
+                .try_acquire_at(1, 1, now + Duration::from_millis(100))
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn rejects_costs_above_burst() {
+        let limiter = limiter(2, 10);
+
+        assert_eq!(
+            limiter.try_acquire_at(1, 3, Instant::now()),
+            Err(Throttled::ExceedsBurst)
+        );
+    }
+
+    #[test]
+    fn full_buckets_are_pruned_once_the_keys_doubled() {
+        let limiter: RateLimiter<usize> = RateLimiter::new(TokenBucketSettings {
+            burst: 1,
+            refill_per_second: 1,
+        });
+        let now = Instant::now();
+
+        for key in 0..PRUNE_THRESHOLD {
+            limiter.try_acquire_at(key, 1, now).unwrap();
+        }
+        let later = now + Duration::from_secs(1);
+        limiter.try_acquire_at(PRUNE_THRESHOLD, 1, later).unwrap();
+
+        let buckets = limiter.lock();
+        assert_eq!(buckets.buckets.len(), 1, "Refilled buckets are dropped");
+        assert_eq!(buckets.prune_at, PRUNE_THRESHOLD);
+    }
+
+    #[test]
+    fn throttled_requests_are_not_charged() {
+        let limits = RateLimits::new(RateLimitSettings {
+            account: TokenBucketSettings {
+                burst: 1,
+                refill_per_second: 1,
+            },
+            ip: TokenBucketSettings {
+                burst: 1,
+                refill_per_second: 1,
+            },
+        });
+        let ip = Some(IpAddr::from([127, 0, 0, 1]));
+        let now = Instant::now();
+
+        assert!(limits.check_at(ip, Some(AccountId(1)), 2, now).is_err());
+        assert!(
+            limits.check_at(ip, Some(AccountId(1)), 1, now).is_ok(),
+            "The IP token was not taken by the throttled request"
+        );
+    }
+}
@@ -0,0 +1,34 @@
+use crate::matching::command::MatchingEngineCommand;
+use crate::matching::reject::Reject;
+use crate::matching::state::AppState;
+use crate::routes::auth::is_admin;
+use crate::routes::models::trading_state_change::TradingStateChange;
+use actix_web::{HttpRequest, HttpResponse, post, web};
+use log::{error, warn};
+
+#[post("/admin/trading_state")]
+async fn set_trading_state(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    change: web::Json<TradingStateChange>,
+) -> HttpResponse {
+    if !is_admin(&req, &state) {
+        warn!("Unauthorized admin request from {:?}", req.peer_addr());
+        return HttpResponse::Unauthorized().finish();
+    }
+
+    let TradingStateChange {
+        symbol,
+        state: new_state,
+    } = change.into_inner();
+    if let Err(e) = state
+        .tx
+        .send(MatchingEngineCommand::SetTradingState(symbol, new_state))
+        .await
+    {
+        error!("Failed to send trading state change: {e}");
+        return HttpResponse::ServiceUnavailable().json(Reject::engine_unavailable());
+    }
+
+    HttpResponse::Ok().finish()
+}
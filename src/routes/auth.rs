@@ -0,0 +1,25 @@
+use crate::auth::constant_time_eq;
+use crate::domain::account::AccountId;
+use crate::matching::state::AppState;
+use actix_web::HttpRequest;
+use actix_web::http::header;
+
+/// Account whose API key the request carries, `None` for anonymous clients.
+pub(crate) fn authenticate(req: &HttpRequest, state: &AppState) -> Option<AccountId> {
+    bearer_token(req).and_then(|key| state.api_keys.account(key))
+}
+
+/// Whether the request carries the configured admin token.
+pub(crate) fn is_admin(req: &HttpRequest, state: &AppState) -> bool {
+    let Some(expected) = state.admin_token.as_deref() else {
+        return false;
+    };
+    bearer_token(req).is_some_and(|token| constant_time_eq(token.as_bytes(), expected.as_bytes()))
+}
+
+fn bearer_token(req: &HttpRequest) -> Option<&str> {
+    req.headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+}
@@ -0,0 +1,59 @@
+use crate::matching::state::AppState;
+use crate::routes::models::book_query::{BookQuery, BookStreamParams, DepthStreamParams};
+use crate::routes::ws::{conflate, forward, negotiate};
+use actix_web::{Error, HttpRequest, HttpResponse, get, web};
+
+#[get("/book/l3")]
+async fn get_l3_book(state: web::Data<AppState>, query: web::Query<BookQuery>) -> HttpResponse {
+    match state.snapshot(query.symbol).await {
+        Ok(snapshot) => HttpResponse::Ok().json(snapshot),
+        Err(reject) => HttpResponse::ServiceUnavailable().json(reject),
+    }
+}
+
+pub async fn l3_stream(
+    req: HttpRequest,
+    stream: web::Payload,
+    data: web::Data<AppState>,
+    params: web::Query<BookStreamParams>,
+) -> Result<HttpResponse, Error> {
+    let (mut res, session, msg_stream) = actix_ws::handle(&req, stream)?;
+    let params = params.into_inner();
+    let encoding = negotiate(&req, &mut res, params.encoding);
+
+    forward(
+        session,
+        msg_stream,
+        data.l3.subscribe(),
+        encoding,
+        true,
+        move |message| {
+            params
+                .symbol
+                .is_none_or(|symbol| symbol == message.symbol())
+        },
+    );
+
+    Ok(res)
+}
+
+pub async fn depth_stream(
+    req: HttpRequest,
+    stream: web::Payload,
+    data: web::Data<AppState>,
+    params: web::Query<DepthStreamParams>,
+) -> Result<HttpResponse, Error> {
+    let (mut res, session, msg_stream) = actix_ws::handle(&req, stream)?;
+    let params = params.into_inner();
+    let encoding = negotiate(&req, &mut res, params.encoding);
+
+    conflate(
+        session,
+        msg_stream,
+        data.depth.subscribe(params.symbol),
+        encoding,
+        data.depth.interval(params.interval_ms),
+    );
+
+    Ok(res)
+}
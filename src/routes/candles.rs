@@ -0,0 +1,58 @@
+use crate::matching::state::AppState;
+use crate::routes::models::candle_query::{CandleQuery, CandleStreamParams};
+use crate::routes::ws::{forward, negotiate};
+use actix_web::{Error, HttpRequest, HttpResponse, get, web};
+use log::error;
+
+#[get("/candles")]
+async fn get_candles(state: web::Data<AppState>, query: web::Query<CandleQuery>) -> HttpResponse {
+    let CandleQuery {
+        symbol,
+        interval,
+        from,
+        to,
+    } = query.into_inner();
+    let store = state.candles.clone();
+    let from = from.unwrap_or(i64::MIN);
+    let to = to.unwrap_or(i64::MAX);
+
+    // spilled bars are read from disk
+    match web::block(move || store.range(symbol, interval, from, to)).await {
+        Ok(Ok(candles)) => HttpResponse::Ok().json(candles),
+        Ok(Err(e)) => {
+            error!("Failed to read spilled candles: {e}");
+            HttpResponse::InternalServerError().finish()
+        }
+        Err(e) => {
+            error!("Failed to query candles: {e}");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+pub async fn candle_stream(
+    req: HttpRequest,
+    stream: web::Payload,
+    data: web::Data<AppState>,
+    params: web::Query<CandleStreamParams>,
+) -> Result<HttpResponse, Error> {
+    let (mut res, session, msg_stream) = actix_ws::handle(&req, stream)?;
+    let params = params.into_inner();
+    let encoding = negotiate(&req, &mut res, params.encoding);
+
+    forward(
+        session,
+        msg_stream,
+        data.candles.subscribe(),
+        encoding,
+        false,
+        move |candle| {
+            params.symbol.is_none_or(|symbol| symbol == candle.symbol)
+                && params
+                    .interval
+                    .is_none_or(|interval| interval == candle.interval)
+        },
+    );
+
+    Ok(res)
+}
@@ -0,0 +1,177 @@
+//! Wire encodings of market events for the WebSocket feed.
+//!
+//! Every event is wrapped once in an [`Encoded`] that all subscribers share,
+//! so each encoding is produced at most once per event, by the first
+//! subscriber that needs it.
+
+use crate::matching::engine::MarketEvent;
+use actix_web::web::Bytes;
+use bytestring::ByteString;
+use log::error;
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, OnceLock};
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Encoding {
+    /// Text frames, the default.
+    #[default]
+    Json,
+    /// Binary frames of MessagePack maps with the field names of the JSON.
+    Msgpack,
+    /// Binary frames of the compact bincode layout of the event.
+    Binary,
+}
+
+impl Encoding {
+    pub const ALL: [Encoding; 3] = [Encoding::Json, Encoding::Msgpack, Encoding::Binary];
+
+    /// Name of the encoding as a WebSocket subprotocol.
+    pub fn subprotocol(&self) -> &'static str {
+        match self {
+            Encoding::Json => "json",
+            Encoding::Msgpack => "msgpack",
+            Encoding::Binary => "binary",
+        }
+    }
+
+    pub fn from_subprotocol(name: &str) -> Option<Encoding> {
+        Encoding::ALL
+            .into_iter()
+            .find(|encoding| encoding.subprotocol() == name)
+    }
+}
+
+pub enum Frame {
+    Text(ByteString),
+    Binary(Bytes),
+}
+
+pub struct Encoded<T> {
+    pub event: T,
+    json: OnceLock<ByteString>,
+    msgpack: OnceLock<Bytes>,
+    binary: OnceLock<Bytes>,
+}
+
+pub type EncodedEvent = Encoded<MarketEvent>;
+
+impl<T: Serialize> Encoded<T> {
+    pub fn new(event: T) -> Self {
+        Encoded {
+            event,
+            json: OnceLock::new(),
+            msgpack: OnceLock::new(),
+            binary: OnceLock::new(),
+        }
+    }
+
+    /// The event in `encoding`, serialized on first use. Frames share their
+    /// buffer, cloning them is cheap.
+    pub fn frame(&self, encoding: Encoding) -> Frame {
+        match encoding {
+            Encoding::Json => Frame::Text(
+                self.json
+                    .get_or_init(|| {
+                        serde_json::to_string(&self.event)
+                            .expect("Events always serialize")
+                            .into()
+                    })
+                    .clone(),
+            ),
+            Encoding::Msgpack => Frame::Binary(
+                self.msgpack
+                    .get_or_init(|| {
+                        rmp_serde::to_vec_named(&self.event)
+                            .expect("Events always serialize")
+                            .into()
+                    })
+                    .clone(),
+            ),
+            Encoding::Binary => Frame::Binary(
+                self.binary
+                    .get_or_init(|| {
+                        bincode::serialize(&self.event)
+                            .expect("Events always serialize")
+                            .into()
+                    })
+                    .clone(),
+            ),
+        }
+    }
+}
+
+/// Wraps every engine event for the WebSocket subscribers.
+pub async fn encode_events(
+    mut events: broadcast::Receiver<MarketEvent>,
+    frames: broadcast::Sender<Arc<EncodedEvent>>,
+) {
+    loop {
+        match events.recv().await {
+            // nobody may be listening
+            Ok(event) => drop(frames.send(Arc::new(EncodedEvent::new(event)))),
+            Err(RecvError::Lagged(missed)) => error!("WebSocket feed missed {missed} events"),
+            Err(RecvError::Closed) => break,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::order::Order;
+    use crate::domain::order_entry::OrderEntry;
+    use crate::domain::side::Side;
+
+    fn event() -> EncodedEvent {
+        let order = Order::from(OrderEntry::new(100, 10, Side::Buy).with_account(7));
+        EncodedEvent::new(MarketEvent::OrderCreated(order))
+    }
+
+    #[test]
+    fn every_encoding_round_trips() {
+        let event = event();
+
+        let Frame::Text(json) = event.frame(Encoding::Json) else {
+            panic!("JSON is sent as text");
+        };
+        let Frame::Binary(msgpack) = event.frame(Encoding::Msgpack) else {
+            panic!("MessagePack is sent as binary");
+        };
+        let Frame::Binary(binary) = event.frame(Encoding::Binary) else {
+            panic!("Binary is sent as binary");
+        };
+
+        let decoded = [
+            serde_json::from_str::<MarketEvent>(&json).unwrap(),
+            rmp_serde::from_slice::<MarketEvent>(&msgpack).unwrap(),
+            bincode::deserialize::<MarketEvent>(&binary).unwrap(),
+        ];
+        for decoded in decoded {
+            let MarketEvent::OrderCreated(order) = decoded else {
+                panic!("Unexpected event {decoded:?}");
+            };
+            assert_eq!(order.quantity.0, 10);
+            assert_eq!(order.account.0, 0, "Accounts stay off the public feed");
+        }
+        assert!(!json.contains("account"));
+        assert!(binary.len() < msgpack.len() && msgpack.len() < json.len());
+    }
+
+    #[test]
+    fn each_encoding_is_serialized_once() {
+        let event = event();
+
+        let (Frame::Binary(first), Frame::Binary(second)) = (
+            event.frame(Encoding::Msgpack),
+            event.frame(Encoding::Msgpack),
+        ) else {
+            panic!("MessagePack is sent as binary");
+        };
+
+        assert_eq!(first.as_ptr(), second.as_ptr());
+        assert!(event.binary.get().is_none(), "Unused encodings are skipped");
+    }
+}
@@ -1,4 +1,11 @@
+pub mod admin;
+pub mod auth;
+pub mod book;
+pub mod candles;
+pub mod encoding;
 pub mod health_check;
 pub mod models;
 pub mod orders;
+pub mod ticker;
+pub mod trades;
 pub mod ws;
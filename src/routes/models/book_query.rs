@@ -0,0 +1,23 @@
+use crate::domain::symbol::Symbol;
+use crate::routes::encoding::Encoding;
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+pub struct BookQuery {
+    #[serde(default)]
+    pub symbol: Symbol,
+}
+
+#[derive(Deserialize)]
+pub struct BookStreamParams {
+    pub symbol: Option<Symbol>,
+    pub encoding: Option<Encoding>,
+}
+
+#[derive(Deserialize)]
+pub struct DepthStreamParams {
+    pub symbol: Option<Symbol>,
+    /// Time between two updates, at least `depth.interval_ms`.
+    pub interval_ms: Option<u64>,
+    pub encoding: Option<Encoding>,
+}
@@ -0,0 +1,22 @@
+use crate::domain::symbol::Symbol;
+use crate::market_data::candles::Interval;
+use crate::routes::encoding::Encoding;
+use serde::Deserialize;
+
+/// Bars opened within `from..to`, in unix nanoseconds.
+#[derive(Deserialize)]
+pub struct CandleQuery {
+    #[serde(default)]
+    pub symbol: Symbol,
+    pub interval: Interval,
+    pub from: Option<i64>,
+    pub to: Option<i64>,
+}
+
+/// Bars in progress of the given instrument and interval, or of all of them.
+#[derive(Deserialize)]
+pub struct CandleStreamParams {
+    pub symbol: Option<Symbol>,
+    pub interval: Option<Interval>,
+    pub encoding: Option<Encoding>,
+}
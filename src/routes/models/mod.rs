@@ -1 +1,10 @@
+pub mod book_query;
+pub mod candle_query;
+pub mod order_entry_params;
 pub mod order_modification;
+pub mod order_result;
+pub mod ticker_query;
+pub mod trade_query;
+pub mod trading_state_change;
+pub mod ws_request;
+pub mod ws_session;
@@ -0,0 +1,8 @@
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+pub struct OrderEntryParams {
+    /// Place either all orders of the request or none of them.
+    #[serde(default)]
+    pub atomic: bool,
+}
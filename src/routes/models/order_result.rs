@@ -0,0 +1,21 @@
+use crate::domain::order::Order;
+use crate::matching::command::CommandResult;
+use crate::matching::reject::Reject;
+use serde::Serialize;
+
+/// Outcome of one order of a request, in the order they were submitted.
+#[derive(Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum OrderResult {
+    Accepted { order: Order },
+    Rejected(Reject),
+}
+
+impl From<CommandResult> for OrderResult {
+    fn from(value: CommandResult) -> Self {
+        match value {
+            Ok(order) => OrderResult::Accepted { order },
+            Err(reject) => OrderResult::Rejected(reject),
+        }
+    }
+}
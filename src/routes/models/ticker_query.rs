@@ -0,0 +1,15 @@
+use crate::domain::symbol::Symbol;
+use crate::routes::encoding::Encoding;
+use serde::Deserialize;
+
+/// The ticker of one instrument, or of every known one.
+#[derive(Deserialize)]
+pub struct TickerQuery {
+    pub symbol: Option<Symbol>,
+}
+
+#[derive(Deserialize)]
+pub struct TickerStreamParams {
+    pub symbol: Option<Symbol>,
+    pub encoding: Option<Encoding>,
+}
@@ -0,0 +1,27 @@
+use crate::domain::symbol::Symbol;
+use crate::domain::trade::TradeId;
+use crate::market_data::trades::TradeQuery;
+use serde::Deserialize;
+use smart_default::SmartDefault;
+
+#[derive(Deserialize, SmartDefault)]
+#[serde(default)]
+pub struct TradeQueryParams {
+    pub symbol: Option<Symbol>,
+    pub since_id: Option<TradeId>,
+    pub from: Option<i64>,
+    pub to: Option<i64>,
+    #[default = 100]
+    pub limit: usize,
+}
+
+impl From<TradeQueryParams> for TradeQuery {
+    fn from(params: TradeQueryParams) -> Self {
+        TradeQuery {
+            symbol: params.symbol,
+            since_id: params.since_id,
+            from: params.from,
+            to: params.to,
+        }
+    }
+}
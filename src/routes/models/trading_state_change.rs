@@ -0,0 +1,9 @@
+use crate::domain::symbol::Symbol;
+use crate::domain::trading_state::TradingState;
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+pub struct TradingStateChange {
+    pub symbol: Option<Symbol>,
+    pub state: TradingState,
+}
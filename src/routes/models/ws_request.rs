@@ -0,0 +1,58 @@
+use crate::domain::order::Order;
+use crate::domain::order_entry::OrderEntry;
+use crate::matching::command::CommandResult;
+use crate::matching::reject::{Reject, RejectCode};
+use crate::routes::models::order_modification::{OrderDeletion, OrderModification};
+use serde::{Deserialize, Serialize};
+
+/// Order entry request sent over the WebSocket, e.g.
+/// `{"request_id": "c1", "type": "Create", "price": 100, "quantity": 10, "side": "Buy"}`.
+#[derive(Deserialize)]
+pub struct WsRequest {
+    /// Client chosen correlation id, echoed in the response frame.
+    pub request_id: String,
+    #[serde(flatten)]
+    pub action: WsAction,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type")]
+pub enum WsAction {
+    Create(OrderEntry),
+    Modify(OrderModification),
+    Cancel(OrderDeletion),
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type")]
+pub enum WsResponse {
+    Ack {
+        request_id: String,
+        order: Order,
+    },
+    /// Fields of the `Reject`, spelled out as the compact encoding has no
+    /// flattened fields.
+    Reject {
+        /// `None` when the request could not be parsed.
+        request_id: Option<String>,
+        code: RejectCode,
+        message: String,
+    },
+}
+
+impl WsResponse {
+    pub fn new(request_id: String, result: CommandResult) -> Self {
+        match result {
+            Ok(order) => WsResponse::Ack { request_id, order },
+            Err(reject) => WsResponse::reject(Some(request_id), reject),
+        }
+    }
+
+    pub fn reject(request_id: Option<String>, reject: Reject) -> Self {
+        WsResponse::Reject {
+            request_id,
+            code: reject.code,
+            message: reject.message,
+        }
+    }
+}
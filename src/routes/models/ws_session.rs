@@ -0,0 +1,13 @@
+use crate::routes::encoding::Encoding;
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+pub struct WsSessionParams {
+    /// Only for sessions authenticated with an API key.
+    #[serde(default)]
+    pub cancel_on_disconnect: bool,
+    #[serde(default)]
+    pub grace_ms: u64,
+    /// Encoding of market events, overrides the subprotocol.
+    pub encoding: Option<Encoding>,
+}
@@ -1,67 +1,227 @@
 use crate::domain::order_entry::OrderEntry;
-use crate::matching::command::MatchingEngineCommand;
+use crate::domain::order_filter::OrderFilter;
+use crate::matching::command::{CommandResult, MatchingEngineCommand};
+use crate::matching::reject::{Reject, RejectCode};
 use crate::matching::state::AppState;
+use crate::rate_limit::Throttled;
+use crate::routes::auth::{authenticate, is_admin};
+use crate::routes::models::order_entry_params::OrderEntryParams;
 use crate::routes::models::order_modification::{OrderDeletion, OrderModification};
-use actix_web::{HttpResponse, delete, patch, post, web};
-use log::debug;
+use crate::routes::models::order_result::OrderResult;
+use actix_web::http::{StatusCode, header};
+use actix_web::{HttpRequest, HttpResponse, delete, patch, post, web};
+use log::{debug, error, warn};
+use serde_json::json;
+use tokio::sync::oneshot;
 
 #[post("/orders")]
 async fn add_orders(
+    req: HttpRequest,
     state: web::Data<AppState>,
+    params: web::Query<OrderEntryParams>,
     entries: web::Json<Vec<OrderEntry>>,
 ) -> HttpResponse {
-    let order_entries: Vec<OrderEntry> = entries.into_inner();
-
-    for o in order_entries {
-        state
-            .tx
-            .send(MatchingEngineCommand::Create(o))
-            .await
-            .expect("Matching engine is not matching!");
+    let Some(account) = authenticate(&req, &state) else {
+        return unauthorized("Orders need an API key");
+    };
+    let order_entries: Vec<OrderEntry> = entries
+        .into_inner()
+        .into_iter()
+        .map(|entry| entry.with_account(account))
+        .collect();
+    if let Err(throttled) = rate_limit(&req, &state, order_entries.len()) {
+        return throttled;
     }
 
-    HttpResponse::Ok().finish()
+    if params.atomic {
+        return respond(submit_batch(&state, order_entries).await);
+    }
+
+    let commands = order_entries.into_iter().map(MatchingEngineCommand::Create);
+    respond(submit_all(&state, commands).await)
 }
 
 #[delete("/orders")]
 async fn remove_orders(
+    req: HttpRequest,
     state: web::Data<AppState>,
     orders: web::Json<Vec<OrderDeletion>>,
 ) -> HttpResponse {
-    for o in orders.0 {
-        if let Err(e) = state
-            .tx
-            .send(MatchingEngineCommand::Delete(o.id, o.revision))
-            .await
-        {
-            debug!("Failed to send delete for order {:?}: {:?}", o.id, e);
-            continue;
-        }
+    let Some(account) = authenticate(&req, &state) else {
+        return unauthorized("Cancels need an API key");
+    };
+    if let Err(throttled) = rate_limit(&req, &state, orders.len()) {
+        return throttled;
     }
 
-    HttpResponse::Ok().finish()
+    let commands = orders
+        .0
+        .into_iter()
+        .map(|o| MatchingEngineCommand::Delete(o.id, o.revision, Some(account)));
+    respond(submit_all(&state, commands).await)
 }
 
 #[patch("/orders")]
 async fn update_orders(
+    req: HttpRequest,
     state: web::Data<AppState>,
     orders: web::Json<Vec<OrderModification>>,
 ) -> HttpResponse {
-    for o in orders.0 {
-        if let Err(e) = state
-            .tx
-            .send(MatchingEngineCommand::Modify(
-                o.id,
-                o.revision,
-                o.new_price,
-                o.new_quantity,
-            ))
-            .await
-        {
-            debug!("Failed to modify order {:?}: {:?}", o.id, e);
-            continue;
+    let Some(account) = authenticate(&req, &state) else {
+        return unauthorized("Modifications need an API key");
+    };
+    if let Err(throttled) = rate_limit(&req, &state, orders.len()) {
+        return throttled;
+    }
+
+    let commands = orders.0.into_iter().map(|o| {
+        let (price, quantity) = (o.new_price, o.new_quantity);
+        MatchingEngineCommand::Modify(o.id, o.revision, price, quantity, Some(account))
+    });
+    respond(submit_all(&state, commands).await)
+}
+
+#[delete("/orders/mass")]
+async fn mass_cancel_orders(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    filter: web::Json<OrderFilter>,
+) -> HttpResponse {
+    // without an account the filter reaches the orders of every account
+    let owner = filter.account.is_some() && authenticate(&req, &state) == filter.account;
+    if !owner && !is_admin(&req, &state) {
+        warn!(
+            "Rejected mass cancel of {:?} from {:?}",
+            filter.account,
+            req.peer_addr()
+        );
+        return unauthorized(
+            "Mass cancels need the API key of the named account, or the admin token",
+        );
+    }
+    if let Err(throttled) = rate_limit(&req, &state, 1) {
+        return throttled;
+    }
+
+    let (respond_to, response) = oneshot::channel();
+
+    if let Err(e) = state
+        .tx
+        .send(MatchingEngineCommand::MassCancel(
+            filter.into_inner(),
+            respond_to,
+        ))
+        .await
+    {
+        error!("Failed to send mass cancel: {e}");
+        return HttpResponse::ServiceUnavailable().json(Reject::engine_unavailable());
+    }
+
+    match response.await {
+        Ok(cancelled) => HttpResponse::Ok().json(cancelled),
+        Err(e) => {
+            error!("Matching engine dropped mass cancel: {e}");
+            HttpResponse::ServiceUnavailable().json(Reject::engine_unavailable())
+        }
+    }
+}
+
+/// Sends the commands one by one and collects their outcomes. Once the engine
+/// is gone the remaining commands are not sent.
+async fn submit_all<I>(state: &AppState, commands: I) -> Vec<CommandResult>
+where
+    I: IntoIterator<Item = MatchingEngineCommand>,
+{
+    let mut results = Vec::new();
+    for cmd in commands {
+        let result = state.request(cmd).await;
+        let unavailable = matches!(&result, Err(r) if r.code == RejectCode::EngineUnavailable);
+        results.push(result);
+        if unavailable {
+            break;
         }
     }
+    results
+}
+
+async fn submit_batch(state: &AppState, entries: Vec<OrderEntry>) -> Vec<CommandResult> {
+    let (respond_to, response) = oneshot::channel();
+    let len = entries.len();
+
+    if let Err(e) = state
+        .tx
+        .send(MatchingEngineCommand::Batch(entries, respond_to))
+        .await
+    {
+        error!("Failed to send batch: {e}");
+        return vec![Err(Reject::engine_unavailable()); len];
+    }
+
+    response.await.unwrap_or_else(|e| {
+        error!("Matching engine dropped the batch: {e}");
+        vec![Err(Reject::engine_unavailable()); len]
+    })
+}
+
+/// Answers with the result of every order as the body, and `200 OK` when
+/// every order was accepted or `207 Multi-Status` when only some were, so
+/// clients do not resend the accepted ones. When nothing was accepted the
+/// status is that of the first rejection; orders aborted with their batch
+/// only decide it if nothing else failed.
+fn respond(results: Vec<CommandResult>) -> HttpResponse {
+    let accepted = results.iter().any(Result::is_ok);
+    let status = match results
+        .iter()
+        .filter_map(|r| r.as_ref().err())
+        .min_by_key(|reject| reject.code == RejectCode::BatchAborted)
+    {
+        None => StatusCode::OK,
+        Some(_) if accepted => StatusCode::MULTI_STATUS,
+        Some(reject) => status(reject.code),
+    };
+    let body: Vec<OrderResult> = results.into_iter().map(OrderResult::from).collect();
 
-    HttpResponse::Ok().finish()
+    HttpResponse::build(status).json(body)
+}
+
+fn status(code: RejectCode) -> StatusCode {
+    match code {
+        RejectCode::NotFound => StatusCode::NOT_FOUND,
+        RejectCode::InvalidPrice | RejectCode::InvalidQuantity | RejectCode::InvalidRequest => {
+            StatusCode::BAD_REQUEST
+        }
+        RejectCode::RateLimited => StatusCode::TOO_MANY_REQUESTS,
+        RejectCode::RiskReject => StatusCode::UNPROCESSABLE_ENTITY,
+        RejectCode::Halted | RejectCode::BatchAborted | RejectCode::PhaseRestricted => {
+            StatusCode::CONFLICT
+        }
+        RejectCode::EngineUnavailable => StatusCode::SERVICE_UNAVAILABLE,
+    }
+}
+
+fn unauthorized(message: &str) -> HttpResponse {
+    HttpResponse::Unauthorized().json(Reject::new(RejectCode::InvalidRequest, message))
+}
+
+fn rate_limit(req: &HttpRequest, state: &AppState, orders: usize) -> Result<(), HttpResponse> {
+    let ip = req.peer_addr().map(|addr| addr.ip());
+    let account = authenticate(req, state);
+
+    match state.rate_limits.check(ip, account, orders as u32) {
+        Ok(()) => Ok(()),
+        Err(Throttled::RetryAfter(retry_after)) => {
+            debug!("Throttled request from {ip:?}, retry after {retry_after:?}");
+            let retry_after_ms = retry_after.as_millis() as u64 + 1;
+            Err(HttpResponse::TooManyRequests()
+                .insert_header((header::RETRY_AFTER, retry_after_ms.div_ceil(1000)))
+                .json(json!({
+                    "error": "rate_limited",
+                    "retry_after_ms": retry_after_ms,
+                })))
+        }
+        Err(Throttled::ExceedsBurst) => Err(HttpResponse::TooManyRequests().json(json!({
+            "error": "rate_limited",
+            "message": "Request exceeds the burst limit, split it into smaller batches",
+        }))),
+    }
 }
@@ -0,0 +1,47 @@
+use crate::market_data::ticker::ticker;
+use crate::matching::state::AppState;
+use crate::routes::models::ticker_query::{TickerQuery, TickerStreamParams};
+use crate::routes::ws::{forward, negotiate};
+use actix_web::{Error, HttpRequest, HttpResponse, get, web};
+
+#[get("/ticker")]
+async fn get_ticker(state: web::Data<AppState>, query: web::Query<TickerQuery>) -> HttpResponse {
+    if let Some(symbol) = query.symbol {
+        return match ticker(&state, symbol).await {
+            Ok(ticker) => HttpResponse::Ok().json(ticker),
+            Err(reject) => HttpResponse::ServiceUnavailable().json(reject),
+        };
+    }
+
+    let mut tickers = Vec::new();
+    for symbol in state.tickers.symbols() {
+        match ticker(&state, symbol).await {
+            Ok(ticker) => tickers.push(ticker),
+            Err(reject) => return HttpResponse::ServiceUnavailable().json(reject),
+        }
+    }
+    tickers.sort_by_key(|ticker| ticker.symbol);
+    HttpResponse::Ok().json(tickers)
+}
+
+pub async fn ticker_stream(
+    req: HttpRequest,
+    stream: web::Payload,
+    data: web::Data<AppState>,
+    params: web::Query<TickerStreamParams>,
+) -> Result<HttpResponse, Error> {
+    let (mut res, session, msg_stream) = actix_ws::handle(&req, stream)?;
+    let params = params.into_inner();
+    let encoding = negotiate(&req, &mut res, params.encoding);
+
+    forward(
+        session,
+        msg_stream,
+        data.tickers.subscribe(),
+        encoding,
+        false,
+        move |ticker| params.symbol.is_none_or(|symbol| symbol == ticker.symbol),
+    );
+
+    Ok(res)
+}
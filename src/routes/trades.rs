@@ -0,0 +1,14 @@
+use crate::market_data::trades::TradeQuery;
+use crate::matching::state::AppState;
+use crate::routes::models::trade_query::TradeQueryParams;
+use actix_web::{HttpResponse, get, web};
+
+#[get("/trades")]
+async fn get_trades(
+    state: web::Data<AppState>,
+    params: web::Query<TradeQueryParams>,
+) -> HttpResponse {
+    let params = params.into_inner();
+    let limit = params.limit;
+    HttpResponse::Ok().json(state.trades.query(&TradeQuery::from(params), limit))
+}
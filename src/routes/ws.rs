@@ -1,39 +1,326 @@
+use crate::domain::account::AccountId;
+use crate::market_data::depth::DepthSubscription;
+use crate::matching::command::{CommandResult, MatchingEngineCommand};
+use crate::matching::reject::{Reject, RejectCode};
 use crate::matching::state::AppState;
+use crate::routes::auth::authenticate;
+use crate::routes::encoding::{Encoded, Encoding, Frame};
+use crate::routes::models::ws_request::{WsAction, WsRequest, WsResponse};
+use crate::routes::models::ws_session::WsSessionParams;
+use actix_web::http::header::{HeaderValue, SEC_WEBSOCKET_PROTOCOL};
 use actix_web::{Error, HttpRequest, HttpResponse, web};
-use actix_ws::Message;
-use log::info;
+use actix_ws::{Closed, Message, MessageStream, ProtocolError, Session};
+use log::{error, info, warn};
+use serde::Serialize;
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{broadcast, oneshot};
+use tokio::time::MissedTickBehavior;
 use tokio_stream::StreamExt;
 
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(15);
+const MAX_CANCEL_GRACE: Duration = Duration::from_secs(30);
+
 pub async fn ws_handler(
     req: HttpRequest,
     stream: web::Payload,
     data: web::Data<AppState>,
+    params: web::Query<WsSessionParams>,
 ) -> Result<HttpResponse, Error> {
-    let (res, mut session, mut msg_stream) = actix_ws::handle(&req, stream)?;
-    let mut ws_rx = data.ws_tx.subscribe();
+    let params = params.into_inner();
+    let account = authenticate(&req, &data);
+    if params.cancel_on_disconnect && account.is_none() {
+        warn!(
+            "Cancel-on-disconnect requested without an API key from {:?}",
+            req.peer_addr()
+        );
+        return Ok(HttpResponse::Unauthorized().finish());
+    }
+
+    let (mut res, mut session, mut msg_stream) = actix_ws::handle(&req, stream)?;
+    let ip = req.peer_addr().map(|addr| addr.ip());
+    let mut ws_rx = data.ws_frames.subscribe();
+
+    let encoding = negotiate(&req, &mut res, params.encoding);
+    // every session of the account counts, so any reconnect keeps its orders
+    let cancel_on_disconnect = account
+        .map(|account| (account, data.sessions.connect(account)))
+        .filter(|_| params.cancel_on_disconnect);
+    let grace = Duration::from_millis(params.grace_ms).min(MAX_CANCEL_GRACE);
 
     info!("WebSocket connection established");
 
+    let state = data.into_inner();
     actix_web::rt::spawn(async move {
+        let mut heartbeat = tokio::time::interval(HEARTBEAT_INTERVAL);
+        let mut last_heartbeat = Instant::now();
+
         loop {
             tokio::select! {
                 Ok(update) = ws_rx.recv() => {
-                    if let Ok(json) = serde_json::to_string(&update) {
-                        if session.text(json).await.is_err() {
-                            break;
+                    if update.event.recipient().is_some_and(|a| Some(a) != account) {
+                        continue;
+                    }
+                    if send(&mut session, update.frame(encoding)).await.is_err() {
+                        break;
+                    }
+                }
+                msg = msg_stream.next() => {
+                    last_heartbeat = Instant::now();
+                    match msg {
+                        Some(Ok(Message::Ping(bytes))) => {
+                            if session.pong(&bytes).await.is_err() {
+                                break;
+                            }
                         }
+                        Some(Ok(Message::Text(text))) => {
+                            match submit(&state, account, ip, &text).await {
+                                Ok((request_id, response)) => {
+                                    let mut session = session.clone();
+                                    actix_web::rt::spawn(async move {
+                                        let result = response
+                                            .await
+                                            .unwrap_or_else(|_| Err(Reject::engine_unavailable()));
+                                        let response = WsResponse::new(request_id, result);
+                                        let frame = Encoded::new(response).frame(encoding);
+                                        let _ = send(&mut session, frame).await;
+                                    });
+                                }
+                                Err(reject) => {
+                                    let frame = Encoded::new(reject).frame(encoding);
+                                    if send(&mut session, frame).await.is_err() {
+                                        break;
+                                    }
+                                }
+                            }
+                        }
+                        Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                        Some(Ok(_)) => {}
                     }
                 }
-                Some(Ok(msg)) = msg_stream.next() => {
-                    if matches!(msg, Message::Close(_)) {
+                _ = heartbeat.tick() => {
+                    if last_heartbeat.elapsed() > CLIENT_TIMEOUT {
+                        info!("WebSocket client heartbeat timed out");
+                        break;
+                    }
+                    if session.ping(b"").await.is_err() {
                         break;
                     }
                 }
-                else => break,
             }
         }
+        let _ = session.close(None).await;
         info!("WebSocket connection closed");
+
+        if let Some((account, generation)) = cancel_on_disconnect {
+            tokio::time::sleep(grace).await;
+
+            if !state.sessions.is_latest(account, generation) {
+                info!("Account {account:?} reconnected, keeping its orders");
+                return;
+            }
+
+            if let Err(e) = state
+                .tx
+                .send(MatchingEngineCommand::CancelAll(account))
+                .await
+            {
+                error!("Failed to cancel orders of {account:?} on disconnect: {e}");
+            }
+        }
     });
 
     Ok(res)
 }
+
+/// Parses an order entry request and hands it to the engine without waiting
+/// for the result, so requests reach the engine in the order they were sent.
+/// Orders of a session always belong to the account of its API key, and it
+/// can only change orders of that account. Anonymous sessions only listen.
+async fn submit(
+    state: &AppState,
+    account: Option<AccountId>,
+    ip: Option<IpAddr>,
+    text: &str,
+) -> Result<(String, oneshot::Receiver<CommandResult>), WsResponse> {
+    let request: WsRequest = serde_json::from_str(text).map_err(|e| {
+        WsResponse::reject(None, Reject::new(RejectCode::InvalidRequest, e.to_string()))
+    })?;
+    let reject = |reject| WsResponse::reject(Some(request.request_id.clone()), reject);
+
+    let Some(account) = account else {
+        return Err(reject(Reject::new(
+            RejectCode::InvalidRequest,
+            "Orders need an API key",
+        )));
+    };
+
+    let cmd = match request.action {
+        WsAction::Create(entry) => MatchingEngineCommand::Create(entry.with_account(account)),
+        WsAction::Modify(o) => MatchingEngineCommand::Modify(
+            o.id,
+            o.revision,
+            o.new_price,
+            o.new_quantity,
+            Some(account),
+        ),
+        WsAction::Cancel(o) => MatchingEngineCommand::Delete(o.id, o.revision, Some(account)),
+    };
+
+    if let Err(throttled) = state.rate_limits.check(ip, Some(account), 1) {
+        return Err(reject(Reject::new(
+            RejectCode::RateLimited,
+            format!("{throttled:?}"),
+        )));
+    }
+
+    let (respond_to, response) = oneshot::channel();
+    if let Err(e) = state
+        .tx
+        .send(MatchingEngineCommand::Request(Box::new(cmd), respond_to))
+        .await
+    {
+        error!("Failed to send command: {e}");
+        return Err(reject(Reject::engine_unavailable()));
+    }
+
+    Ok((request.request_id, response))
+}
+
+/// Streams the updates accepted by `filter` to a session that only listens,
+/// until the client leaves or stops answering pings. A `gapless` stream is
+/// closed once the client fell behind, instead of skipping updates.
+pub(crate) fn forward<T, F>(
+    mut session: Session,
+    mut msg_stream: MessageStream,
+    mut updates: broadcast::Receiver<Arc<Encoded<T>>>,
+    encoding: Encoding,
+    gapless: bool,
+    filter: F,
+) where
+    T: Serialize + Send + Sync + 'static,
+    F: Fn(&T) -> bool + 'static,
+{
+    actix_web::rt::spawn(async move {
+        let mut heartbeat = tokio::time::interval(HEARTBEAT_INTERVAL);
+        let mut last_heartbeat = Instant::now();
+
+        loop {
+            tokio::select! {
+                update = updates.recv() => match update {
+                    Ok(update) if filter(&update.event) => {
+                        if send(&mut session, update.frame(encoding)).await.is_err() {
+                            break;
+                        }
+                    }
+                    Err(RecvError::Lagged(missed)) if gapless => {
+                        warn!("Closing a stream that missed {missed} updates");
+                        break;
+                    }
+                    Ok(_) | Err(RecvError::Lagged(_)) => {}
+                    Err(RecvError::Closed) => break,
+                },
+                msg = msg_stream.next() => {
+                    last_heartbeat = Instant::now();
+                    if !answer(&mut session, msg).await {
+                        break;
+                    }
+                }
+                _ = heartbeat.tick() => {
+                    if last_heartbeat.elapsed() > CLIENT_TIMEOUT || session.ping(b"").await.is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+        let _ = session.close(None).await;
+    });
+}
+
+/// Sends the levels of the book that changed to a session that only
+/// listens, at most once per `interval`. Changes pile up in the subscription
+/// while the client is slow, so it never misses the latest state.
+pub(crate) fn conflate(
+    mut session: Session,
+    mut msg_stream: MessageStream,
+    subscription: Arc<DepthSubscription>,
+    encoding: Encoding,
+    interval: Duration,
+) {
+    actix_web::rt::spawn(async move {
+        let mut heartbeat = tokio::time::interval(HEARTBEAT_INTERVAL);
+        let mut last_heartbeat = Instant::now();
+        let mut throttle = tokio::time::interval(interval);
+        throttle.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        'session: loop {
+            tokio::select! {
+                _ = throttle.tick() => {
+                    for update in subscription.take() {
+                        let frame = Encoded::new(update).frame(encoding);
+                        if send(&mut session, frame).await.is_err() {
+                            break 'session;
+                        }
+                    }
+                }
+                msg = msg_stream.next() => {
+                    last_heartbeat = Instant::now();
+                    if !answer(&mut session, msg).await {
+                        break;
+                    }
+                }
+                _ = heartbeat.tick() => {
+                    if last_heartbeat.elapsed() > CLIENT_TIMEOUT || session.ping(b"").await.is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+        let _ = session.close(None).await;
+    });
+}
+
+/// Answers a frame of a client that only listens, false once it left.
+async fn answer(session: &mut Session, msg: Option<Result<Message, ProtocolError>>) -> bool {
+    match msg {
+        Some(Ok(Message::Ping(bytes))) => session.pong(&bytes).await.is_ok(),
+        Some(Ok(Message::Close(_))) | Some(Err(_)) | None => false,
+        Some(Ok(_)) => true,
+    }
+}
+
+/// Encoding asked for by the client. The query parameter wins over the
+/// subprotocol, which is confirmed in the handshake response when offered.
+pub(crate) fn negotiate(
+    req: &HttpRequest,
+    res: &mut HttpResponse,
+    requested: Option<Encoding>,
+) -> Encoding {
+    let protocol = subprotocol(req);
+    if let Some(protocol) = protocol {
+        res.headers_mut().insert(
+            SEC_WEBSOCKET_PROTOCOL,
+            HeaderValue::from_static(protocol.subprotocol()),
+        );
+    }
+    requested.or(protocol).unwrap_or_default()
+}
+
+async fn send(session: &mut Session, frame: Frame) -> Result<(), Closed> {
+    match frame {
+        Frame::Text(text) => session.text(text).await,
+        Frame::Binary(bytes) => session.binary(bytes).await,
+    }
+}
+
+/// First offered subprotocol naming an encoding.
+fn subprotocol(req: &HttpRequest) -> Option<Encoding> {
+    req.headers()
+        .get_all(SEC_WEBSOCKET_PROTOCOL)
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .find_map(|name| Encoding::from_subprotocol(name.trim()))
+}
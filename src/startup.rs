@@ -1,15 +1,60 @@
+use crate::gateway::{fix, itch, ouch};
+use crate::market_data::{candles, depth, l3, ticker, trades};
 use crate::matching::state::AppState;
+use crate::routes::admin::set_trading_state;
+use crate::routes::book::{depth_stream, get_l3_book, l3_stream};
+use crate::routes::candles::{candle_stream, get_candles};
+use crate::routes::encoding::encode_events;
 use crate::routes::health_check::health_check;
-use crate::routes::orders::{add_orders, remove_orders, update_orders};
+use crate::routes::orders::{add_orders, mass_cancel_orders, remove_orders, update_orders};
+use crate::routes::ticker::{get_ticker, ticker_stream};
+use crate::routes::trades::get_trades;
 use crate::routes::ws::ws_handler;
 use actix_web::dev::Server;
 use actix_web::middleware::Logger;
 use actix_web::web::Data;
 use actix_web::{App, HttpServer, web};
+use log::{error, info};
 use std::net::TcpListener;
 
 pub fn run(listener: TcpListener, state: AppState) -> Result<Server, std::io::Error> {
-    env_logger::init_from_env(env_logger::Env::new().default_filter_or("debug"));
+    let _ = env_logger::try_init_from_env(env_logger::Env::new().default_filter_or("debug"));
+
+    if let Some(settings) = state.ouch.clone() {
+        let listener = bind(&settings.host, settings.port)?;
+        info!("Binary gateway listening on {}", listener.local_addr()?);
+        tokio::spawn(ouch::serve(listener, state.clone(), settings));
+    }
+
+    if let Some(settings) = state.fix.clone() {
+        let listener = bind(&settings.host, settings.port)?;
+        info!("FIX gateway listening on {}", listener.local_addr()?);
+        tokio::spawn(fix::serve(listener, state.clone(), settings));
+    }
+
+    if let Some(settings) = state.itch.clone() {
+        let listener = bind(&settings.retransmit_host, settings.retransmit_port)?;
+        info!("Market data feed sending to {}", settings.target);
+        let events = state.subscribe_all();
+        tokio::spawn(async move {
+            if let Err(e) = itch::serve(listener, events, settings).await {
+                error!("Market data feed stopped: {e}");
+            }
+        });
+    }
+
+    tokio::spawn(encode_events(
+        state.ws_tx.subscribe(),
+        state.ws_frames.clone(),
+    ));
+    tokio::spawn(candles::aggregate(
+        state.subscribe_all(),
+        state.candles.clone(),
+    ));
+    tokio::spawn(trades::record(state.subscribe_all(), state.trades.clone()));
+    tokio::spawn(ticker::publish(state.subscribe_all(), state.clone()));
+    tokio::spawn(l3::publish(state.subscribe_all(), state.l3.clone()));
+    tokio::spawn(depth::track(state.subscribe_all(), state.depth.clone()));
 
     let matching_ch = Data::new(state);
     let server = HttpServer::new(move || {
@@ -17,9 +62,19 @@ pub fn run(listener: TcpListener, state: AppState) -> Result<Server, std::io::Er
             .service(add_orders)
             .service(remove_orders)
             .service(update_orders)
+            .service(mass_cancel_orders)
+            .service(set_trading_state)
+            .service(get_candles)
+            .service(get_trades)
+            .service(get_ticker)
+            .service(get_l3_book)
             .app_data(matching_ch.clone())
             .route("/health_check", web::get().to(health_check))
             .route("/ws", web::get().to(ws_handler))
+            .route("/ws/candles", web::get().to(candle_stream))
+            .route("/ws/ticker", web::get().to(ticker_stream))
+            .route("/ws/l3", web::get().to(l3_stream))
+            .route("/ws/depth", web::get().to(depth_stream))
             .wrap(Logger::new("%a %{User-Agent}i"))
     })
     .listen(listener)?
@@ -27,3 +82,9 @@ pub fn run(listener: TcpListener, state: AppState) -> Result<Server, std::io::Er
 
     Ok(server)
 }
+
+fn bind(host: &str, port: u16) -> Result<tokio::net::TcpListener, std::io::Error> {
+    let listener = TcpListener::bind((host, port))?;
+    listener.set_nonblocking(true)?;
+    tokio::net::TcpListener::from_std(listener)
+}
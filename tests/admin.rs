@@ -0,0 +1,40 @@
+use crate::utils::test_app::{ADMIN_TOKEN, spawn_app};
+
+mod utils;
+
+#[tokio::test]
+async fn trading_state_change_requires_admin_token() {
+    let app = spawn_app();
+    let client = reqwest::Client::new();
+
+    for token in [None, Some("wrong-token")] {
+        let mut request = client
+            .post(format!("{}/admin/trading_state", &app.address))
+            .header("Content-Type", "application/json")
+            .body(r#"{"state": "Halted"}"#);
+        if let Some(token) = token {
+            request = request.bearer_auth(token);
+        }
+
+        let response = request.send().await.expect("Failed to get the response!");
+
+        assert_eq!(response.status().as_u16(), 401);
+    }
+}
+
+#[tokio::test]
+async fn trading_state_change_accepts_admin_token() {
+    let app = spawn_app();
+    let client = reqwest::Client::new();
+
+    let response = client
+        .post(format!("{}/admin/trading_state", &app.address))
+        .header("Content-Type", "application/json")
+        .bearer_auth(ADMIN_TOKEN)
+        .body(r#"{"symbol": "ABC", "state": "Halted"}"#)
+        .send()
+        .await
+        .expect("Failed to get the response!");
+
+    assert!(response.status().is_success());
+}
@@ -0,0 +1,144 @@
+use crate::utils::test_app::{api_key, spawn_app};
+use exchange::domain::order_book_level::OrderBookSnapshot;
+use exchange::market_data::l3::L3Message;
+use futures_util::StreamExt;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use tokio_tungstenite::connect_async;
+use tokio_tungstenite::tungstenite::Message;
+
+mod utils;
+
+#[tokio::test]
+async fn l3_snapshot_and_feed_show_every_order() {
+    let app = spawn_app();
+    let client = reqwest::Client::new();
+    let url = format!("{}/ws/l3?symbol=ABC", app.address.replace("http", "ws"));
+    let (mut ws, _) = connect_async(url).await.expect("Failed to connect");
+
+    let response = client
+        .post(format!("{}/orders", &app.address))
+        .bearer_auth(api_key(1))
+        .header("Content-Type", "application/json")
+        .body(
+            r#"[
+                {"price": 100, "quantity": 10, "side": "Sell", "symbol": "ABC"},
+                {"price": 100, "quantity": 5, "side": "Sell", "symbol": "ABC"},
+                {"price": 98, "quantity": 1, "side": "Buy", "symbol": "XYZ"},
+                {"price": 100, "quantity": 4, "side": "Buy", "symbol": "ABC"}
+            ]"#,
+        )
+        .send()
+        .await
+        .expect("Failed to create orders!");
+    assert!(response.status().is_success());
+    let results: Vec<Value> = serde_json::from_str(&response.text().await.unwrap()).unwrap();
+    let id = |i: usize| results[i]["order"]["id"].as_str().unwrap().to_string();
+
+    let mut messages = Vec::new();
+    while messages.len() < 5 {
+        let Message::Text(text) = ws.next().await.unwrap().unwrap() else {
+            continue;
+        };
+        messages.push(serde_json::from_str::<L3Message>(&text).expect("Invalid message"));
+    }
+    match &messages[..] {
+        [
+            L3Message::Add { .. },
+            L3Message::Add { .. },
+            L3Message::Add { .. },
+            L3Message::Execute {
+                order_id: maker,
+                leaves_quantity: maker_leaves,
+                ..
+            },
+            L3Message::Execute {
+                leaves_quantity: taker_leaves,
+                ..
+            },
+        ] => {
+            assert_eq!(maker.0.to_string(), id(0));
+            assert_eq!((maker_leaves.0, taker_leaves.0), (6, 0));
+        }
+        other => panic!("Unexpected messages: {other:?}"),
+    }
+    let sequences: Vec<_> = messages.iter().map(L3Message::sequence).collect();
+    assert!(sequences.is_sorted());
+    assert_eq!(
+        sequences[3], sequences[4],
+        "Both executions come from one trade"
+    );
+
+    let response = client
+        .get(format!("{}/book/l3?symbol=ABC", &app.address))
+        .send()
+        .await
+        .expect("Failed to query the book!");
+    assert!(response.status().is_success());
+    let snapshot: OrderBookSnapshot =
+        serde_json::from_str(&response.text().await.unwrap()).expect("Invalid snapshot");
+
+    assert!(snapshot.sequence >= sequences[4]);
+    assert!(snapshot.bids.is_empty());
+    let asks: Vec<_> = snapshot
+        .asks
+        .iter()
+        .map(|o| (o.id.0.to_string(), o.revision.0, o.quantity.0, o.position))
+        .collect();
+    assert_eq!(asks, [(id(0), 1, 6, 0), (id(1), 0, 5, 1)]);
+}
+
+#[tokio::test]
+async fn depth_updates_are_conflated() {
+    let app = spawn_app();
+    let client = reqwest::Client::new();
+    let url = format!(
+        "{}/ws/depth?symbol=ABC&interval_ms=50",
+        app.address.replace("http", "ws")
+    );
+    let (mut ws, _) = connect_async(url).await.expect("Failed to connect");
+
+    let orders: Vec<Value> = (0..100)
+        .map(|i| serde_json::json!({"price": 100 + i % 5, "quantity": 1, "side": "Sell", "symbol": "ABC"}))
+        .chain([serde_json::json!({"price": 101, "quantity": 15, "side": "Buy", "symbol": "ABC"})])
+        .collect();
+    let response = client
+        .post(format!("{}/orders", &app.address))
+        .bearer_auth(api_key(1))
+        .header("Content-Type", "application/json")
+        .body(Value::from(orders).to_string())
+        .send()
+        .await
+        .expect("Failed to create orders!");
+    assert!(response.status().is_success());
+
+    let expected = BTreeMap::from([(100, 5), (101, 20), (102, 20), (103, 20), (104, 20)]);
+    let mut asks = BTreeMap::new();
+    let mut frames = 0;
+    while asks != expected {
+        let Message::Text(text) = ws.next().await.unwrap().unwrap() else {
+            continue;
+        };
+        frames += 1;
+        let update: Value = serde_json::from_str(&text).expect("Invalid update");
+        assert_eq!(update["symbol"], "ABC");
+        assert!(
+            update["bids"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .all(|level| level["quantity"] == 0)
+        );
+        for level in update["asks"].as_array().unwrap() {
+            let (price, quantity) = (
+                level["price"].as_i64().unwrap(),
+                level["quantity"].as_i64().unwrap(),
+            );
+            match quantity {
+                0 => asks.remove(&price),
+                quantity => asks.insert(price, quantity),
+            };
+        }
+    }
+    assert!(frames < 10, "{frames} updates were not conflated");
+}
@@ -0,0 +1,75 @@
+use crate::utils::test_app::{api_key, spawn_app};
+use exchange::market_data::candles::{Candle, Interval};
+use futures_util::StreamExt;
+use tokio_tungstenite::connect_async;
+use tokio_tungstenite::tungstenite::Message;
+
+mod utils;
+
+#[tokio::test]
+async fn trades_are_aggregated_into_candles() {
+    let app = spawn_app();
+    let client = reqwest::Client::new();
+    let url = format!(
+        "{}/ws/candles?symbol=ABC&interval=1m",
+        app.address.replace("http", "ws")
+    );
+    let (mut ws, _) = connect_async(url).await.expect("Failed to connect");
+
+    let response = client
+        .post(format!("{}/orders", &app.address))
+        .bearer_auth(api_key(1))
+        .header("Content-Type", "application/json")
+        .body(
+            r#"[
+                {"price": 100, "quantity": 10, "side": "Sell", "symbol": "ABC"},
+                {"price": 102, "quantity": 10, "side": "Sell", "symbol": "ABC"},
+                {"price": 102, "quantity": 15, "side": "Buy", "symbol": "ABC"},
+                {"price": 50, "quantity": 1, "side": "Sell", "symbol": "XYZ"},
+                {"price": 50, "quantity": 1, "side": "Buy", "symbol": "XYZ"}
+            ]"#,
+        )
+        .send()
+        .await
+        .expect("Failed to create orders!");
+    assert!(response.status().is_success());
+
+    let mut updates = Vec::new();
+    while updates.len() < 2 {
+        let Message::Text(text) = ws.next().await.unwrap().unwrap() else {
+            continue;
+        };
+        updates.push(serde_json::from_str::<Candle>(&text).expect("Invalid candle"));
+    }
+    assert_eq!(updates[0].close.0, 100);
+    assert_eq!(updates[1].close.0, 102);
+    assert!(
+        updates
+            .iter()
+            .all(|c| c.symbol.as_str() == "ABC" && c.interval == Interval::OneMinute)
+    );
+
+    let response = client
+        .get(format!(
+            "{}/candles?symbol=ABC&interval=1m&from=0",
+            &app.address
+        ))
+        .send()
+        .await
+        .expect("Failed to query candles!");
+    assert!(response.status().is_success());
+
+    let candles: Vec<Candle> =
+        serde_json::from_str(&response.text().await.unwrap()).expect("Invalid response body");
+    assert_eq!(candles.len(), 1);
+    assert_eq!(candles[0], updates[1]);
+    assert_eq!((candles[0].open.0, candles[0].high.0), (100, 102));
+    assert_eq!((candles[0].volume.0, candles[0].trades), (15, 2));
+
+    let response = client
+        .get(format!("{}/candles?interval=2m", &app.address))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 400);
+}
@@ -0,0 +1,272 @@
+use exchange::configuration::{FixSessionSettings, FixSettings};
+use exchange::domain::account::AccountId;
+use exchange::gateway::fix::message::{FixMessage, msg_type, read_message, tag};
+use exchange::gateway::fix::serve;
+use exchange::matching::engine::matching_engine;
+use exchange::matching::state::AppState;
+use std::sync::Arc;
+use tokio::io::{AsyncWriteExt, BufReader};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{broadcast, mpsc};
+
+async fn spawn_gateway() -> String {
+    let (tx, rx) = mpsc::channel(1_000);
+    let (ws_tx, _) = broadcast::channel(1_000);
+    tokio::spawn(matching_engine(rx, ws_tx.clone()));
+
+    let store_dir = std::env::temp_dir().join(format!("fix-{}", uuid::Uuid::new_v4()));
+    let settings = FixSettings {
+        store_dir: store_dir.to_string_lossy().into_owned(),
+        sessions: vec![FixSessionSettings {
+            sender_comp_id: "CLIENT".to_string(),
+            account: AccountId(1),
+            password: Some("secret".to_string()),
+        }],
+        ..Default::default()
+    };
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    tokio::spawn(serve(
+        listener,
+        AppState::new(tx, ws_tx),
+        Arc::new(settings),
+    ));
+
+    address
+}
+
+struct Client {
+    reader: BufReader<OwnedReadHalf>,
+    writer: OwnedWriteHalf,
+    sequence: u64,
+}
+
+impl Client {
+    async fn logon(address: &str, password: &str, sequence: u64) -> (Client, FixMessage) {
+        let (reader, writer) = TcpStream::connect(address).await.unwrap().into_split();
+        let mut client = Client {
+            reader: BufReader::new(reader),
+            writer,
+            sequence,
+        };
+        let logon = FixMessage::new(msg_type::LOGON)
+            .with(tag::ENCRYPT_METHOD, 0)
+            .with(tag::HEART_BT_INT, 30)
+            .with(tag::PASSWORD, password);
+        client.send(logon).await;
+        let response = client.receive().await;
+        (client, response)
+    }
+
+    async fn send(&mut self, message: FixMessage) {
+        let message = message.with_header(vec![
+            (tag::SENDER_COMP_ID, "CLIENT".to_string()),
+            (tag::TARGET_COMP_ID, "EXCHANGE".to_string()),
+            (tag::MSG_SEQ_NUM, self.sequence.to_string()),
+            (tag::SENDING_TIME, "20250101-00:00:00.000".to_string()),
+        ]);
+        self.writer.write_all(&message.encode()).await.unwrap();
+        self.sequence += 1;
+    }
+
+    async fn receive(&mut self) -> FixMessage {
+        let raw = read_message(&mut self.reader)
+            .await
+            .expect("Connection closed");
+        FixMessage::decode(&raw).expect("Invalid message")
+    }
+
+    async fn closed(mut self) {
+        while read_message(&mut self.reader).await.is_ok() {}
+    }
+}
+
+fn new_order(cl_ord_id: &str, side: &str, quantity: i64, price: i64) -> FixMessage {
+    FixMessage::new(msg_type::NEW_ORDER_SINGLE)
+        .with(tag::CL_ORD_ID, cl_ord_id)
+        .with(tag::SYMBOL, "DEFAULT")
+        .with(tag::SIDE, side)
+        .with(tag::ORDER_QTY, quantity)
+        .with(tag::ORD_TYPE, 2)
+        .with(tag::PRICE, price)
+}
+
+fn assert_report(report: &FixMessage, cl_ord_id: &str, exec_type: &str, status: &str) {
+    assert_eq!(report.msg_type(), msg_type::EXECUTION_REPORT);
+    assert_eq!(report.get(tag::CL_ORD_ID), Some(cl_ord_id));
+    assert_eq!(report.get(tag::EXEC_TYPE), Some(exec_type));
+    assert_eq!(report.get(tag::ORD_STATUS), Some(status));
+}
+
+#[tokio::test]
+async fn logon_with_wrong_password_is_rejected() {
+    let address = spawn_gateway().await;
+
+    let (_, response) = Client::logon(&address, "wrong", 1).await;
+
+    assert_eq!(response.msg_type(), msg_type::LOGOUT);
+}
+
+#[tokio::test]
+async fn orders_are_reported_as_execution_reports() {
+    let address = spawn_gateway().await;
+    let (mut client, logon) = Client::logon(&address, "secret", 1).await;
+    assert_eq!(logon.msg_type(), msg_type::LOGON);
+    assert_eq!(logon.get(tag::MSG_SEQ_NUM), Some("1"));
+
+    client.send(new_order("1", "1", 10, 100)).await;
+    let new = client.receive().await;
+    assert_report(&new, "1", "0", "0");
+    assert_eq!(new.get(tag::LEAVES_QTY), Some("10"));
+
+    client.send(new_order("2", "2", 4, 100)).await;
+    assert_report(&client.receive().await, "2", "0", "0");
+    let maker = client.receive().await;
+    assert_report(&maker, "1", "F", "1");
+    assert_eq!(maker.get(tag::LAST_QTY), Some("4"));
+    assert_eq!(maker.get(tag::LAST_PX), Some("100.00"));
+    assert_eq!(maker.get(tag::AVG_PX), Some("100.00"));
+    assert_eq!(maker.get(tag::LEAVES_QTY), Some("6"));
+    assert_eq!(maker.get(tag::CUM_QTY), Some("4"));
+    assert_report(&client.receive().await, "2", "F", "2");
+
+    let replace = FixMessage::new(msg_type::ORDER_CANCEL_REPLACE_REQUEST)
+        .with(tag::ORIG_CL_ORD_ID, "1")
+        .with(tag::CL_ORD_ID, "3")
+        .with(tag::SYMBOL, "DEFAULT")
+        .with(tag::SIDE, 1)
+        .with(tag::ORDER_QTY, 8)
+        .with(tag::ORD_TYPE, 2)
+        .with(tag::PRICE, "99.5");
+    client.send(replace).await;
+    let replaced = client.receive().await;
+    assert_report(&replaced, "3", "5", "1");
+    assert_eq!(replaced.get(tag::ORIG_CL_ORD_ID), Some("1"));
+    assert_eq!(replaced.get(tag::ORDER_QTY), Some("8"));
+    assert_eq!(replaced.get(tag::LEAVES_QTY), Some("4"));
+    assert_eq!(replaced.get(tag::PRICE), Some("99.50"));
+
+    let cancel = FixMessage::new(msg_type::ORDER_CANCEL_REQUEST)
+        .with(tag::ORIG_CL_ORD_ID, "3")
+        .with(tag::CL_ORD_ID, "4")
+        .with(tag::SYMBOL, "DEFAULT")
+        .with(tag::SIDE, 1);
+    client.send(cancel.clone()).await;
+    let canceled = client.receive().await;
+    assert_report(&canceled, "4", "4", "4");
+    assert_eq!(canceled.get(tag::ORIG_CL_ORD_ID), Some("3"));
+
+    client.send(cancel).await;
+    let rejected = client.receive().await;
+    assert_eq!(rejected.msg_type(), msg_type::ORDER_CANCEL_REJECT);
+    assert_eq!(rejected.get(tag::CXL_REJ_RESPONSE_TO), Some("1"));
+    assert_eq!(rejected.get(tag::CXL_REJ_REASON), Some("1"));
+
+    client.send(new_order("5", "1", 0, 100)).await;
+    let rejected = client.receive().await;
+    assert_report(&rejected, "5", "8", "8");
+    assert_eq!(rejected.get(tag::ORD_REJ_REASON), Some("13"));
+}
+
+#[tokio::test]
+async fn time_in_force_is_taken_from_the_order() {
+    let address = spawn_gateway().await;
+    let (mut client, _) = Client::logon(&address, "secret", 1).await;
+
+    client
+        .send(new_order("1", "2", 3, 100).with(tag::TIME_IN_FORCE, 1))
+        .await;
+    assert_report(&client.receive().await, "1", "0", "0");
+
+    // the rest of an IOC order is canceled right after it traded
+    client
+        .send(new_order("2", "1", 5, 100).with(tag::TIME_IN_FORCE, 3))
+        .await;
+    assert_report(&client.receive().await, "2", "0", "0");
+    assert_report(&client.receive().await, "1", "F", "2");
+    assert_report(&client.receive().await, "2", "F", "1");
+    let canceled = client.receive().await;
+    assert_report(&canceled, "2", "4", "4");
+    assert_eq!(canceled.get(tag::LEAVES_QTY), Some("0"));
+    assert_eq!(canceled.get(tag::CUM_QTY), Some("3"));
+
+    client
+        .send(new_order("3", "1", 5, 100).with(tag::TIME_IN_FORCE, 6))
+        .await;
+    assert_report(&client.receive().await, "3", "8", "8");
+}
+
+#[tokio::test]
+async fn session_messages_and_persisted_sequence_numbers() {
+    let address = spawn_gateway().await;
+    let (mut client, _) = Client::logon(&address, "secret", 1).await;
+
+    client
+        .send(FixMessage::new(msg_type::TEST_REQUEST).with(tag::TEST_REQ_ID, "ping"))
+        .await;
+    let heartbeat = client.receive().await;
+    assert_eq!(heartbeat.msg_type(), msg_type::HEARTBEAT);
+    assert_eq!(heartbeat.get(tag::TEST_REQ_ID), Some("ping"));
+    assert_eq!(heartbeat.get(tag::MSG_SEQ_NUM), Some("2"));
+
+    let resend = FixMessage::new(msg_type::RESEND_REQUEST)
+        .with(tag::BEGIN_SEQ_NO, 1)
+        .with(tag::ECHO_SEQ_NO_END, 0);
+    client.send(resend).await;
+    let gap_fill = client.receive().await;
+    assert_eq!(gap_fill.msg_type(), msg_type::SEQUENCE_RESET);
+    assert_eq!(gap_fill.get(tag::MSG_SEQ_NUM), Some("1"));
+    assert_eq!(gap_fill.get(tag::GAP_FILL_FLAG), Some("Y"));
+    assert_eq!(gap_fill.get(tag::NEW_SEQ_NO), Some("3"));
+
+    client.send(FixMessage::new(msg_type::LOGOUT)).await;
+    let logout = client.receive().await;
+    assert_eq!(logout.msg_type(), msg_type::LOGOUT);
+    assert_eq!(logout.get(tag::MSG_SEQ_NUM), Some("3"));
+    client.closed().await;
+
+    // a logon below the expected sequence number is refused
+    let (client, response) = Client::logon(&address, "secret", 1).await;
+    assert_eq!(response.msg_type(), msg_type::LOGOUT);
+    assert!(response.get(tag::TEXT).unwrap().contains("expecting 5"));
+    client.closed().await;
+
+    // a gap is asked for again after a logon above it
+    let (mut client, logon) = Client::logon(&address, "secret", 7).await;
+    assert_eq!(logon.msg_type(), msg_type::LOGON);
+    assert_eq!(logon.get(tag::MSG_SEQ_NUM), Some("5"));
+    let resend = client.receive().await;
+    assert_eq!(resend.msg_type(), msg_type::RESEND_REQUEST);
+    assert_eq!(resend.get(tag::BEGIN_SEQ_NO), Some("5"));
+}
+
+#[tokio::test]
+async fn resend_requests_replay_execution_reports() {
+    let address = spawn_gateway().await;
+    let (mut client, _) = Client::logon(&address, "secret", 1).await;
+
+    client.send(new_order("1", "1", 10, 100)).await;
+    let new = client.receive().await;
+    assert_eq!(new.get(tag::MSG_SEQ_NUM), Some("2"));
+
+    let resend = FixMessage::new(msg_type::RESEND_REQUEST)
+        .with(tag::BEGIN_SEQ_NO, 1)
+        .with(tag::ECHO_SEQ_NO_END, 0);
+    client.send(resend).await;
+
+    let gap_fill = client.receive().await;
+    assert_eq!(gap_fill.msg_type(), msg_type::SEQUENCE_RESET);
+    assert_eq!(gap_fill.get(tag::MSG_SEQ_NUM), Some("1"));
+    assert_eq!(gap_fill.get(tag::NEW_SEQ_NO), Some("2"));
+
+    let replayed = client.receive().await;
+    assert_report(&replayed, "1", "0", "0");
+    assert_eq!(replayed.get(tag::MSG_SEQ_NUM), Some("2"));
+    assert_eq!(replayed.get(tag::POSS_DUP_FLAG), Some("Y"));
+    assert_eq!(
+        replayed.get(tag::ORIG_SENDING_TIME),
+        new.get(tag::SENDING_TIME)
+    );
+    assert_eq!(replayed.get(tag::ORDER_ID), new.get(tag::ORDER_ID));
+}
@@ -0,0 +1,215 @@
+use exchange::configuration::ItchSettings;
+use exchange::domain::order::{Price, Quantity};
+use exchange::domain::order_entry::OrderEntry;
+use exchange::domain::side::Side;
+use exchange::gateway::itch::messages::{ItchMessage, MoldPacket, RetransmitRequest, read_packet};
+use exchange::gateway::itch::serve;
+use exchange::gateway::ouch::messages::padded;
+use exchange::matching::command::MatchingEngineCommand;
+use exchange::matching::engine::matching_engine;
+use exchange::matching::state::AppState;
+use std::sync::Arc;
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+use tokio::sync::{broadcast, mpsc};
+
+struct Feed {
+    state: AppState,
+    subscriber: UdpSocket,
+    retransmit: String,
+}
+
+async fn spawn_feed() -> Feed {
+    spawn_feed_with(1_000).await
+}
+
+/// `capacity` bounds the lossy broadcast of the engine, not the feed.
+async fn spawn_feed_with(capacity: usize) -> Feed {
+    let (tx, rx) = mpsc::channel(1_000);
+    let (ws_tx, _) = broadcast::channel(capacity);
+    tokio::spawn(matching_engine(rx, ws_tx.clone()));
+    let state = AppState::new(tx, ws_tx);
+
+    let subscriber = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let settings = ItchSettings {
+        target: subscriber.local_addr().unwrap().to_string(),
+        bind: "127.0.0.1:0".to_string(),
+        heartbeat_ms: 50,
+        ..Default::default()
+    };
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let retransmit = listener.local_addr().unwrap().to_string();
+    tokio::spawn(serve(listener, state.subscribe_all(), Arc::new(settings)));
+
+    let feed = Feed {
+        state,
+        subscriber,
+        retransmit,
+    };
+    // the first heartbeat shows the feed is subscribed to the engine
+    let heartbeat = feed.packet().await;
+    assert_eq!((heartbeat.sequence, heartbeat.messages.len()), (1, 0));
+    feed
+}
+
+impl Feed {
+    async fn packet(&self) -> MoldPacket {
+        let mut buf = [0; 2048];
+        let len = self.subscriber.recv(&mut buf).await.unwrap();
+        MoldPacket::decode(&buf[..len]).expect("Invalid packet")
+    }
+
+    /// Collects `count` messages, checking that none went missing.
+    async fn messages(&self, count: usize) -> Vec<Vec<u8>> {
+        let mut messages = Vec::new();
+        while messages.len() < count {
+            let packet = self.packet().await;
+            assert_eq!(packet.sequence, messages.len() as u64 + 1, "Gap in feed");
+            messages.extend(packet.messages);
+        }
+        messages
+    }
+}
+
+#[tokio::test]
+async fn book_changes_are_published_and_retransmitted() {
+    let feed = spawn_feed().await;
+    let request = |cmd| feed.state.request(cmd);
+
+    let resting = request(MatchingEngineCommand::Create(
+        OrderEntry::new(100, 10, Side::Sell).with_account(1),
+    ))
+    .await
+    .unwrap();
+    let taker = request(MatchingEngineCommand::Create(
+        OrderEntry::new(100, 4, Side::Buy).with_account(2),
+    ))
+    .await
+    .unwrap();
+    let order = request(MatchingEngineCommand::Create(
+        OrderEntry::new(101, 5, Side::Sell).with_account(1),
+    ))
+    .await
+    .unwrap();
+    let reduced = request(MatchingEngineCommand::Modify(
+        order.id,
+        order.revision,
+        None,
+        Some(Quantity(3)),
+        None,
+    ))
+    .await
+    .unwrap();
+    let repriced = request(MatchingEngineCommand::Modify(
+        order.id,
+        reduced.revision,
+        Some(Price(102)),
+        None,
+        None,
+    ))
+    .await
+    .unwrap();
+    request(MatchingEngineCommand::Delete(
+        order.id,
+        repriced.revision,
+        None,
+    ))
+    .await
+    .unwrap();
+
+    let messages = feed.messages(9).await;
+    let decoded: Vec<_> = messages
+        .iter()
+        .map(|m| ItchMessage::decode(m).expect("Invalid message"))
+        .collect();
+
+    match &decoded[..] {
+        [
+            ItchMessage::AddOrder {
+                order_id: a,
+                side: Side::Sell,
+                quantity: Quantity(10),
+                price: Price(100),
+                ..
+            },
+            ItchMessage::AddOrder {
+                order_id: b,
+                side: Side::Buy,
+                ..
+            },
+            ItchMessage::OrderExecuted {
+                order_id: maker,
+                quantity: Quantity(4),
+                match_number,
+                ..
+            },
+            ItchMessage::OrderExecuted {
+                order_id: taker_id, ..
+            },
+            ItchMessage::Trade {
+                side: Side::Buy,
+                quantity: Quantity(4),
+                price: Price(100),
+                match_number: print,
+                symbol,
+                ..
+            },
+            ItchMessage::AddOrder { order_id: c, .. },
+            ItchMessage::OrderCancel {
+                order_id: reduced_id,
+                quantity: Quantity(2),
+                ..
+            },
+            ItchMessage::OrderReplace {
+                quantity: Quantity(3),
+                price: Price(102),
+                ..
+            },
+            ItchMessage::OrderCancel {
+                order_id: deleted,
+                quantity: Quantity(3),
+                ..
+            },
+        ] => {
+            assert_eq!((*a, *maker), (resting.id, resting.id));
+            assert_eq!((*b, *taker_id), (taker.id, taker.id));
+            assert_eq!(match_number, print);
+            assert_eq!(symbol.as_str(), "DEFAULT");
+            assert_eq!((*c, *reduced_id, *deleted), (order.id, order.id, order.id));
+        }
+        other => panic!("Unexpected messages: {other:?}"),
+    }
+
+    let mut stream = TcpStream::connect(&feed.retransmit).await.unwrap();
+    let retransmit = RetransmitRequest {
+        session: padded("ITCH"),
+        sequence: 3,
+        count: 3,
+    };
+    stream.write_all(&retransmit.encode()).await.unwrap();
+    let packet = read_packet(&mut stream).await.unwrap();
+
+    assert_eq!(packet.sequence, 3);
+    assert_eq!(packet.messages, messages[2..5]);
+}
+
+#[tokio::test]
+async fn events_are_not_lost_when_the_broadcast_lags() {
+    let feed = spawn_feed_with(1).await;
+
+    for price in 1..=50 {
+        feed.state
+            .request(MatchingEngineCommand::Create(
+                OrderEntry::new(price, 1, Side::Buy).with_account(1),
+            ))
+            .await
+            .unwrap();
+    }
+
+    let messages = feed.messages(50).await;
+    assert!(
+        messages
+            .iter()
+            .all(|m| matches!(ItchMessage::decode(m), Some(ItchMessage::AddOrder { .. })))
+    );
+}
@@ -1,8 +1,26 @@
-use exchange::domain::order::Price;
+#![allow(clippy::assertions_on_constants)]
+
+use exchange::configuration::{
+    CircuitBreakerSettings, FeeSettings, FeeTier, InstrumentSettings, PhaseStart, ScheduleSettings,
+};
+use exchange::domain::account::AccountId;
+use exchange::domain::fill::Liquidity;
+use exchange::domain::order::{OrderId, Price, Quantity, Revision, TimeInForce};
+use exchange::domain::order_change::{CancelReason, ModifyReason};
 use exchange::domain::order_entry::OrderEntry;
 use exchange::domain::side::Side;
+use exchange::domain::symbol::Symbol;
+use exchange::domain::trading_phase::TradingPhase;
+use exchange::domain::trading_state::TradingState;
+use exchange::matching::clock::Clock;
 use exchange::matching::command::MatchingEngineCommand;
-use exchange::matching::engine::{MarketEvent, matching_engine};
+use exchange::matching::engine::{MarketEvent, MatchingEngine, matching_engine};
+use exchange::matching::reject::RejectCode;
+use exchange::matching::schedule::TimeOfDay;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicI64, Ordering};
+use uuid::Uuid;
 
 #[tokio::test]
 async fn test_matching_engine_broadcasts_trade() {
@@ -105,6 +123,7 @@ async fn test_matching_engine_broadcasts_trade_after_modification() {
                 order.revision,
                 Some(Price(100)),
                 None,
+                None,
             ))
             .await
             .unwrap(),
@@ -117,7 +136,14 @@ async fn test_matching_engine_broadcasts_trade_after_modification() {
 
     let modification_event = event_rx.recv().await.unwrap();
     match modification_event {
-        MarketEvent::OrderModified => {}
+        MarketEvent::OrderModified(change) => {
+            assert!(change.priority_lost);
+            assert_eq!(change.old_price, Price(120));
+            assert_eq!(change.new_price, Price(100));
+            assert_eq!(change.old_revision, Revision(0));
+            assert_eq!(change.new_revision, Revision(1));
+            assert_eq!(change.reason, ModifyReason::UserModify);
+        }
         _ => assert!(false, "Expected MarketEvent::OrderModified",),
     }
 
@@ -135,3 +161,781 @@ async fn test_matching_engine_broadcasts_trade_after_modification() {
         ),
     }
 }
+
+#[tokio::test]
+async fn test_matching_engine_cancels_all_account_orders() {
+    use tokio::sync::{broadcast, mpsc};
+
+    let (cmd_tx, cmd_rx) = mpsc::channel(10);
+    let (event_tx, _) = broadcast::channel(10);
+
+    let engine_tx = event_tx.clone();
+    tokio::spawn(matching_engine(cmd_rx, engine_tx));
+
+    let mut event_rx = event_tx.subscribe();
+
+    let quote = OrderEntry::new(100, 10, Side::Buy).with_account(7);
+    cmd_tx
+        .send(MatchingEngineCommand::Create(quote.clone()))
+        .await
+        .unwrap();
+    cmd_tx
+        .send(MatchingEngineCommand::Create(
+            OrderEntry::new(110, 10, Side::Sell).with_account(8),
+        ))
+        .await
+        .unwrap();
+    cmd_tx
+        .send(MatchingEngineCommand::CancelAll(AccountId(7)))
+        .await
+        .unwrap();
+
+    // skip both creations
+    let _ = event_rx.recv().await.unwrap();
+    let _ = event_rx.recv().await.unwrap();
+
+    let cancel_event = event_rx.recv().await.unwrap();
+    match cancel_event {
+        MarketEvent::OrderDeleted { order, reason } => {
+            assert_eq!(order.account, AccountId(7));
+            assert_eq!(order.price, quote.price);
+            assert_eq!(reason, CancelReason::CancelOnDisconnect);
+        }
+        _ => panic!(
+            "Expected MarketEvent::OrderDeleted, got: {:?}",
+            cancel_event
+        ),
+    }
+}
+
+#[tokio::test]
+async fn test_matching_engine_rejects_orders_while_halted() {
+    use tokio::sync::{broadcast, mpsc};
+
+    let (cmd_tx, cmd_rx) = mpsc::channel(10);
+    let (event_tx, _) = broadcast::channel(10);
+
+    let engine_tx = event_tx.clone();
+    tokio::spawn(matching_engine(cmd_rx, engine_tx));
+
+    let mut event_rx = event_tx.subscribe();
+
+    cmd_tx
+        .send(MatchingEngineCommand::Create(OrderEntry::new(
+            100,
+            10,
+            Side::Buy,
+        )))
+        .await
+        .unwrap();
+    cmd_tx
+        .send(MatchingEngineCommand::SetTradingState(
+            None,
+            TradingState::Halted,
+        ))
+        .await
+        .unwrap();
+    cmd_tx
+        .send(MatchingEngineCommand::Create(OrderEntry::new(
+            100,
+            10,
+            Side::Sell,
+        )))
+        .await
+        .unwrap();
+
+    let resting = match event_rx.recv().await.unwrap() {
+        MarketEvent::OrderCreated(order) => order,
+        event => panic!("Expected MarketEvent::OrderCreated, got: {:?}", event),
+    };
+
+    match event_rx.recv().await.unwrap() {
+        MarketEvent::TradingStateChanged { symbol, state } => {
+            assert_eq!(symbol, None);
+            assert_eq!(state, TradingState::Halted);
+        }
+        event => panic!(
+            "Expected MarketEvent::TradingStateChanged, got: {:?}",
+            event
+        ),
+    }
+
+    match event_rx.recv().await.unwrap() {
+        MarketEvent::OrderRejected { reject, .. } => assert_eq!(reject.code, RejectCode::Halted),
+        event => panic!("Expected MarketEvent::OrderRejected, got: {:?}", event),
+    }
+
+    // the sell order is rejected, but cancels are still accepted
+    cmd_tx
+        .send(MatchingEngineCommand::Delete(
+            resting.id,
+            resting.revision,
+            None,
+        ))
+        .await
+        .unwrap();
+
+    match event_rx.recv().await.unwrap() {
+        MarketEvent::OrderDeleted { order, reason } => {
+            assert_eq!(order.id, resting.id);
+            assert_eq!(reason, CancelReason::UserCancel);
+        }
+        event => panic!("Expected MarketEvent::OrderDeleted, got: {:?}", event),
+    }
+}
+
+#[tokio::test]
+async fn test_circuit_breaker_halts_and_resumes_instrument() {
+    use tokio::sync::{broadcast, mpsc};
+
+    let symbol: Symbol = "ABC".parse().unwrap();
+    let instruments = HashMap::from([(
+        symbol,
+        InstrumentSettings {
+            circuit_breaker: Some(CircuitBreakerSettings {
+                threshold_bps: 1_000,
+                window_ms: 60_000,
+                cooldown_ms: 50,
+            }),
+            ..Default::default()
+        },
+    )]);
+
+    let (cmd_tx, cmd_rx) = mpsc::channel(10);
+    let (event_tx, _) = broadcast::channel(20);
+
+    let engine = MatchingEngine::new(event_tx.clone()).with_instruments(instruments);
+    tokio::spawn(engine.run(cmd_rx));
+
+    let mut event_rx = event_tx.subscribe();
+
+    for (price, quantity, side) in [
+        (100, 10, Side::Buy),
+        (100, 10, Side::Sell),
+        (105, 10, Side::Sell),
+        (150, 10, Side::Sell),
+        (150, 20, Side::Buy),
+    ] {
+        cmd_tx
+            .send(MatchingEngineCommand::Create(
+                OrderEntry::new(price, quantity, side).with_symbol(symbol),
+            ))
+            .await
+            .unwrap();
+    }
+
+    let mut events = Vec::new();
+    while events.len() < 11 {
+        match event_rx.recv().await.unwrap() {
+            MarketEvent::Fill(_) | MarketEvent::OrderModified(_) => {}
+            event => events.push(event),
+        }
+    }
+    let trade_price = |event: &MarketEvent| match event {
+        MarketEvent::TradeExecuted(trade) => trade.price,
+        event => panic!("Expected MarketEvent::TradeExecuted, got: {:?}", event),
+    };
+
+    // the sweep trades within the band and stops before the level at 150
+    assert_eq!(trade_price(&events[6]), Price(105));
+    match &events[7] {
+        MarketEvent::CircuitBreakerTriggered {
+            symbol: s,
+            reference_price,
+            trigger_price,
+            ..
+        } => {
+            assert_eq!(*s, symbol);
+            assert_eq!(*reference_price, Price(100));
+            assert_eq!(*trigger_price, Price(150));
+        }
+        event => panic!(
+            "Expected MarketEvent::CircuitBreakerTriggered, got: {:?}",
+            event
+        ),
+    }
+    match &events[8] {
+        MarketEvent::TradingStateChanged { state, .. } => {
+            assert_eq!(*state, TradingState::Halted)
+        }
+        event => panic!(
+            "Expected MarketEvent::TradingStateChanged, got: {:?}",
+            event
+        ),
+    }
+    match &events[9] {
+        MarketEvent::TradingStateChanged { state, .. } => assert_eq!(*state, TradingState::Open),
+        event => panic!(
+            "Expected MarketEvent::TradingStateChanged, got: {:?}",
+            event
+        ),
+    }
+    // the rest of the buy order rested crossed and trades on the resume
+    assert_eq!(trade_price(&events[10]), Price(150));
+}
+
+#[tokio::test]
+async fn test_matching_engine_reports_fills_with_fees() {
+    use tokio::sync::{broadcast, mpsc};
+
+    let fees = FeeSettings {
+        tiers: vec![FeeTier {
+            name: "base".to_string(),
+            min_volume: 0,
+            maker_bps: -10,
+            taker_bps: 30,
+        }],
+        accounts: vec![],
+    };
+
+    let (cmd_tx, cmd_rx) = mpsc::channel(10);
+    let (event_tx, _) = broadcast::channel(10);
+
+    let engine = MatchingEngine::new(event_tx.clone()).with_fees(fees);
+    tokio::spawn(engine.run(cmd_rx));
+
+    let mut event_rx = event_tx.subscribe();
+
+    cmd_tx
+        .send(MatchingEngineCommand::Create(
+            OrderEntry::new(10_000, 1_000, Side::Sell).with_account(1),
+        ))
+        .await
+        .unwrap();
+    cmd_tx
+        .send(MatchingEngineCommand::Create(
+            OrderEntry::new(10_000, 1_000, Side::Buy).with_account(2),
+        ))
+        .await
+        .unwrap();
+
+    let mut fills = Vec::new();
+    while fills.len() < 2 {
+        if let MarketEvent::Fill(fill) = event_rx.recv().await.unwrap() {
+            fills.push(fill);
+        }
+    }
+
+    // notional 10_000 * 1_000 / 100 = 100_000
+    assert_eq!(fills[0].account, AccountId(1));
+    assert_eq!(fills[0].liquidity, Liquidity::Maker);
+    assert_eq!(fills[0].side, Side::Sell);
+    assert_eq!(fills[0].fee, -100);
+    assert_eq!(fills[1].account, AccountId(2));
+    assert_eq!(fills[1].liquidity, Liquidity::Taker);
+    assert_eq!(fills[1].fee, 300);
+    assert_eq!(fills[0].trade_id, fills[1].trade_id);
+    assert_eq!(fills[1].aggressor, Side::Buy);
+    assert_eq!(fills[1].leaves_quantity, Quantity(0));
+    assert_eq!(fills[1].cum_quantity, Quantity(1_000));
+    assert_eq!(fills[1].avg_price, Price(10_000));
+    assert_eq!(
+        MarketEvent::Fill(fills[1].clone()).recipient(),
+        Some(AccountId(2))
+    );
+}
+
+#[tokio::test]
+async fn test_matching_engine_reports_partial_fills_of_resting_orders() {
+    use tokio::sync::{broadcast, mpsc};
+
+    let (cmd_tx, cmd_rx) = mpsc::channel(10);
+    let (event_tx, _) = broadcast::channel(20);
+
+    tokio::spawn(matching_engine(cmd_rx, event_tx.clone()));
+
+    let mut event_rx = event_tx.subscribe();
+
+    for (quantity, side) in [(10, Side::Sell), (4, Side::Buy)] {
+        cmd_tx
+            .send(MatchingEngineCommand::Create(OrderEntry::new(
+                100, quantity, side,
+            )))
+            .await
+            .unwrap();
+    }
+
+    let change = loop {
+        if let MarketEvent::OrderModified(change) = event_rx.recv().await.unwrap() {
+            break change;
+        }
+    };
+
+    assert_eq!(change.reason, ModifyReason::PartialFill);
+    assert!(!change.priority_lost);
+    assert_eq!(change.old_quantity, Quantity(10));
+    assert_eq!(change.new_quantity, Quantity(6));
+    assert_eq!(change.new_revision, Revision(1));
+
+    // the new revision addresses the resting order
+    cmd_tx
+        .send(MatchingEngineCommand::Delete(
+            change.id,
+            change.new_revision,
+            None,
+        ))
+        .await
+        .unwrap();
+
+    loop {
+        match event_rx.recv().await.unwrap() {
+            MarketEvent::OrderDeleted { order, .. } => {
+                assert_eq!(order.id, change.id);
+                assert_eq!(order.quantity, Quantity(6));
+                break;
+            }
+            MarketEvent::Fill(_) => {}
+            event => panic!("Expected MarketEvent::OrderDeleted, got: {:?}", event),
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_matching_engine_rejects_orders_above_risk_limit() {
+    use tokio::sync::{broadcast, mpsc, oneshot};
+
+    let symbol: Symbol = "ABC".parse().unwrap();
+    let instruments = HashMap::from([(
+        symbol,
+        InstrumentSettings {
+            max_order_quantity: Some(100),
+            ..Default::default()
+        },
+    )]);
+
+    let (cmd_tx, cmd_rx) = mpsc::channel(10);
+    let (event_tx, _) = broadcast::channel(10);
+
+    let engine = MatchingEngine::new(event_tx.clone()).with_instruments(instruments);
+    tokio::spawn(engine.run(cmd_rx));
+
+    let mut event_rx = event_tx.subscribe();
+
+    let (respond_to, response) = oneshot::channel();
+    cmd_tx
+        .send(MatchingEngineCommand::Request(
+            Box::new(MatchingEngineCommand::Create(
+                OrderEntry::new(100, 101, Side::Buy)
+                    .with_symbol(symbol)
+                    .with_account(3),
+            )),
+            respond_to,
+        ))
+        .await
+        .unwrap();
+
+    let reject = response
+        .await
+        .unwrap()
+        .expect_err("Order should be rejected");
+    assert_eq!(reject.code, RejectCode::RiskReject);
+
+    match event_rx.recv().await.unwrap() {
+        event @ MarketEvent::OrderRejected { .. } => {
+            assert_eq!(event.recipient(), Some(AccountId(3)))
+        }
+        event => panic!("Expected MarketEvent::OrderRejected, got: {:?}", event),
+    }
+}
+
+#[tokio::test]
+async fn test_auction_collects_orders_and_uncrosses_on_open() {
+    use tokio::sync::{broadcast, mpsc};
+
+    let (cmd_tx, cmd_rx) = mpsc::channel(10);
+    let (event_tx, _) = broadcast::channel(50);
+
+    tokio::spawn(matching_engine(cmd_rx, event_tx.clone()));
+    let mut event_rx = event_tx.subscribe();
+
+    cmd_tx
+        .send(MatchingEngineCommand::SetTradingState(
+            None,
+            TradingState::Auction,
+        ))
+        .await
+        .unwrap();
+    for (price, quantity, side) in [
+        (101, 10, Side::Buy),
+        (99, 4, Side::Sell),
+        (100, 8, Side::Sell),
+    ] {
+        cmd_tx
+            .send(MatchingEngineCommand::Create(OrderEntry::new(
+                price, quantity, side,
+            )))
+            .await
+            .unwrap();
+    }
+    cmd_tx
+        .send(MatchingEngineCommand::SetTradingState(
+            None,
+            TradingState::Open,
+        ))
+        .await
+        .unwrap();
+
+    let mut indicative = Vec::new();
+    let mut trades = Vec::new();
+    let mut changes = Vec::new();
+    while changes.len() < 2 {
+        match event_rx.recv().await.unwrap() {
+            MarketEvent::IndicativeEquilibrium { equilibrium, .. } => {
+                indicative.push(equilibrium.map(|e| (e.price.0, e.volume.0, e.surplus)))
+            }
+            MarketEvent::TradeExecuted(trade) => trades.push((trade.price.0, trade.quantity.0)),
+            MarketEvent::OrderModified(change) => {
+                changes.push((change.side, change.new_quantity.0))
+            }
+            _ => {}
+        }
+    }
+
+    // nothing matched while the orders were collected
+    assert_eq!(indicative, [None, Some((101, 4, 6)), Some((100, 10, -2))]);
+    assert_eq!(trades, [(100, 4), (100, 6)]);
+    // the buy order was filled completely, the second sell order rests with 2
+    assert_eq!(changes, [(Side::Buy, 6), (Side::Sell, 2)]);
+}
+
+struct ManualClock(AtomicI64);
+
+impl Clock for ManualClock {
+    fn now(&self) -> i64 {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+#[test]
+fn test_schedule_moves_instrument_through_the_trading_day() {
+    use tokio::sync::broadcast;
+
+    const HOUR: i64 = 3_600 * 1_000_000_000;
+    let symbol: Symbol = "ABC".parse().unwrap();
+    let start = |phase, hours| PhaseStart {
+        phase,
+        start: TimeOfDay::hms(hours, 0, 0),
+    };
+    let instruments = HashMap::from([(
+        symbol,
+        InstrumentSettings {
+            schedule: Some(ScheduleSettings {
+                phases: vec![
+                    start(TradingPhase::PreOpen, 8),
+                    start(TradingPhase::Continuous, 9),
+                    start(TradingPhase::ClosingAuction, 17),
+                    start(TradingPhase::Closed, 18),
+                ],
+            }),
+            ..Default::default()
+        },
+    )]);
+
+    let clock = Arc::new(ManualClock(AtomicI64::new(8 * HOUR)));
+    let (event_tx, mut event_rx) = broadcast::channel(100);
+    let mut engine = MatchingEngine::new(event_tx)
+        .with_instruments(instruments)
+        .with_clock(clock.clone());
+
+    let mut events = |engine: &mut MatchingEngine, hour: i64, entry: OrderEntry| {
+        clock.0.store(hour * HOUR, Ordering::SeqCst);
+        engine.handle(MatchingEngineCommand::Create(entry.with_symbol(symbol)));
+        std::iter::from_fn(|| event_rx.try_recv().ok()).collect::<Vec<_>>()
+    };
+    let phases = |events: &[MarketEvent]| {
+        events
+            .iter()
+            .filter_map(|event| match event {
+                MarketEvent::PhaseChanged { phase, .. } => Some(*phase),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+    };
+
+    // orders are collected before the open, IOC orders would never trade
+    let pre_open = events(&mut engine, 8, OrderEntry::new(101, 5, Side::Buy));
+    assert_eq!(phases(&pre_open), [TradingPhase::PreOpen]);
+    assert!(
+        pre_open
+            .iter()
+            .all(|e| !matches!(e, MarketEvent::TradeExecuted(_)))
+    );
+    let ioc = OrderEntry::new(99, 1, Side::Sell).with_time_in_force(TimeInForce::Ioc);
+    match &events(&mut engine, 8, ioc)[..] {
+        [MarketEvent::OrderRejected { reject, .. }] => {
+            assert_eq!(reject.code, RejectCode::PhaseRestricted)
+        }
+        events => panic!("Expected MarketEvent::OrderRejected, got: {:?}", events),
+    }
+    let gtc = OrderEntry::new(100, 3, Side::Sell).with_time_in_force(TimeInForce::Gtc);
+    events(&mut engine, 8, gtc);
+
+    // the open uncrosses the book before the order of the command
+    let open = events(
+        &mut engine,
+        9,
+        OrderEntry::new(120, 1, Side::Sell).with_time_in_force(TimeInForce::Gtc),
+    );
+    assert_eq!(phases(&open), [TradingPhase::Continuous]);
+    let trades: Vec<_> = open
+        .iter()
+        .filter_map(|event| match event {
+            MarketEvent::TradeExecuted(trade) => Some((trade.price.0, trade.quantity.0)),
+            _ => None,
+        })
+        .collect();
+    // buyers are left over at every price, so the highest one is used
+    assert_eq!(trades, [(101, 3)]);
+
+    // the rest of the DAY buy order expires at the close, GTC orders stay
+    let close = events(&mut engine, 18, OrderEntry::new(100, 1, Side::Buy));
+    assert_eq!(phases(&close), [TradingPhase::Closed]);
+    let expired: Vec<_> = close
+        .iter()
+        .filter_map(|event| match event {
+            MarketEvent::OrderDeleted { order, reason } => Some((order.quantity.0, *reason)),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(expired, [(2, CancelReason::Expired)]);
+    match close.last() {
+        Some(MarketEvent::OrderRejected { reject, .. }) => {
+            assert_eq!(reject.code, RejectCode::Halted)
+        }
+        event => panic!("Expected MarketEvent::OrderRejected, got: {:?}", event),
+    }
+}
+
+#[test]
+fn test_orders_of_other_accounts_cannot_be_changed() {
+    use tokio::sync::{broadcast, oneshot};
+
+    let (event_tx, _) = broadcast::channel(100);
+    let mut engine = MatchingEngine::new(event_tx);
+    let mut request = |cmd| {
+        let (respond_to, mut response) = oneshot::channel();
+        engine.handle(MatchingEngineCommand::Request(Box::new(cmd), respond_to));
+        response.try_recv().expect("Engine responds right away")
+    };
+
+    let order = request(MatchingEngineCommand::Create(
+        OrderEntry::new(100, 5, Side::Buy).with_account(1),
+    ))
+    .unwrap();
+    let (id, revision) = (order.id, order.revision);
+
+    let modify =
+        MatchingEngineCommand::Modify(id, revision, None, Some(Quantity(1)), Some(AccountId(2)));
+    assert_eq!(request(modify).unwrap_err().code, RejectCode::NotFound);
+    let cancel = MatchingEngineCommand::Delete(id, revision, Some(AccountId(2)));
+    assert_eq!(request(cancel).unwrap_err().code, RejectCode::NotFound);
+
+    let cancel = MatchingEngineCommand::Delete(id, revision, Some(AccountId(1)));
+    assert_eq!(request(cancel).unwrap().id, id);
+}
+
+#[test]
+fn test_trades_are_stamped_by_the_engine_clock() {
+    use tokio::sync::broadcast;
+
+    let (event_tx, mut event_rx) = broadcast::channel(100);
+    let clock = Arc::new(ManualClock(AtomicI64::new(42)));
+    let mut engine = MatchingEngine::new(event_tx).with_clock(clock);
+
+    engine.handle(MatchingEngineCommand::Create(OrderEntry::new(
+        100,
+        5,
+        Side::Buy,
+    )));
+    engine.handle(MatchingEngineCommand::Create(OrderEntry::new(
+        100,
+        5,
+        Side::Sell,
+    )));
+
+    let times: Vec<_> = std::iter::from_fn(|| event_rx.try_recv().ok())
+        .filter_map(|event| match event {
+            MarketEvent::TradeExecuted(trade) => Some(trade.exec_time()),
+            MarketEvent::Fill(fill) => Some(fill.exec_time),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(times, [42, 42, 42]);
+}
+
+#[test]
+fn test_nested_requests_are_rejected() {
+    use tokio::sync::{broadcast, oneshot};
+
+    let (event_tx, mut event_rx) = broadcast::channel(100);
+    let mut engine = MatchingEngine::new(event_tx);
+    let (inner_respond_to, mut inner) = oneshot::channel();
+    let (respond_to, mut response) = oneshot::channel();
+    let create = MatchingEngineCommand::Create(OrderEntry::new(100, 5, Side::Buy));
+    let nested = MatchingEngineCommand::Request(Box::new(create), inner_respond_to);
+
+    engine.handle(MatchingEngineCommand::Request(Box::new(nested), respond_to));
+
+    let reject = response.try_recv().expect("Engine responds right away");
+    assert_eq!(reject.unwrap_err().code, RejectCode::InvalidRequest);
+    assert!(inner.try_recv().is_err(), "The nested request is not run");
+    assert!(event_rx.try_recv().is_err(), "No order is placed");
+}
+
+#[test]
+fn test_rejected_cancels_are_published_to_the_requester() {
+    use tokio::sync::broadcast;
+
+    let (event_tx, mut event_rx) = broadcast::channel(100);
+    let mut engine = MatchingEngine::new(event_tx);
+    let unknown = OrderId::from(Uuid::new_v4());
+
+    engine.handle(MatchingEngineCommand::Delete(unknown, Revision(0), None));
+    assert!(
+        event_rx.try_recv().is_err(),
+        "Nobody to address the reject to"
+    );
+
+    engine.handle(MatchingEngineCommand::Delete(
+        unknown,
+        Revision(0),
+        Some(AccountId(5)),
+    ));
+    match event_rx.try_recv() {
+        Ok(MarketEvent::OrderRejected {
+            order_id,
+            account,
+            reject,
+        }) => {
+            assert_eq!(order_id, unknown);
+            assert_eq!(account, AccountId(5));
+            assert_eq!(reject.code, RejectCode::NotFound);
+        }
+        event => panic!("Expected MarketEvent::OrderRejected, got: {:?}", event),
+    }
+}
+
+#[test]
+fn test_feeds_receive_the_events_of_each_command_as_one_batch() {
+    use tokio::sync::{broadcast, mpsc, oneshot};
+
+    let (event_tx, _) = broadcast::channel(100);
+    let mut engine = MatchingEngine::new(event_tx);
+    let (feed, mut batches) = mpsc::unbounded_channel();
+    engine.handle(MatchingEngineCommand::SubscribeAll(feed));
+
+    engine.handle(MatchingEngineCommand::Create(OrderEntry::new(
+        100,
+        5,
+        Side::Sell,
+    )));
+    engine.handle(MatchingEngineCommand::Create(OrderEntry::new(
+        100,
+        3,
+        Side::Buy,
+    )));
+
+    let first = batches.try_recv().expect("Order was created");
+    assert_eq!(first.sequence, 1);
+    assert!(matches!(first.events[..], [MarketEvent::OrderCreated(_)]));
+    let second = batches.try_recv().expect("Orders traded");
+    assert_eq!(second.sequence, 2);
+    assert!(matches!(
+        second.events[..],
+        [
+            MarketEvent::OrderCreated(_),
+            MarketEvent::TradeExecuted(_),
+            ..
+        ]
+    ));
+    assert!(batches.try_recv().is_err());
+
+    let (respond_to, mut snapshot) = oneshot::channel();
+    engine.handle(MatchingEngineCommand::Snapshot(
+        Symbol::default(),
+        respond_to,
+    ));
+    let snapshot = snapshot.try_recv().expect("Engine responds right away");
+    assert_eq!(snapshot.sequence, 1 + second.events.len() as u64);
+    assert_eq!(snapshot.asks[0].quantity, Quantity(2));
+}
+
+#[test]
+fn test_halted_instrument_stays_halted_across_phase_changes() {
+    use tokio::sync::broadcast;
+
+    const HOUR: i64 = 3_600 * 1_000_000_000;
+    let symbol: Symbol = "ABC".parse().unwrap();
+    let start = |phase, hours| PhaseStart {
+        phase,
+        start: TimeOfDay::hms(hours, 0, 0),
+    };
+    let instruments = HashMap::from([(
+        symbol,
+        InstrumentSettings {
+            schedule: Some(ScheduleSettings {
+                phases: vec![
+                    start(TradingPhase::PreOpen, 8),
+                    start(TradingPhase::Continuous, 9),
+                ],
+            }),
+            ..Default::default()
+        },
+    )]);
+
+    let clock = Arc::new(ManualClock(AtomicI64::new(8 * HOUR)));
+    let (event_tx, mut event_rx) = broadcast::channel(100);
+    let mut engine = MatchingEngine::new(event_tx)
+        .with_instruments(instruments)
+        .with_clock(clock.clone());
+
+    let mut events = |engine: &mut MatchingEngine, hour: i64, cmd| {
+        clock.0.store(hour * HOUR, Ordering::SeqCst);
+        engine.handle(cmd);
+        std::iter::from_fn(|| event_rx.try_recv().ok()).collect::<Vec<_>>()
+    };
+    let create = |price, side| {
+        MatchingEngineCommand::Create(OrderEntry::new(price, 5, side).with_symbol(symbol))
+    };
+    let trades = |events: &[MarketEvent]| {
+        events
+            .iter()
+            .filter(|event| matches!(event, MarketEvent::TradeExecuted(_)))
+            .count()
+    };
+    let states = |events: &[MarketEvent]| {
+        events
+            .iter()
+            .filter_map(|event| match event {
+                MarketEvent::TradingStateChanged { state, .. } => Some(*state),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+    };
+
+    // the crossed orders wait for the opening auction
+    events(&mut engine, 8, create(101, Side::Buy));
+    events(&mut engine, 8, create(100, Side::Sell));
+    let halt = MatchingEngineCommand::SetTradingState(Some(symbol), TradingState::Halted);
+    assert_eq!(
+        states(&events(&mut engine, 8, halt)),
+        [TradingState::Halted]
+    );
+
+    // the open neither lifts the halt nor uncrosses the book
+    let open = events(&mut engine, 9, create(99, Side::Buy));
+    assert_eq!(states(&open), [TradingState::Halted]);
+    assert_eq!(trades(&open), 0);
+    match open.last() {
+        Some(MarketEvent::OrderRejected { reject, .. }) => {
+            assert_eq!(reject.code, RejectCode::Halted)
+        }
+        event => panic!("Expected MarketEvent::OrderRejected, got: {:?}", event),
+    }
+
+    // resuming trades the book under the current phase
+    let resume = MatchingEngineCommand::SetTradingState(Some(symbol), TradingState::Open);
+    let resumed = events(&mut engine, 9, resume);
+    assert_eq!(states(&resumed), [TradingState::Open]);
+    assert_eq!(trades(&resumed), 1);
+}
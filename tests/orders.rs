@@ -0,0 +1,377 @@
+use crate::utils::test_app::{ADMIN_TOKEN, api_key, spawn_app, spawn_app_with};
+use exchange::configuration::{ApiKey, RateLimitSettings, TokenBucketSettings};
+use exchange::domain::account::AccountId;
+use exchange::domain::order::Order;
+use exchange::rate_limit::RateLimits;
+use serde_json::{Value, json};
+
+mod utils;
+
+#[tokio::test]
+async fn mass_cancel_returns_cancelled_orders() {
+    let app = spawn_app();
+    let client = reqwest::Client::new();
+
+    let orders = [
+        (
+            1,
+            r#"[
+                {"price": 100, "quantity": 10, "side": "Buy", "symbol": "ABC"},
+                {"price": 101, "quantity": 10, "side": "Buy", "symbol": "XYZ"},
+                {"price": 120, "quantity": 10, "side": "Sell", "symbol": "ABC"}
+            ]"#,
+        ),
+        (
+            2,
+            r#"[{"price": 99, "quantity": 10, "side": "Buy", "symbol": "ABC"}]"#,
+        ),
+    ];
+    for (account, body) in orders {
+        let response = client
+            .post(format!("{}/orders", &app.address))
+            .bearer_auth(api_key(account))
+            .header("Content-Type", "application/json")
+            .body(body)
+            .send()
+            .await
+            .expect("Failed to create orders!");
+        assert!(response.status().is_success());
+    }
+
+    let mass_cancel = |key: String| {
+        client
+            .delete(format!("{}/orders/mass", &app.address))
+            .header("Content-Type", "application/json")
+            .bearer_auth(key)
+            .body(r#"{"account": 1, "side": "Buy", "symbol": "ABC"}"#)
+            .send()
+    };
+
+    let response = mass_cancel(api_key(2))
+        .await
+        .expect("Failed to mass cancel orders!");
+    assert_eq!(
+        response.status().as_u16(),
+        401,
+        "Orders of other accounts cannot be cancelled"
+    );
+
+    let response = mass_cancel(api_key(1))
+        .await
+        .expect("Failed to mass cancel orders!");
+    assert!(response.status().is_success());
+
+    let cancelled: Vec<Order> =
+        serde_json::from_str(&response.text().await.unwrap()).expect("Invalid response body");
+
+    assert_eq!(cancelled.len(), 1);
+    assert_eq!(cancelled[0].price.0, 100);
+    assert_eq!(cancelled[0].symbol.as_str(), "ABC");
+}
+
+#[tokio::test]
+async fn mass_cancel_without_an_account_needs_the_admin_token() {
+    let app = spawn_app();
+    let client = reqwest::Client::new();
+
+    let response = client
+        .post(format!("{}/orders", &app.address))
+        .bearer_auth(api_key(1))
+        .header("Content-Type", "application/json")
+        .body(r#"[{"price": 100, "quantity": 10, "side": "Buy"}]"#)
+        .send()
+        .await
+        .expect("Failed to create orders!");
+    assert!(response.status().is_success());
+
+    let mass_cancel = |token: Option<&str>| {
+        let request = client
+            .delete(format!("{}/orders/mass", &app.address))
+            .header("Content-Type", "application/json")
+            .body("{}");
+        match token {
+            Some(token) => request.bearer_auth(token),
+            None => request,
+        }
+        .send()
+    };
+
+    let response = mass_cancel(None)
+        .await
+        .expect("Failed to mass cancel orders!");
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = mass_cancel(Some(ADMIN_TOKEN))
+        .await
+        .expect("Failed to mass cancel orders!");
+    assert!(response.status().is_success());
+    let cancelled: Vec<Order> =
+        serde_json::from_str(&response.text().await.unwrap()).expect("Invalid response body");
+    assert_eq!(cancelled.len(), 1, "The order was still resting");
+}
+
+#[tokio::test]
+async fn order_entry_is_rate_limited_per_account() {
+    let app = spawn_app_with(|state| {
+        state
+            .with_api_keys(vec![
+                ApiKey {
+                    account: AccountId(1),
+                    key: "key-1".to_string(),
+                },
+                ApiKey {
+                    account: AccountId(2),
+                    key: "key-2".to_string(),
+                },
+            ])
+            .with_rate_limits(RateLimits::new(RateLimitSettings {
+                account: TokenBucketSettings {
+                    burst: 2,
+                    refill_per_second: 1,
+                },
+                ..Default::default()
+            }))
+    });
+    let client = reqwest::Client::new();
+    let order = r#"{"price": 100, "quantity": 10, "side": "Buy"}"#;
+    let create = |key: Option<&str>, body: String| {
+        let request = client
+            .post(format!("{}/orders", &app.address))
+            .header("Content-Type", "application/json")
+            .body(body);
+        match key {
+            Some(key) => request.bearer_auth(key),
+            None => request,
+        }
+        .send()
+    };
+
+    let response = create(Some("key-1"), format!("[{order}, {order}]"))
+        .await
+        .expect("Failed to create orders!");
+    assert!(response.status().is_success());
+
+    let response = create(Some("key-1"), format!("[{order}]"))
+        .await
+        .expect("Failed to create orders!");
+
+    assert_eq!(response.status().as_u16(), 429);
+    assert_eq!(response.headers()["Retry-After"], "1");
+
+    let response = create(Some("key-2"), format!("[{order}]"))
+        .await
+        .expect("Failed to create orders!");
+
+    assert!(
+        response.status().is_success(),
+        "Other accounts are not throttled"
+    );
+
+    let response = create(None, format!("[{order}]"))
+        .await
+        .expect("Failed to create orders!");
+
+    assert_eq!(
+        response.status().as_u16(),
+        401,
+        "Orders need the API key of their account"
+    );
+}
+
+#[tokio::test]
+async fn order_entry_returns_per_order_results() {
+    let app = spawn_app();
+    let client = reqwest::Client::new();
+
+    let response = client
+        .post(format!("{}/orders", &app.address))
+        .bearer_auth(api_key(1))
+        .header("Content-Type", "application/json")
+        .body(
+            r#"[
+                {"price": 100, "quantity": 10, "side": "Buy"},
+                {"price": 0, "quantity": 10, "side": "Buy"}
+            ]"#,
+        )
+        .send()
+        .await
+        .expect("Failed to create orders!");
+
+    assert_eq!(response.status().as_u16(), 207, "Some orders were accepted");
+
+    let results: Vec<Value> =
+        serde_json::from_str(&response.text().await.unwrap()).expect("Invalid response body");
+    assert_eq!(results[0]["status"], "accepted");
+    assert_eq!(results[0]["order"]["price"], 100);
+    assert_eq!(results[1]["status"], "rejected");
+    assert_eq!(results[1]["code"], "invalid_price");
+}
+
+#[tokio::test]
+async fn orders_belong_to_the_account_of_the_api_key() {
+    let app = spawn_app();
+    let client = reqwest::Client::new();
+    let order = r#"[{"price": 100, "quantity": 10, "side": "Buy", "account": 2}]"#;
+
+    let response = client
+        .post(format!("{}/orders", &app.address))
+        .header("Content-Type", "application/json")
+        .body(order)
+        .send()
+        .await
+        .expect("Failed to create orders!");
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = client
+        .post(format!("{}/orders", &app.address))
+        .bearer_auth(api_key(1))
+        .header("Content-Type", "application/json")
+        .body(order)
+        .send()
+        .await
+        .expect("Failed to create orders!");
+    assert!(response.status().is_success());
+    let results: Vec<Value> =
+        serde_json::from_str(&response.text().await.unwrap()).expect("Invalid response body");
+    assert_eq!(
+        results[0]["order"]["account"], 1,
+        "The body names another account"
+    );
+}
+
+#[tokio::test]
+async fn unknown_orders_are_not_found() {
+    let app = spawn_app();
+    let client = reqwest::Client::new();
+    let unknown = r#"[{"id": "00000000-0000-0000-0000-000000000000", "revision": 0}]"#;
+
+    let response = client
+        .delete(format!("{}/orders", &app.address))
+        .bearer_auth(api_key(1))
+        .header("Content-Type", "application/json")
+        .body(unknown)
+        .send()
+        .await
+        .expect("Failed to delete orders!");
+
+    assert_eq!(response.status().as_u16(), 404);
+    let results: Vec<Value> =
+        serde_json::from_str(&response.text().await.unwrap()).expect("Invalid response body");
+    assert_eq!(results[0]["code"], "not_found");
+
+    let response = client
+        .patch(format!("{}/orders", &app.address))
+        .bearer_auth(api_key(1))
+        .header("Content-Type", "application/json")
+        .body(unknown.replace("0}", r#"0, "new_quantity": 5}"#))
+        .send()
+        .await
+        .expect("Failed to modify orders!");
+
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn orders_are_only_changed_with_the_api_key_of_their_account() {
+    let app = spawn_app();
+    let client = reqwest::Client::new();
+
+    let response = client
+        .post(format!("{}/orders", &app.address))
+        .bearer_auth(api_key(1))
+        .header("Content-Type", "application/json")
+        .body(r#"[{"price": 100, "quantity": 10, "side": "Buy"}]"#)
+        .send()
+        .await
+        .expect("Failed to create orders!");
+    let results: Vec<Value> =
+        serde_json::from_str(&response.text().await.unwrap()).expect("Invalid response body");
+    let cancel = json!([{"id": results[0]["order"]["id"], "revision": 0}]).to_string();
+
+    let response = client
+        .delete(format!("{}/orders", &app.address))
+        .header("Content-Type", "application/json")
+        .body(cancel.clone())
+        .send()
+        .await
+        .expect("Failed to delete orders!");
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = client
+        .delete(format!("{}/orders", &app.address))
+        .bearer_auth(api_key(2))
+        .header("Content-Type", "application/json")
+        .body(cancel.clone())
+        .send()
+        .await
+        .expect("Failed to delete orders!");
+    assert_eq!(response.status().as_u16(), 404);
+
+    let response = client
+        .delete(format!("{}/orders", &app.address))
+        .bearer_auth(api_key(1))
+        .header("Content-Type", "application/json")
+        .body(cancel)
+        .send()
+        .await
+        .expect("Failed to delete orders!");
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn atomic_batches_are_all_or_nothing() {
+    let app = spawn_app();
+    let client = reqwest::Client::new();
+
+    let response = client
+        .post(format!("{}/orders?atomic=true", &app.address))
+        .bearer_auth(api_key(1))
+        .header("Content-Type", "application/json")
+        .body(
+            r#"[
+                {"price": 100, "quantity": 10, "side": "Buy"},
+                {"price": 100, "quantity": 0, "side": "Buy"}
+            ]"#,
+        )
+        .send()
+        .await
+        .expect("Failed to create orders!");
+
+    assert_eq!(response.status().as_u16(), 400);
+    let results: Vec<Value> =
+        serde_json::from_str(&response.text().await.unwrap()).expect("Invalid response body");
+    assert_eq!(results[0]["code"], "batch_aborted");
+    assert_eq!(results[1]["code"], "invalid_quantity");
+
+    let response = client
+        .delete(format!("{}/orders/mass", &app.address))
+        .header("Content-Type", "application/json")
+        .bearer_auth(api_key(1))
+        .body(r#"{"account": 1}"#)
+        .send()
+        .await
+        .expect("Failed to mass cancel orders!");
+    let cancelled: Vec<Order> =
+        serde_json::from_str(&response.text().await.unwrap()).expect("Invalid response body");
+    assert!(cancelled.is_empty(), "No order of the batch is placed");
+
+    let response = client
+        .post(format!("{}/orders?atomic=true", &app.address))
+        .bearer_auth(api_key(1))
+        .header("Content-Type", "application/json")
+        .body(
+            r#"[
+                {"price": 100, "quantity": 10, "side": "Buy"},
+                {"price": 101, "quantity": 5, "side": "Buy"}
+            ]"#,
+        )
+        .send()
+        .await
+        .expect("Failed to create orders!");
+
+    assert!(response.status().is_success());
+    let results: Vec<Value> =
+        serde_json::from_str(&response.text().await.unwrap()).expect("Invalid response body");
+    assert_eq!(results.len(), 2);
+    assert!(results.iter().all(|r| r["status"] == "accepted"));
+}
@@ -0,0 +1,224 @@
+use exchange::configuration::{AccountCredential, OuchSettings};
+use exchange::domain::account::AccountId;
+use exchange::domain::order::{Price, Quantity, TimeInForce};
+use exchange::domain::side::Side;
+use exchange::gateway::ouch::messages::{
+    ClientPacket, Request, Response, ServerPacket, Token, padded, read_packet,
+};
+use exchange::gateway::ouch::{LOGIN_NOT_AUTHORIZED, LOGIN_SESSION_UNAVAILABLE, serve};
+use exchange::matching::engine::matching_engine;
+use exchange::matching::state::AppState;
+use std::sync::Arc;
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{broadcast, mpsc};
+
+async fn spawn_gateway() -> String {
+    let (tx, rx) = mpsc::channel(1_000);
+    let (ws_tx, _) = broadcast::channel(1_000);
+    tokio::spawn(matching_engine(rx, ws_tx.clone()));
+
+    let settings = OuchSettings {
+        credentials: vec![AccountCredential {
+            account: AccountId(1),
+            password: "secret".to_string(),
+        }],
+        ..Default::default()
+    };
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    tokio::spawn(serve(
+        listener,
+        AppState::new(tx, ws_tx),
+        Arc::new(settings),
+    ));
+
+    address
+}
+
+async fn login(address: &str, password: &str, sequence: u64) -> (TcpStream, ServerPacket) {
+    let mut stream = TcpStream::connect(address).await.unwrap();
+    let login = ClientPacket::Login {
+        account: AccountId(1),
+        credential: padded(password),
+        sequence,
+    };
+    stream.write_all(&login.encode()).await.unwrap();
+    let response = receive(&mut stream).await;
+    (stream, response)
+}
+
+/// Logs in as soon as the previous connection of the account is gone.
+async fn login_once_free(address: &str, sequence: u64) -> (TcpStream, ServerPacket) {
+    let unavailable = ServerPacket::LoginRejected {
+        reason: LOGIN_SESSION_UNAVAILABLE,
+    };
+    loop {
+        let (stream, response) = login(address, "secret", sequence).await;
+        if response != unavailable {
+            return (stream, response);
+        }
+        tokio::task::yield_now().await;
+    }
+}
+
+async fn receive(stream: &mut TcpStream) -> ServerPacket {
+    loop {
+        let packet = read_packet(stream).await.expect("Connection closed");
+        match ServerPacket::decode(&packet).expect("Invalid packet") {
+            ServerPacket::Heartbeat => continue,
+            packet => return packet,
+        }
+    }
+}
+
+async fn send(stream: &mut TcpStream, request: Request) {
+    stream
+        .write_all(&ClientPacket::Order(request).encode())
+        .await
+        .unwrap();
+}
+
+fn enter(token: Token, side: Side, quantity: i64) -> Request {
+    Request::Enter {
+        token,
+        side,
+        quantity: Quantity(quantity),
+        symbol: "ABC".parse().unwrap(),
+        price: Price(100),
+        time_in_force: TimeInForce::Day,
+    }
+}
+
+#[tokio::test]
+async fn login_requires_the_account_credential() {
+    let address = spawn_gateway().await;
+
+    let (_, response) = login(&address, "wrong", 0).await;
+
+    assert_eq!(
+        response,
+        ServerPacket::LoginRejected {
+            reason: LOGIN_NOT_AUTHORIZED
+        }
+    );
+}
+
+#[tokio::test]
+async fn accounts_have_one_live_session() {
+    let address = spawn_gateway().await;
+    let (mut first, _) = login(&address, "secret", 0).await;
+    send(&mut first, enter(padded("S1"), Side::Sell, 10)).await;
+    assert!(matches!(
+        receive(&mut first).await,
+        ServerPacket::Sequenced(Response::Accepted { .. })
+    ));
+
+    let (_, response) = login(&address, "secret", 1).await;
+    assert_eq!(
+        response,
+        ServerPacket::LoginRejected {
+            reason: LOGIN_SESSION_UNAVAILABLE
+        }
+    );
+    drop(first);
+
+    // a login ahead of the log resumes at its end without dropping anything
+    let (second, response) = login_once_free(&address, 99).await;
+    assert!(matches!(
+        response,
+        ServerPacket::LoginAccepted { sequence: 2, .. }
+    ));
+    drop(second);
+
+    let (mut third, response) = login_once_free(&address, 1).await;
+    assert!(matches!(
+        response,
+        ServerPacket::LoginAccepted { sequence: 1, .. }
+    ));
+    assert!(matches!(
+        receive(&mut third).await,
+        ServerPacket::Sequenced(Response::Accepted { .. })
+    ));
+}
+
+#[tokio::test]
+async fn orders_are_accepted_executed_and_replayed() {
+    let address = spawn_gateway().await;
+    let (sell, buy) = (padded("S1"), padded("B1"));
+
+    let (mut stream, response) = login(&address, "secret", 0).await;
+    assert!(matches!(
+        response,
+        ServerPacket::LoginAccepted { sequence: 1, .. }
+    ));
+
+    send(&mut stream, enter(sell, Side::Sell, 10)).await;
+    send(&mut stream, enter(buy, Side::Buy, 4)).await;
+    send(&mut stream, Request::Cancel { token: sell }).await;
+
+    let mut responses = Vec::new();
+    for _ in 0..5 {
+        match receive(&mut stream).await {
+            ServerPacket::Sequenced(response) => responses.push(response),
+            packet => panic!("Expected a sequenced packet, got: {:?}", packet),
+        }
+    }
+
+    assert!(matches!(responses[0], Response::Accepted { token, .. } if token == sell));
+    assert!(matches!(responses[1], Response::Accepted { token, .. } if token == buy));
+    assert!(matches!(
+        responses[2],
+        Response::Executed { token, liquidity: b'A', leaves_quantity: Quantity(6), .. } if token == sell
+    ));
+    assert!(matches!(
+        responses[3],
+        Response::Executed { token, liquidity: b'R', leaves_quantity: Quantity(0), .. } if token == buy
+    ));
+    assert!(matches!(
+        responses[4],
+        Response::Canceled { token, quantity: Quantity(6), .. } if token == sell
+    ));
+
+    stream
+        .write_all(&ClientPacket::Logout.encode())
+        .await
+        .unwrap();
+
+    // a new connection replays the session from the requested sequence
+    let (mut stream, response) = login(&address, "secret", 3).await;
+    assert!(matches!(
+        response,
+        ServerPacket::LoginAccepted { sequence: 3, .. }
+    ));
+    match receive(&mut stream).await {
+        ServerPacket::Sequenced(response) => assert_eq!(response, responses[2]),
+        packet => panic!("Expected a sequenced packet, got: {:?}", packet),
+    }
+}
+
+#[tokio::test]
+async fn unfilled_ioc_orders_are_canceled() {
+    let address = spawn_gateway().await;
+    let token = padded("I1");
+    let (mut stream, _) = login(&address, "secret", 0).await;
+
+    let ioc = Request::Enter {
+        token,
+        side: Side::Buy,
+        quantity: Quantity(4),
+        symbol: "ABC".parse().unwrap(),
+        price: Price(100),
+        time_in_force: TimeInForce::Ioc,
+    };
+    send(&mut stream, ioc).await;
+
+    assert!(matches!(
+        receive(&mut stream).await,
+        ServerPacket::Sequenced(Response::Accepted { token: t, .. }) if t == token
+    ));
+    assert!(matches!(
+        receive(&mut stream).await,
+        ServerPacket::Sequenced(Response::Canceled { token: t, quantity: Quantity(4), reason: b'I', .. }) if t == token
+    ));
+}
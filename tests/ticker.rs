@@ -0,0 +1,107 @@
+use crate::utils::test_app::{api_key, spawn_app, spawn_app_with};
+use exchange::configuration::TickerSettings;
+use futures_util::StreamExt;
+use serde_json::Value;
+use tokio_tungstenite::connect_async;
+use tokio_tungstenite::tungstenite::Message;
+
+mod utils;
+
+#[tokio::test]
+async fn tickers_combine_trades_and_the_book() {
+    let app = spawn_app_with(|state| state.with_ticker(TickerSettings { interval_ms: 10 }));
+    let client = reqwest::Client::new();
+    let url = format!("{}/ws/ticker?symbol=ABC", app.address.replace("http", "ws"));
+    let (mut ws, _) = connect_async(url).await.expect("Failed to connect");
+
+    let response = client
+        .post(format!("{}/orders", &app.address))
+        .bearer_auth(api_key(1))
+        .header("Content-Type", "application/json")
+        .body(
+            r#"[
+                {"price": 100, "quantity": 100, "side": "Sell", "symbol": "ABC"},
+                {"price": 104, "quantity": 100, "side": "Sell", "symbol": "ABC"},
+                {"price": 104, "quantity": 150, "side": "Buy", "symbol": "ABC"},
+                {"price": 99, "quantity": 5, "side": "Buy", "symbol": "ABC"},
+                {"price": 50, "quantity": 1, "side": "Buy", "symbol": "XYZ"}
+            ]"#,
+        )
+        .send()
+        .await
+        .expect("Failed to create orders!");
+    assert!(response.status().is_success());
+
+    // tickers are throttled, the last one reflects every order
+    let ticker = loop {
+        let Message::Text(text) = ws.next().await.unwrap().unwrap() else {
+            continue;
+        };
+        let ticker: Value = serde_json::from_str(&text).expect("Invalid ticker");
+        assert_eq!(ticker["symbol"], "ABC");
+        if ticker["best_bid"]["price"] == 99 {
+            break ticker;
+        }
+    };
+    assert_eq!(ticker["last"], 104);
+    assert_eq!(
+        (&ticker["open"], &ticker["high"]),
+        (&100.into(), &104.into())
+    );
+    assert_eq!(
+        (&ticker["volume"], &ticker["trades"]),
+        (&150.into(), &2.into())
+    );
+    assert_eq!(ticker["vwap"], 101);
+    assert_eq!(ticker["change_bps"], 400);
+    assert_eq!(ticker["best_ask"]["price"], 104);
+    assert_eq!(ticker["best_ask"]["quantity"], 50);
+
+    let response = client
+        .get(format!("{}/ticker?symbol=ABC", &app.address))
+        .send()
+        .await
+        .expect("Failed to query the ticker!");
+    assert!(response.status().is_success());
+    let queried: Value = serde_json::from_str(&response.text().await.unwrap()).unwrap();
+    assert_eq!(queried, ticker);
+
+    // instruments are known once the ticker saw their events
+    let all = loop {
+        let response = client
+            .get(format!("{}/ticker", &app.address))
+            .send()
+            .await
+            .unwrap();
+        let all: Vec<Value> = serde_json::from_str(&response.text().await.unwrap()).unwrap();
+        if all.len() == 2 {
+            break all;
+        }
+        tokio::task::yield_now().await;
+    };
+    assert_eq!(all[1]["symbol"], "XYZ");
+    assert_eq!(all[1]["best_bid"]["price"], 50);
+    assert_eq!(all[1]["last"], Value::Null);
+}
+
+#[tokio::test]
+async fn unknown_instruments_have_an_empty_ticker() {
+    let app = spawn_app();
+
+    let response = reqwest::get(format!("{}/ticker?symbol=NEW", &app.address))
+        .await
+        .expect("Failed to query the ticker!");
+    assert!(response.status().is_success());
+
+    let ticker: Value = serde_json::from_str(&response.text().await.unwrap()).unwrap();
+    assert_eq!(ticker["symbol"], "NEW");
+    assert_eq!(
+        (&ticker["last"], &ticker["vwap"]),
+        (&Value::Null, &Value::Null)
+    );
+    assert_eq!(
+        (&ticker["volume"], &ticker["trades"]),
+        (&0.into(), &0.into())
+    );
+    assert_eq!(ticker["best_bid"], Value::Null);
+}
@@ -0,0 +1,65 @@
+use crate::utils::test_app::{api_key, spawn_app};
+use exchange::domain::side::Side;
+use exchange::domain::trade::Trade;
+
+mod utils;
+
+#[tokio::test]
+async fn trades_are_paged_by_id() {
+    let app = spawn_app();
+    let client = reqwest::Client::new();
+
+    let response = client
+        .post(format!("{}/orders", &app.address))
+        .bearer_auth(api_key(1))
+        .header("Content-Type", "application/json")
+        .body(
+            r#"[
+                {"price": 100, "quantity": 10, "side": "Sell", "symbol": "ABC"},
+                {"price": 101, "quantity": 10, "side": "Sell", "symbol": "ABC"},
+                {"price": 102, "quantity": 10, "side": "Sell", "symbol": "ABC"},
+                {"price": 102, "quantity": 30, "side": "Buy", "symbol": "ABC"},
+                {"price": 50, "quantity": 1, "side": "Buy", "symbol": "XYZ"},
+                {"price": 50, "quantity": 1, "side": "Sell", "symbol": "XYZ"}
+            ]"#,
+        )
+        .send()
+        .await
+        .expect("Failed to create orders!");
+    assert!(response.status().is_success());
+
+    let query = |query: String| trades(&client, &app.address, query);
+
+    // trades are recorded after they are published
+    let mut latest = query("limit=2".to_string()).await;
+    while latest.len() < 2 || latest[1].symbol.as_str() != "XYZ" {
+        tokio::task::yield_now().await;
+        latest = query("limit=2".to_string()).await;
+    }
+    assert_eq!(latest[0].price.0, 102);
+    assert_eq!(latest[1].aggressor, Side::Sell);
+
+    let first_page = query("symbol=ABC&since_id=0&limit=2".to_string()).await;
+    assert_eq!(
+        first_page.iter().map(|t| t.price.0).collect::<Vec<_>>(),
+        [100, 101]
+    );
+    assert!(first_page.iter().all(|t| t.aggressor == Side::Buy));
+    assert!(first_page[0].exec_time() <= first_page[1].exec_time());
+
+    let since_id = first_page[1].id.0;
+    let second_page = query(format!("symbol=ABC&since_id={since_id}&limit=2")).await;
+    assert_eq!(second_page.len(), 1);
+    assert_eq!(second_page[0].price.0, 102);
+    assert!(second_page[0].id > first_page[1].id);
+}
+
+async fn trades(client: &reqwest::Client, address: &str, query: String) -> Vec<Trade> {
+    let response = client
+        .get(format!("{address}/trades?{query}"))
+        .send()
+        .await
+        .expect("Failed to query trades!");
+    assert!(response.status().is_success());
+    serde_json::from_str(&response.text().await.unwrap()).expect("Invalid response body")
+}
@@ -1,14 +1,33 @@
+use exchange::configuration::ApiKey;
+use exchange::domain::account::AccountId;
 use exchange::matching::engine::matching_engine;
 use exchange::matching::state::AppState;
 use exchange::startup;
 use std::net::TcpListener;
 use tokio::sync::{broadcast, mpsc};
 
+pub const ADMIN_TOKEN: &str = "test-admin-token";
+
+/// Accounts 1 to 5 have API keys, see `api_key`.
+const ACCOUNTS_WITH_KEYS: u64 = 5;
+
+/// API key of a test account, sent as a bearer token.
+pub fn api_key(account: u64) -> String {
+    format!("key-{account}")
+}
+
 pub struct TestApp {
     pub address: String,
 }
 
 pub fn spawn_app() -> TestApp {
+    spawn_app_with(|state| state)
+}
+
+pub fn spawn_app_with<F>(configure: F) -> TestApp
+where
+    F: FnOnce(AppState) -> AppState,
+{
     let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bind to the random port");
     let port = listener.local_addr().unwrap().port();
 
@@ -19,8 +38,17 @@ pub fn spawn_app() -> TestApp {
 
     tokio::spawn(matching_engine(rx, ws_tx.clone()));
 
-    let state = AppState { tx, ws_tx };
-    let server = startup::run(listener, state).expect("Test server was not created successfully");
+    let api_keys = (1..=ACCOUNTS_WITH_KEYS)
+        .map(|account| ApiKey {
+            account: AccountId(account),
+            key: api_key(account),
+        })
+        .collect();
+    let state = AppState::new(tx, ws_tx)
+        .with_admin_token(Some(ADMIN_TOKEN.to_string()))
+        .with_api_keys(api_keys);
+    let server =
+        startup::run(listener, configure(state)).expect("Test server was not created successfully");
 
     tokio::spawn(server);
 
@@ -0,0 +1,377 @@
+use crate::utils::test_app::{api_key, spawn_app};
+use exchange::matching::engine::MarketEvent;
+use futures_util::{SinkExt, StreamExt};
+use serde_json::{Value, json};
+use std::time::Duration;
+use tokio_tungstenite::connect_async;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::handshake::client::Request;
+
+mod utils;
+
+async fn next_response<S>(stream: &mut S) -> Value
+where
+    S: StreamExt<Item = Result<Message, tokio_tungstenite::tungstenite::Error>> + Unpin,
+{
+    loop {
+        let msg = stream
+            .next()
+            .await
+            .expect("Connection closed")
+            .expect("Failed to read frame");
+        if let Message::Text(text) = msg {
+            let value: Value = serde_json::from_str(&text).expect("Invalid frame");
+            // market events are externally tagged, responses carry a type
+            if value.get("type").is_some() {
+                return value;
+            }
+        }
+    }
+}
+
+/// Market events up to and including the first one of type `name`.
+async fn events_until<S>(stream: &mut S, name: &str) -> Vec<Value>
+where
+    S: StreamExt<Item = Result<Message, tokio_tungstenite::tungstenite::Error>> + Unpin,
+{
+    let mut events = Vec::new();
+    loop {
+        let msg = stream
+            .next()
+            .await
+            .expect("Connection closed")
+            .expect("Failed to read frame");
+        if let Message::Text(text) = msg {
+            let value: Value = serde_json::from_str(&text).expect("Invalid frame");
+            if value.get("type").is_some() {
+                continue;
+            }
+            let last = value.get(name).is_some();
+            events.push(value);
+            if last {
+                return events;
+            }
+        }
+    }
+}
+
+async fn next_binary<S>(stream: &mut S) -> Vec<u8>
+where
+    S: StreamExt<Item = Result<Message, tokio_tungstenite::tungstenite::Error>> + Unpin,
+{
+    loop {
+        let msg = stream
+            .next()
+            .await
+            .expect("Connection closed")
+            .expect("Failed to read frame");
+        if let Message::Binary(bytes) = msg {
+            return bytes.to_vec();
+        }
+    }
+}
+
+/// App that knows the API key of account 5.
+fn authenticated(url: &str) -> Request {
+    authenticated_as(url, 5)
+}
+
+fn authenticated_as(url: &str, account: u64) -> Request {
+    let mut request = url.into_client_request().unwrap();
+    let bearer = format!("Bearer {}", api_key(account));
+    request
+        .headers_mut()
+        .insert("Authorization", bearer.parse().unwrap());
+    request
+}
+
+const CREATE: &str =
+    r#"{"request_id": "c1", "type": "Create", "price": 100, "quantity": 10, "side": "Buy"}"#;
+
+#[tokio::test]
+async fn orders_are_acknowledged_with_their_request_id() {
+    let app = spawn_app();
+    let url = format!("{}/ws", app.address.replace("http", "ws"));
+    let (mut ws, _) = connect_async(authenticated(&url))
+        .await
+        .expect("Failed to connect");
+
+    ws.send(Message::text(CREATE)).await.unwrap();
+
+    let ack = next_response(&mut ws).await;
+    assert_eq!(ack["type"], "Ack");
+    assert_eq!(ack["request_id"], "c1");
+    assert_eq!(
+        ack["order"]["account"], 5,
+        "Orders belong to the session account"
+    );
+
+    let cancel = json!({
+        "request_id": "c2",
+        "type": "Cancel",
+        "id": ack["order"]["id"],
+        "revision": 0,
+    });
+    ws.send(Message::text(cancel.to_string())).await.unwrap();
+
+    let ack = next_response(&mut ws).await;
+    assert_eq!(ack["type"], "Ack");
+    assert_eq!(ack["request_id"], "c2");
+
+    ws.send(Message::text(cancel.to_string())).await.unwrap();
+
+    let reject = next_response(&mut ws).await;
+    assert_eq!(reject["type"], "Reject");
+    assert_eq!(reject["request_id"], "c2");
+    assert_eq!(reject["code"], "not_found");
+}
+
+#[tokio::test]
+async fn malformed_requests_are_rejected() {
+    let app = spawn_app();
+    let url = format!("{}/ws", app.address.replace("http", "ws"));
+    let (mut ws, _) = connect_async(url).await.expect("Failed to connect");
+
+    ws.send(Message::text(r#"{"type": "Create"}"#))
+        .await
+        .unwrap();
+
+    let reject = next_response(&mut ws).await;
+    assert_eq!(reject["type"], "Reject");
+    assert_eq!(reject["request_id"], Value::Null);
+    assert_eq!(reject["code"], "invalid_request");
+}
+
+#[tokio::test]
+async fn events_are_sent_as_message_pack_when_asked_by_query() {
+    let app = spawn_app();
+    let url = format!("{}/ws?encoding=msgpack", app.address.replace("http", "ws"));
+    let (mut ws, _) = connect_async(authenticated(&url))
+        .await
+        .expect("Failed to connect");
+
+    ws.send(Message::text(CREATE)).await.unwrap();
+
+    // the acknowledgement may overtake the event
+    let (mut event, mut ack) = (None, None);
+    while event.is_none() || ack.is_none() {
+        let frame = next_binary(&mut ws).await;
+        match rmp_serde::from_slice::<MarketEvent>(&frame) {
+            Ok(decoded) => event = Some(decoded),
+            Err(_) => ack = Some(frame),
+        }
+    }
+
+    assert!(matches!(event, Some(MarketEvent::OrderCreated(_))));
+    let ack_type = rmp_serde::to_vec("Ack").unwrap();
+    assert!(
+        ack.unwrap()
+            .windows(ack_type.len())
+            .any(|window| window == ack_type.as_slice()),
+        "Responses use the session encoding"
+    );
+}
+
+#[tokio::test]
+async fn events_are_sent_in_the_negotiated_subprotocol() {
+    let app = spawn_app();
+    let mut request = authenticated(&format!("{}/ws", app.address.replace("http", "ws")));
+    request
+        .headers_mut()
+        .insert("Sec-WebSocket-Protocol", "v2, binary".parse().unwrap());
+    let (mut ws, response) = connect_async(request).await.expect("Failed to connect");
+    assert_eq!(
+        response.headers()["Sec-WebSocket-Protocol"],
+        "binary",
+        "The chosen subprotocol is confirmed"
+    );
+
+    ws.send(Message::text(CREATE)).await.unwrap();
+
+    // the acknowledgement may overtake the event, it starts with its type
+    let ack_prefix = bincode::serialize("Ack").unwrap();
+    let (mut event, mut ack) = (None, None);
+    while event.is_none() || ack.is_none() {
+        let frame = next_binary(&mut ws).await;
+        if frame.starts_with(&ack_prefix) {
+            ack = Some(frame);
+        } else {
+            event = Some(frame);
+        }
+    }
+
+    let event: MarketEvent = bincode::deserialize(&event.unwrap()).expect("Invalid binary frame");
+    let MarketEvent::OrderCreated(order) = event else {
+        panic!("Unexpected event {event:?}");
+    };
+    assert_eq!(order.quantity.0, 10);
+
+    let request_id = bincode::serialize("c1").unwrap();
+    assert!(
+        ack.unwrap()
+            .windows(request_id.len())
+            .any(|window| window == request_id.as_slice()),
+        "Responses use the session encoding"
+    );
+}
+
+#[tokio::test]
+async fn private_events_are_only_sent_to_the_authenticated_account() {
+    let app = spawn_app();
+    let url = format!("{}/ws", app.address.replace("http", "ws"));
+    let (mut snooper, _) = connect_async(format!("{url}?account=5"))
+        .await
+        .expect("Failed to connect");
+    let (mut owner, _) = connect_async(authenticated(&url))
+        .await
+        .expect("Failed to connect");
+
+    for (request_id, side) in [("buy", "Buy"), ("sell", "Sell")] {
+        let create = json!({
+            "request_id": request_id,
+            "type": "Create",
+            "price": 100,
+            "quantity": 10,
+            "side": side,
+            "account": 5,
+        });
+        owner.send(Message::text(create.to_string())).await.unwrap();
+    }
+    let fill = events_until(&mut owner, "Fill").await.pop().unwrap();
+    assert_eq!(fill["Fill"]["account"], 5);
+
+    // anything published after the fills reaches the snooper after them
+    let marker =
+        r#"{"request_id": "m", "type": "Create", "price": 1, "quantity": 1, "side": "Buy"}"#;
+    owner.send(Message::text(marker)).await.unwrap();
+    let mut seen = Vec::new();
+    while !seen
+        .iter()
+        .any(|event: &Value| event["OrderCreated"]["price"] == 1)
+    {
+        seen.extend(events_until(&mut snooper, "OrderCreated").await);
+    }
+    assert!(
+        seen.iter().all(|event| event.get("Fill").is_none()),
+        "Naming an account does not reveal its fills"
+    );
+    assert!(
+        seen.iter()
+            .filter_map(|event| event.get("OrderCreated"))
+            .all(|order| order.get("account").is_none()),
+        "Accounts stay off the public feed"
+    );
+}
+
+#[tokio::test]
+async fn cancel_on_disconnect_needs_an_api_key() {
+    let app = spawn_app();
+    let url = format!(
+        "{}/ws?cancel_on_disconnect=true",
+        app.address.replace("http", "ws")
+    );
+
+    assert!(connect_async(url.as_str()).await.is_err());
+    assert!(connect_async(authenticated(&url)).await.is_ok());
+}
+
+#[tokio::test]
+async fn orders_are_cancelled_on_disconnect_unless_the_account_reconnects() {
+    let app = spawn_app();
+    let base = format!("{}/ws", app.address.replace("http", "ws"));
+    let url = format!("{base}?cancel_on_disconnect=true&grace_ms=200");
+    let (mut listener, _) = connect_async(authenticated_as(&base, 4))
+        .await
+        .expect("Failed to connect");
+
+    let (mut session, _) = connect_async(authenticated(&url))
+        .await
+        .expect("Failed to connect");
+    session.send(Message::text(CREATE)).await.unwrap();
+    assert_eq!(next_response(&mut session).await["type"], "Ack");
+    session.close(None).await.unwrap();
+
+    let deleted = events_until(&mut listener, "OrderDeleted")
+        .await
+        .pop()
+        .unwrap();
+    assert_eq!(deleted["OrderDeleted"]["reason"], "CancelOnDisconnect");
+
+    let (mut first, _) = connect_async(authenticated(&url))
+        .await
+        .expect("Failed to connect");
+    first.send(Message::text(CREATE)).await.unwrap();
+    assert_eq!(next_response(&mut first).await["type"], "Ack");
+    first.close(None).await.unwrap();
+
+    // a session without the flag counts as a reconnect too
+    let (_second, _) = connect_async(authenticated(&base))
+        .await
+        .expect("Failed to connect");
+    tokio::time::sleep(Duration::from_millis(400)).await;
+
+    let marker =
+        r#"{"request_id": "m", "type": "Create", "price": 1, "quantity": 1, "side": "Sell"}"#;
+    listener.send(Message::text(marker)).await.unwrap();
+    let mut seen = Vec::new();
+    while !seen
+        .iter()
+        .any(|event: &Value| event["OrderCreated"]["price"] == 1)
+    {
+        seen.extend(events_until(&mut listener, "OrderCreated").await);
+    }
+    assert!(
+        seen.iter().all(|event| event.get("OrderDeleted").is_none()),
+        "Reconnecting within the grace period keeps the orders"
+    );
+}
+
+#[tokio::test]
+async fn sessions_cannot_change_orders_of_other_accounts() {
+    let app = spawn_app();
+    let url = format!("{}/ws", app.address.replace("http", "ws"));
+    let (mut owner, _) = connect_async(authenticated(&url))
+        .await
+        .expect("Failed to connect");
+    let (mut other, _) = connect_async(authenticated_as(&url, 4))
+        .await
+        .expect("Failed to connect");
+    let (mut anonymous, _) = connect_async(url.as_str())
+        .await
+        .expect("Failed to connect");
+
+    owner.send(Message::text(CREATE)).await.unwrap();
+    let ack = next_response(&mut owner).await;
+
+    let modify = json!({
+        "request_id": "m1",
+        "type": "Modify",
+        "id": ack["order"]["id"],
+        "revision": 0,
+        "new_quantity": 1,
+    });
+    let cancel = json!({
+        "request_id": "c2",
+        "type": "Cancel",
+        "id": ack["order"]["id"],
+        "revision": 0,
+    });
+    for request in [modify, cancel] {
+        other
+            .send(Message::text(request.to_string()))
+            .await
+            .unwrap();
+        let reject = next_response(&mut other).await;
+        assert_eq!(reject["type"], "Reject");
+        assert_eq!(reject["code"], "not_found");
+
+        anonymous
+            .send(Message::text(request.to_string()))
+            .await
+            .unwrap();
+        let reject = next_response(&mut anonymous).await;
+        assert_eq!(reject["type"], "Reject");
+        assert_eq!(reject["code"], "invalid_request");
+    }
+}